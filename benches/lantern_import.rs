@@ -0,0 +1,89 @@
+//! Times importing `assets/lantern.comp.bin`, with and without fingerprinted bucket search.
+//! The lantern is imported next to a scene of carved spheres, as scans only dominate once buckets fill up.
+//! The fastest of several runs is reported, as the others mostly differ by noise.
+//! Run with `cargo bench --bench lantern_import`.
+use voxel_dags::{
+    basic_dag::BasicDAG,
+    conversion::Converter,
+    editing::{shapes::Sphere, Editor, Operation::Unlink, Shape},
+    hash_table::{config::HashTableConfig, storage::Backing},
+    tracking::dummy::HostOnlyHashDAG,
+    utils::{
+        serialization::{read_exact_slice, read_word},
+        XorShift,
+    },
+};
+use ::{
+    nalgebra::Vector3,
+    std::{fs::File, io::Read, time::Instant},
+};
+
+const RUNS: usize = 10;
+const SPHERES: u32 = 64;
+
+/// Skips the header and the unused fields of the compressed lantern, like the tests do.
+fn lantern() -> BasicDAG {
+    let mut file = File::open("assets/lantern.comp.bin").expect("File missing.");
+    file.read_exact(&mut [0; 8 * 6]).unwrap();
+    let levels = read_word(&mut file).unwrap();
+    let num_nodes = read_word(&mut file).unwrap() as usize;
+    file.read_exact(&mut [0; 4 * 6]).unwrap();
+    let pool = read_exact_slice(&mut file, num_nodes).unwrap();
+    BasicDAG::new(levels, pool)
+}
+
+/// The default geometry, and one with few buckets whose scans dominate the import.
+const CONFIGS: [(&str, HashTableConfig); 2] = [
+    ("default", HashTableConfig::DEFAULT),
+    (
+        "crowded",
+        HashTableConfig {
+            buckets_per_hi_level: 1 << 6,
+            buckets_per_lo_level: 1 << 6,
+            ..HashTableConfig::DEFAULT
+        },
+    ),
+];
+
+/// Carves spheres at pseudo random places, the same ones every time.
+fn scene(config: HashTableConfig, fingerprinted: bool) -> HostOnlyHashDAG<'static> {
+    let capacity = config.total_virt_space().min(16_000_000);
+    let mut dag = HostOnlyHashDAG::with_backing(Backing::Heap, capacity as _, config).unwrap();
+    dag.set_growable(true);
+    dag.hash_dag.set_fingerprinted(fingerprinted).unwrap();
+    let mut vptr = dag.full_node_ptr(0).unwrap();
+    let mut random = XorShift::default();
+    for _ in 0..SPHERES {
+        let center = Vector3::new(random.below(4096), random.below(4096), random.below(4096));
+        let shape = Sphere::new(&center, 16 + random.below(48));
+        vptr = dag.edit(vptr, Unlink, &shape).unwrap();
+    }
+    dag
+}
+
+fn main() {
+    let bd = lantern();
+    for ((name, config), fingerprinted) in CONFIGS
+        .iter()
+        .flat_map(|config| [(config, false), (config, true)])
+    {
+        let start = Instant::now();
+        let scene = scene(*config, fingerprinted);
+        let carving = start.elapsed().as_secs_f64();
+        // Warms up caches and the allocator, so the first timed run is no outlier.
+        scene.clone().import_strict(&bd, None).unwrap();
+        let fastest = (0..RUNS)
+            .map(|_| {
+                let mut dag = scene.clone();
+                let start = Instant::now();
+                dag.import_strict(&bd, None).unwrap();
+                start.elapsed().as_secs_f64()
+            })
+            .fold(f64::INFINITY, f64::min);
+        println!(
+            "{name} buckets, fingerprinted: {fingerprinted}: carving {:.1} ms, import {:.2} ms",
+            1e3 * carving,
+            1e3 * fastest
+        );
+    }
+}
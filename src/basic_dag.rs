@@ -2,20 +2,88 @@ use super::{
     constants::SUPPORTED_LEVELS,
     utils::{
         descend,
-        serialization::{read_boxed_slice, read_word},
+        serialization::{read_boxed_slice, read_word, write_slice, write_word},
     },
-    Result,
+    Error, Result,
 };
 use ::{
     nalgebra::Vector3,
     num_traits::identities::Zero,
     std::{
         fs::File,
-        io::{BufReader, Read},
+        io::{BufReader, BufWriter, Read, Write},
         path::Path,
     },
 };
 
+/// The length of the header preceding every DAG file.
+pub const HEADER_LEN: usize = 8 * 6;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// The metadata preceding the DAG in a file: the bounding box of the model in world space.
+pub struct BasicDAGHeader {
+    pub aabb_min: Vector3<f64>,
+    pub aabb_max: Vector3<f64>,
+}
+
+impl BasicDAGHeader {
+    /// A header whose bounding box coincides with voxel space, i.e. one unit per voxel.
+    #[inline]
+    #[must_use]
+    pub fn voxel_space(levels: u32) -> Self {
+        Self {
+            aabb_min: Vector3::zero(),
+            aabb_max: Vector3::repeat(f64::from(1u32 << levels)),
+        }
+    }
+    #[inline]
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Self {
+        let mut words = bytes
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()));
+        let mut next = || Vector3::from_fn(|_, _| words.next().unwrap());
+        let aabb_min = next();
+        let aabb_max = next();
+        Self { aabb_min, aabb_max }
+    }
+    #[inline]
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        let words = self.aabb_min.iter().chain(self.aabb_max.iter());
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+    /// The extent of a single voxel in world space, given the amount of levels of the DAG.
+    #[inline]
+    #[must_use]
+    pub fn voxel_size(&self, levels: u32) -> Vector3<f64> {
+        (self.aabb_max - self.aabb_min) / f64::from(1u32 << levels)
+    }
+    /// The world space position of the minimal corner of a voxel.
+    #[inline]
+    #[must_use]
+    pub fn to_world(&self, levels: u32, voxel: &Vector3<u32>) -> Vector3<f64> {
+        let voxel: Vector3<_> = voxel.map(f64::from);
+        self.aabb_min + voxel.component_mul(&self.voxel_size(levels))
+    }
+    /// The voxel containing a world space position, if it lies within the bounding box.
+    #[inline]
+    #[must_use]
+    pub fn to_voxel(&self, levels: u32, position: &Vector3<f64>) -> Option<Vector3<u32>> {
+        let voxel = (position - self.aabb_min).component_div(&self.voxel_size(levels));
+        let bound = f64::from(1u32 << levels);
+        if voxel.iter().all(|&v| (0.0..bound).contains(&v)) {
+            Some(voxel.map(|v| v as u32))
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug, PartialOrd, Eq, PartialEq)]
 /// An octree-aligned voxel.
 pub struct OctVox {
@@ -52,6 +120,7 @@ impl OctVox {
 
 #[derive(Debug)]
 pub struct BasicDAG {
+    pub header: BasicDAGHeader,
     pub pool: Box<[u32]>,
     pub levels: u32,
     pub root_idx: usize,
@@ -62,21 +131,50 @@ impl BasicDAG {
     #[must_use]
     pub fn new(levels: u32, pool: Box<[u32]>) -> Self {
         Self {
+            header: BasicDAGHeader::voxel_space(levels),
             pool,
             levels,
             root_idx: 0,
         }
     }
     #[inline]
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Option<Self> {
-        let mut file = BufReader::new(File::open(path).ok()?);
-        file.read_exact(&mut [0; 8 * 6]).ok()?;
-        Some(Self {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)?;
+        Ok(Self {
+            header: BasicDAGHeader::from_bytes(&header),
             levels: read_word(&mut file)?,
             pool: read_boxed_slice(&mut file)?,
             root_idx: 0,
         })
     }
+    /// Writes the DAG in the format read by `from_file`.
+    #[inline]
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.to_writer(&mut file)?;
+        Ok(file.flush()?)
+    }
+    /// Writes the DAG in the format read by `from_file`.
+    /// The root has to be the first node of the pool, otherwise its index is invalid.
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let level = SUPPORTED_LEVELS.checked_sub(self.levels).ok_or_else(|| {
+            Error::Format(format!(
+                "A DAG of {} levels exceeds the {SUPPORTED_LEVELS} supported ones.",
+                self.levels
+            ))
+        })?;
+        if self.root_idx != 0 {
+            return Err(Error::InvalidIndex {
+                level,
+                idx: self.root_idx,
+            });
+        }
+        writer.write_all(&self.header.to_bytes())?;
+        write_word(writer, self.levels)?;
+        Ok(write_slice(writer, &self.pool)?)
+    }
     #[inline]
     #[must_use]
     pub fn find_node(&self, target: &OctVox) -> Option<usize> {
@@ -88,10 +186,10 @@ impl BasicDAG {
         }
     }
     fn seek(&self, target: &OctVox, (state, idx): (OctVox, usize)) -> Result<Option<usize>> {
-        let &mask = self.pool.get(idx).ok_or(format!(
-            "Node index out of bounds! Depth: {}, Index: {}",
-            state.depth, idx
-        ))?;
+        let &mask = self.pool.get(idx).ok_or(Error::InvalidIndex {
+            level: SUPPORTED_LEVELS - state.depth,
+            idx,
+        })?;
         let child_mask = mask as u8;
         let mut offset = 1;
         for child in 0..8 {
@@ -0,0 +1,173 @@
+use super::{
+    hash_table::{basic::HashTable, config::HashTableConfig},
+    shared_hash_dag::SharedHashDAG,
+    tracking::Tracker,
+    validation::Node,
+    Error, HashDAG, HashDAGSync, Result,
+};
+use ::std::{
+    ops::Deref,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Buckets are guarded by a fixed set of locks. Buckets sharing a lock cannot be written to at the same time.
+pub const BUCKET_LOCKS: usize = 1 << 12;
+
+/// A `SharedHashDAG` which can be shared between threads, for inserting nodes concurrently.
+///
+/// Every bucket is only read and written while holding its lock, pages are allocated atomically on `hi`.
+/// A page belongs to a single bucket, so its entry in the page table is guarded by the same lock.
+/// Added nodes are not fingerprinted, they are scanned until a node is added to their bucket through `SharedHashDAG`.
+pub struct ConcurrentHashDAG<'shmem, T: Tracker> {
+    hash_dag: HashTable<'shmem>,
+    tracker: Mutex<T>,
+    locks: Box<[Mutex<()>]>,
+}
+
+impl<'shmem, T: Tracker> From<SharedHashDAG<HashTable<'shmem>, T>>
+    for ConcurrentHashDAG<'shmem, T>
+{
+    #[inline]
+    fn from(
+        SharedHashDAG {
+            hash_dag, tracker, ..
+        }: SharedHashDAG<HashTable<'shmem>, T>,
+    ) -> Self {
+        Self {
+            hash_dag,
+            tracker: Mutex::new(tracker),
+            locks: (0..BUCKET_LOCKS).map(|_| Mutex::new(())).collect(),
+        }
+    }
+}
+
+impl<'shmem, T: Tracker> ConcurrentHashDAG<'shmem, T> {
+    /// Returns to single-threaded use, e.g. for editing or garbage collection.
+    #[inline]
+    pub fn into_inner(self) -> SharedHashDAG<HashTable<'shmem>, T> {
+        SharedHashDAG {
+            hash_dag: self.hash_dag,
+            tracker: self
+                .tracker
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner),
+            journal: None,
+        }
+    }
+}
+
+impl<'shmem, T: Tracker> Deref for ConcurrentHashDAG<'shmem, T> {
+    type Target = HashTable<'shmem>;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.hash_dag
+    }
+}
+
+impl<T: Tracker> HashDAG for ConcurrentHashDAG<'_, T> {
+    #[inline]
+    fn get(&self, vptr: u32) -> Result<u32> {
+        self.hash_dag.get(vptr)
+    }
+    #[inline]
+    fn leaf(&self, vptr: u32) -> Result<&[u32]> {
+        self.hash_dag.leaf(vptr)
+    }
+    #[inline]
+    fn interior(&self, vptr: u32) -> Result<&[u32]> {
+        self.hash_dag.interior(vptr)
+    }
+    #[inline]
+    fn dump(&self) -> (&[u32], &[u32]) {
+        self.hash_dag.dump()
+    }
+    #[inline]
+    fn config(&self) -> &HashTableConfig {
+        &self.hash_dag.config
+    }
+    #[inline]
+    fn full_node_pointers(&self) -> &[u32] {
+        &self.hash_dag.full_node_pointers
+    }
+}
+
+impl<T: Tracker + Send> HashDAGSync for ConcurrentHashDAG<'_, T> {
+    fn find_or_add_leaf(&self, node: Node) -> Result<u32> {
+        let leaf_level = self.hash_dag.config.leaf_level();
+        let node = node.validated_as_leaf()?;
+        let bucket = self.hash_dag.config.leaf_bucket(*node);
+        let full_node_ptr = self.full_node_ptr(leaf_level)?;
+        if *node == self.leaf(full_node_ptr)? {
+            return Ok(full_node_ptr);
+        }
+        self.find_or_add(leaf_level, bucket, *node, |bucket, bucket_len| {
+            self.find_leaf(bucket, bucket_len, *node)
+        })
+    }
+    fn find_or_add_interior(&self, level: u32, node: Node) -> Result<u32> {
+        let config = &self.hash_dag.config;
+        let node = node.validated_as_interior(&self.hash_dag, config.level_info(level))?;
+        let bucket = config.interior_bucket(level, *node);
+        let full_node_ptr = self.full_node_ptr(level)?;
+        if *node == self.interior(full_node_ptr)? {
+            return Ok(full_node_ptr);
+        }
+        self.find_or_add(level, bucket, *node, |bucket, bucket_len| {
+            self.find_interior(level, bucket, bucket_len, *node)
+        })
+    }
+}
+
+impl<T: Tracker> ConcurrentHashDAG<'_, T> {
+    #[inline]
+    fn lock(&self, level: u32, bucket: u32) -> MutexGuard<'_, ()> {
+        self.locks[self.hash_dag.config.new_bucket_len_idx(level, bucket) % BUCKET_LOCKS]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Probes like `HashTable::probe_leaf`, holding one bucket's lock at a time.
+    /// A bucket without room for the node never gains room, so the node cannot be added to it meanwhile.
+    fn find_or_add<F>(&self, level: u32, bucket: u32, node: &[u32], find: F) -> Result<u32>
+    where
+        F: Fn(u32, u32) -> Result<Option<u32>>,
+    {
+        for probed in self.probe(level, bucket) {
+            let _bucket = self.lock(level, probed);
+            let bucket_len = self.bucket_len(level, probed);
+            if bucket_len != 0 {
+                if let Some(vptr) = find(probed, bucket_len)? {
+                    return Ok(vptr);
+                }
+            }
+            if self.has_room(level, probed, node.len() as _) {
+                return self.add(level, probed, node);
+            }
+        }
+        Err(Error::BucketOverflow { level, bucket })
+    }
+    /// The caller must hold the lock of the bucket.
+    fn add(&self, level: u32, bucket: u32, node: &[u32]) -> Result<u32> {
+        let config = &self.hash_dag.config;
+        let (vptr, bucket_len, allocate) = self.placement(level, bucket, node.len() as _)?;
+        self.check_pool(vptr)?;
+        if allocate {
+            let page = (vptr / config.page_len) as usize;
+            // SAFETY: the page belongs to the locked bucket.
+            unsafe { self.lut.try_allocate_shared(page, self.pool.len()) }
+                .ok_or(Error::PoolExhausted)?;
+        }
+        let pool_idx = self.pool_idx(vptr)?;
+        let range = pool_idx..pool_idx + node.len();
+        // SAFETY: the range lies beyond the length of the locked bucket, so nobody else reads it yet.
+        unsafe {
+            self.pool.copy_from_shared(range.start, node);
+            self.bucket_len
+                .copy_from_shared(config.new_bucket_len_idx(level, bucket), &[bucket_len]);
+        }
+        self.tracker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .register(vptr, range)?;
+        Ok(vptr)
+    }
+}
@@ -1,13 +1,13 @@
 use super::{
-    basic_dag::BasicDAG,
-    constants::{COLOR_TREE_LEVELS, LEAF_LEVEL, SUPPORTED_LEVELS},
+    basic_dag::{BasicDAG, BasicDAGHeader},
     hash_table::basic::HashTable,
     shared_hash_dag::SharedHashDAG,
     tracking::Tracker,
     utils::count_leaves,
     validation::Node::{self, Pass, Strict},
-    HashDAG, HashDAGMut, Result,
+    Error, HashDAG, HashDAGMut, Result,
 };
+use ::std::collections::HashMap;
 
 // TODO [1] it turns out that the stop is not optimized away when none. Execution time has increased by 13%. Optimize this?
 pub trait Converter {
@@ -19,7 +19,13 @@ pub trait Converter {
     /// During this process a lot of cache is allocated, if this proves to be problematic you may want to consider importing in batches.
     /// Arguments: stop: at which level to stop importing (relative to the root). Anything >= `LEAF_LEVEL` will error.
     fn import(&mut self, dag: &BasicDAG, stop: Option<u32>) -> Result<u32>;
-    // TODO export(vptr) -> dyn (dag: &BasicDAG)
+    /// Exports the DAG spanned by `vptr` into a compact pool with direct child indices, the root being at index 0.
+    /// Shared nodes are written only once. Voxel counts are not part of the exported format.
+    /// The header spans voxel space, see `export_with_header` to keep the placement of an imported model.
+    fn export(&self, vptr: u32) -> Result<BasicDAG>;
+    /// Like `export`, writing `header` instead, e.g. the one of the imported `BasicDAG` so that its bounding box survives.
+    /// `vptr` has to be of the level the model was imported at, or the header does not span its voxels.
+    fn export_with_header(&self, vptr: u32, header: BasicDAGHeader) -> Result<BasicDAG>;
     // TODO export_serialized(vptr) -> "dyn (dag: &BasicDAG)::serialized()"
 }
 
@@ -32,15 +38,47 @@ impl<T: Tracker> Converter for SharedHashDAG<HashTable<'_>, T> {
     fn import(&mut self, dag: &BasicDAG, stop: Option<u32>) -> Result<u32> {
         self.import(Pass(&[]), dag, stop)
     }
+    #[inline]
+    fn export(&self, vptr: u32) -> Result<BasicDAG> {
+        export(&self.hash_dag, vptr, None)
+    }
+    #[inline]
+    fn export_with_header(&self, vptr: u32, header: BasicDAGHeader) -> Result<BasicDAG> {
+        export(&self.hash_dag, vptr, Some(header))
+    }
+}
+
+/// See `Converter::export_with_header`, this works on any `HashDAG`. Without a header, it spans voxel space.
+pub(crate) fn export<DAG: HashDAG>(
+    dag: &DAG,
+    vptr: u32,
+    header: Option<BasicDAGHeader>,
+) -> Result<BasicDAG> {
+    let config = dag.config();
+    let level = config.vptr_to_lvl(vptr);
+    if config.leaf_level() <= level {
+        return Err(Error::InvalidLevel(level));
+    }
+    let mut pool = Vec::new();
+    emit(dag, (&mut pool, &mut HashMap::new()), (level, vptr))?;
+    let mut bd = BasicDAG::new(config.supported_levels - level, pool.into_boxed_slice());
+    if let Some(header) = header {
+        bd.header = header;
+    }
+    Ok(bd)
 }
 
 impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
     fn import(&mut self, how: Node, bd: &BasicDAG, mut stop: Option<u32>) -> Result<u32> {
-        let root_level = SUPPORTED_LEVELS - bd.levels;
+        let root_level = self
+            .config
+            .supported_levels
+            .checked_sub(bd.levels)
+            .ok_or(Error::InvalidLevel(bd.levels))?;
         if let Some(mut level) = stop {
             level += root_level;
-            if LEAF_LEVEL <= level {
-                return Err("The import stop is greater or equal to the leaf level.".into());
+            if self.config.leaf_level() <= level {
+                return Err(Error::InvalidLevel(level));
             } else {
                 stop = Some(level);
             }
@@ -60,28 +98,28 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
         (pool, visits, stop): (Node, &mut [u32], Option<u32>),
         (level, idx): (u32, usize),
     ) -> Result<(u32, Option<u32>)> {
-        let is_visited = *visits.get(idx).ok_or(format!(
-            "Node index out of bounds! Level: {level}, Index: {idx}"
-        ))? != !0;
+        let is_visited = *visits.get(idx).ok_or(Error::InvalidIndex { level, idx })? != !0;
         // TODO [1] could move this out and have a function that invokes this one so there exists both one with and without early stopping
+        let (leaf_level, color_tree_levels) =
+            (self.config.leaf_level(), self.config.color_tree_levels());
         if Some(level) == stop {
-            let voxel_count = if COLOR_TREE_LEVELS <= level {
-                Some(1 << (3 * (SUPPORTED_LEVELS - level)))
+            let voxel_count = if color_tree_levels <= level {
+                Some(self.config.voxels_per_node(level))
             } else {
                 None
             };
             Ok((self.full_node_ptr(level)?, voxel_count))
         } else if is_visited {
-            if level == LEAF_LEVEL {
+            if level == leaf_level {
                 Ok((visits[idx], Some(count_leaves(self.leaf(visits[idx])?))))
             } else {
                 Ok((visits[idx], Some(self.get(visits[idx])? >> 8)))
             }
-        } else if level == LEAF_LEVEL {
-            let &right = pool.get(idx + 1).ok_or(format!(
-                "Least significant leaf mask index out of bounds! Leaf level, Pointer: {}",
-                idx + 1
-            ))?;
+        } else if level == leaf_level {
+            let &right = pool.get(idx + 1).ok_or(Error::InvalidIndex {
+                level,
+                idx: idx + 1,
+            })?;
             let leaf = &[pool[idx], right];
             visits[idx] = self.find_or_add_leaf(match pool {
                 Strict(_) => Strict(leaf),
@@ -93,7 +131,7 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
             let children = child_mask.count_ones() as usize;
             let mut interior = Vec::with_capacity(children + 1);
             interior.push(child_mask);
-            let voxel_count = if COLOR_TREE_LEVELS <= level {
+            let voxel_count = if color_tree_levels <= level {
                 let mut voxel_count = 0;
                 for &idx in pool.iter().skip(idx + 1).take(children) {
                     // TODO [1] could make this generic so both a function that checks a stop and one who doesn't can be used
@@ -121,3 +159,28 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
         }
     }
 }
+
+/// Appends the node (and recursively its children) to the pool unless it has been emitted before.
+/// Returns the index of the node in the pool.
+fn emit<DAG: HashDAG>(
+    dag: &DAG,
+    (pool, visits): (&mut Vec<u32>, &mut HashMap<u32, u32>),
+    (level, vptr): (u32, u32),
+) -> Result<u32> {
+    if let Some(&idx) = visits.get(&vptr) {
+        return Ok(idx);
+    }
+    let idx = pool.len();
+    visits.insert(vptr, idx as _);
+    if level == dag.config().leaf_level() {
+        pool.extend_from_slice(dag.leaf(vptr)?);
+    } else {
+        let interior = dag.interior(vptr)?;
+        pool.push(interior[0] & 0xff);
+        pool.resize(idx + interior.len(), 0);
+        for (offset, &child) in interior.iter().enumerate().skip(1) {
+            pool[idx + offset] = emit(dag, (pool, visits), (level + 1, child))?;
+        }
+    }
+    Ok(idx as _)
+}
@@ -0,0 +1,175 @@
+use super::{
+    super::{
+        hash_table::basic::HashTable, shared_hash_dag::SharedHashDAG, tracking::Tracker,
+        utils::count_leaves, validation::Node::Pass, Error, HashDAG, HashDAGMut, Result,
+    },
+    inner::interior_from,
+};
+use ::std::collections::HashMap;
+
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Clone)]
+pub enum BooleanOperation {
+    /// Voxels in either DAG.
+    Union,
+    /// Voxels in both DAGs.
+    Intersection,
+    /// Voxels in the first DAG but not in the second.
+    Difference,
+    /// Voxels in exactly one of the DAGs.
+    Xor,
+}
+
+impl BooleanOperation {
+    #[inline]
+    #[must_use]
+    pub const fn apply(self, a: u32, b: u32) -> u32 {
+        match self {
+            Self::Union => a | b,
+            Self::Intersection => a & b,
+            Self::Difference => a & !b,
+            Self::Xor => a ^ b,
+        }
+    }
+}
+
+/// Combines two DAGs of the same table voxel by voxel, e.g. to composite models.
+pub trait Combiner {
+    /// Combines the DAGs spanned by `a` and `b`, which have to be of the same level, into a new root.
+    /// Fails with `EmptyDAG` if no voxel remains, in which case nothing has been added.
+    fn combine(&mut self, operation: BooleanOperation, a: u32, b: u32) -> Result<u32>;
+    #[inline]
+    fn union(&mut self, a: u32, b: u32) -> Result<u32> {
+        self.combine(BooleanOperation::Union, a, b)
+    }
+    #[inline]
+    fn intersection(&mut self, a: u32, b: u32) -> Result<u32> {
+        self.combine(BooleanOperation::Intersection, a, b)
+    }
+    #[inline]
+    fn difference(&mut self, a: u32, b: u32) -> Result<u32> {
+        self.combine(BooleanOperation::Difference, a, b)
+    }
+    #[inline]
+    fn xor(&mut self, a: u32, b: u32) -> Result<u32> {
+        self.combine(BooleanOperation::Xor, a, b)
+    }
+}
+
+/// Nodes are `None` where there are no voxels, as children are then left out of their parent.
+type Pair = (Option<u32>, Option<u32>);
+
+impl<T: Tracker> Combiner for SharedHashDAG<HashTable<'_>, T> {
+    fn combine(&mut self, operation: BooleanOperation, a: u32, b: u32) -> Result<u32> {
+        let (level, level_b) = (self.config.vptr_to_lvl(a), self.config.vptr_to_lvl(b));
+        if level != level_b || self.config.leaf_level() <= level {
+            return Err(Error::InvalidLevel(level_b));
+        }
+        self.transaction(|dag| {
+            let mut memo = HashMap::new();
+            dag.combine_nodes(operation, &mut memo, level, (Some(a), Some(b)))?
+                .0
+                .ok_or(Error::EmptyDAG)
+        })
+    }
+}
+
+impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
+    /// Combines two nodes of `level`, returning the resulting node and its voxel count.
+    fn combine_nodes(
+        &mut self,
+        operation: BooleanOperation,
+        memo: &mut HashMap<Pair, (Option<u32>, u32)>,
+        level: u32,
+        (a, b): Pair,
+    ) -> Result<(Option<u32>, u32)> {
+        if let Some(shortcut) = self.shortcut(operation, level, (a, b))? {
+            return Ok((shortcut, self.voxel_count(level, shortcut)?));
+        } else if let Some(&combined) = memo.get(&(a, b)) {
+            return Ok(combined);
+        }
+        let combined = if level == self.config.leaf_level() {
+            let [a, b] = [a, b].map(|vptr| {
+                vptr.map_or(Ok([0; 2]), |vptr| {
+                    self.leaf(vptr).map(|leaf| [leaf[0], leaf[1]])
+                })
+            });
+            let (a, b) = (a?, b?);
+            let leaf = [operation.apply(a[0], b[0]), operation.apply(a[1], b[1])];
+            if leaf == [0; 2] {
+                (None, 0)
+            } else {
+                (
+                    Some(self.find_or_add_leaf(Pass(&leaf))?),
+                    count_leaves(&leaf),
+                )
+            }
+        } else {
+            let [a_children, b_children] = [a, b].map(|vptr| self.children(vptr));
+            let (a_children, b_children) = (a_children?, b_children?);
+            let (mut children, mut voxel_count) = ([None; 8], 0);
+            for child in 0..8 {
+                let pair = (a_children[child], b_children[child]);
+                let (vptr, count) = self.combine_nodes(operation, memo, level + 1, pair)?;
+                children[child] = vptr;
+                voxel_count += count;
+            }
+            if !self.config.level_info(level).is_color_tree_level {
+                voxel_count = 0;
+            }
+            match interior_from(children, voxel_count) {
+                Some(interior) => (
+                    Some(self.find_or_add_interior(level, Pass(&interior))?),
+                    voxel_count,
+                ),
+                None => (None, 0),
+            }
+        };
+        memo.insert((a, b), combined);
+        Ok(combined)
+    }
+    /// The result of combining `a` and `b` if it is known without descending, e.g. as one of them is full or empty.
+    fn shortcut(
+        &self,
+        operation: BooleanOperation,
+        level: u32,
+        (a, b): Pair,
+    ) -> Result<Option<Option<u32>>> {
+        use BooleanOperation::{Difference, Intersection, Union, Xor};
+        let full = Some(self.full_node_ptr(level)?);
+        Ok(match operation {
+            Union | Xor if a.is_none() => Some(b),
+            Union | Xor | Difference if b.is_none() => Some(a),
+            Intersection | Difference if a.is_none() => Some(None),
+            Intersection if b.is_none() => Some(None),
+            Union if a == full || b == full => Some(full),
+            Intersection if a == full => Some(b),
+            Intersection if b == full => Some(a),
+            Difference if b == full => Some(None),
+            Union | Intersection if a == b => Some(a),
+            Difference | Xor if a == b => Some(None),
+            _ => None,
+        })
+    }
+    /// The children of an interior node by octant, none for an empty node.
+    fn children(&self, vptr: Option<u32>) -> Result<[Option<u32>; 8]> {
+        let mut children = [None; 8];
+        if let Some(vptr) = vptr {
+            let interior = self.interior(vptr)?;
+            let mut pointers = interior[1..].iter();
+            for (child, slot) in children.iter_mut().enumerate() {
+                if interior[0] & (1 << child) != 0 {
+                    *slot = pointers.next().copied();
+                }
+            }
+        }
+        Ok(children)
+    }
+    /// The voxels below a node, as stored in its mask. Always 0 above the color tree levels.
+    fn voxel_count(&self, level: u32, vptr: Option<u32>) -> Result<u32> {
+        match vptr {
+            None => Ok(0),
+            Some(vptr) if level == self.config.leaf_level() => Ok(count_leaves(self.leaf(vptr)?)),
+            Some(vptr) => Ok(self.get(vptr)? >> 8),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use super::{
+    super::{garbage_collection::Collector, Error, Result},
+    Editor, OctVox, Operation, Shape,
+};
+
+/// The roots a DAG went through while being edited, each with a label, e.g. to undo edits.
+///
+/// Edits never change existing nodes, so old roots stay valid as long as they are not garbage collected.
+/// Use `History::collect` instead of `Collector::collect` to keep them alive and up to date.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History {
+    entries: Vec<(String, u32)>,
+    /// The index of the checked out entry. Entries after it can be redone until something new is recorded.
+    current: usize,
+}
+
+impl History {
+    /// Starts with a single entry for `root`.
+    #[inline]
+    #[must_use]
+    pub fn new(label: impl Into<String>, root: u32) -> Self {
+        Self {
+            entries: vec![(label.into(), root)],
+            current: 0,
+        }
+    }
+    /// The checked out root.
+    #[inline]
+    #[must_use]
+    pub fn root(&self) -> u32 {
+        self.entries[self.current].1
+    }
+    /// The index of the checked out entry.
+    #[inline]
+    #[must_use]
+    pub const fn current(&self) -> usize {
+        self.current
+    }
+    /// Every entry as its label and root, oldest first.
+    #[inline]
+    #[must_use]
+    pub fn entries(&self) -> &[(String, u32)] {
+        &self.entries
+    }
+    /// Records `root` after the checked out entry and checks it out. Entries which could have been redone are dropped.
+    pub fn record(&mut self, label: impl Into<String>, root: u32) {
+        self.entries.truncate(self.current + 1);
+        self.entries.push((label.into(), root));
+        self.current += 1;
+    }
+    /// Edits the checked out root and records the result.
+    pub fn edit<E, S>(
+        &mut self,
+        editor: &mut E,
+        label: impl Into<String>,
+        operation: Operation,
+        shape: &S,
+    ) -> Result<u32>
+    where
+        E: Editor,
+        S: Shape,
+        S::Edit: From<OctVox>,
+    {
+        let root = editor.edit(self.root(), operation, shape)?;
+        self.record(label, root);
+        Ok(root)
+    }
+    /// Checks out the previous entry, returning its root. Does nothing at the first entry.
+    pub fn undo(&mut self) -> Option<u32> {
+        self.current = self.current.checked_sub(1)?;
+        Some(self.root())
+    }
+    /// Checks out the next entry, returning its root. Does nothing at the last entry.
+    pub fn redo(&mut self) -> Option<u32> {
+        if self.current + 1 < self.entries.len() {
+            self.current += 1;
+            Some(self.root())
+        } else {
+            None
+        }
+    }
+    /// Checks out the entry at `idx`, keeping every other entry.
+    pub fn checkout(&mut self, idx: usize) -> Result<u32> {
+        if idx < self.entries.len() {
+            self.current = idx;
+            Ok(self.root())
+        } else {
+            Err(Error::InvalidHistoryEntry(idx))
+        }
+    }
+    /// Drops all but the `len` most recent entries, so that their nodes can be collected.
+    /// The checked out entry is always kept.
+    pub fn retain_last(&mut self, len: usize) {
+        let drop = (self.entries.len().saturating_sub(len)).min(self.current);
+        self.entries.drain(..drop);
+        self.current -= drop;
+    }
+    /// Garbage collects everything which is neither reachable from an entry nor from `roots`, see `Collector::collect`.
+    /// Entries are updated to their new virtual pointers, those of `roots` are returned in the same order.
+    pub fn collect<C: Collector>(&mut self, collector: &mut C, roots: &[u32]) -> Result<Vec<u32>> {
+        let all: Vec<_> = self
+            .entries
+            .iter()
+            .map(|&(_, root)| root)
+            .chain(roots.iter().copied())
+            .collect();
+        let mut all = collector.collect(&all)?;
+        let roots = all.split_off(self.entries.len());
+        for ((_, root), new_root) in self.entries.iter_mut().zip(all) {
+            *root = new_root;
+        }
+        Ok(roots)
+    }
+}
@@ -1,4 +1,7 @@
-use super::{super::basic_dag::OctVox, Shape};
+use super::{
+    super::{basic_dag::OctVox, hash_table::config::HashTableConfig},
+    Shape,
+};
 use ::{nalgebra::Vector3, std::ops::Deref};
 
 #[must_use]
@@ -27,12 +30,15 @@ pub struct NodeState {
 impl NodeState {
     #[inline]
     #[must_use]
-    pub fn edit_shape<S>(&self) -> S::Edit
+    pub fn edit_shape<S>(&self, config: &HashTableConfig) -> S::Edit
     where
         S: Shape,
         S::Edit: From<OctVox>,
     {
-        S::Edit::from(OctVox::new(self.level, &self.path))
+        S::Edit::from(OctVox {
+            depth: config.supported_levels - self.level,
+            path: self.path,
+        })
     }
 }
 
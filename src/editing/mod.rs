@@ -1,16 +1,17 @@
 use self::inner::{interior_from, NodeState};
 use super::{
     basic_dag::OctVox,
-    constants::{COLOR_TREE_LEVELS, LEAF_LEVEL, SUPPORTED_LEVELS},
     hash_table::basic::HashTable,
     shared_hash_dag::SharedHashDAG,
     tracking::Tracker,
-    utils::{count_leaves, descend, vptr_to_lvl},
+    utils::{count_leaves, descend},
     validation::Node::Pass,
-    HashDAG, HashDAGMut, Result,
+    Error, HashDAG, HashDAGMut, Result,
 };
 use ::{nalgebra::Vector3, num_traits::identities::Zero};
 
+pub mod boolean;
+pub mod history;
 pub mod inner;
 pub mod shapes;
 
@@ -44,16 +45,19 @@ impl<T: Tracker> Editor for SharedHashDAG<HashTable<'_>, T> {
         S::Edit: From<OctVox>,
     {
         let root_node = NodeState {
-            level: vptr_to_lvl(vptr),
+            level: self.config.vptr_to_lvl(vptr),
             vptr: Some(vptr),
             path: Vector3::zero(),
         };
-        let vptr = if COLOR_TREE_LEVELS <= root_node.level {
-            self.edit_deep((operation, shape), root_node)?.0
-        } else {
-            self.edit((operation, shape), root_node)?
-        };
-        Ok(vptr.ok_or("An empty DAG is invalid state.")?)
+        // A failing edit may have added nodes already, those are rolled back.
+        self.transaction(|dag| {
+            let vptr = if dag.config.color_tree_levels() <= root_node.level {
+                dag.edit_deep((operation, shape), root_node)?.0
+            } else {
+                dag.edit((operation, shape), root_node)?
+            };
+            vptr.ok_or(Error::EmptyDAG)
+        })
     }
 }
 
@@ -69,9 +73,9 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
     {
         let result = self.edit_interior(node, move |dag, node| {
             let vptr = *node;
-            let edit_ptr = if !shape.collides(&node.edit_shape::<S>()) {
+            let edit_ptr = if !shape.collides(&node.edit_shape::<S>(&dag.config)) {
                 *node
-            } else if node.level == COLOR_TREE_LEVELS {
+            } else if node.level == dag.config.color_tree_levels() {
                 dag.edit_deep((operation, shape), node)?.0
             } else {
                 dag.edit((operation, shape), node)?
@@ -90,11 +94,12 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
         S: Shape,
         S::Edit: From<OctVox>,
     {
-        let edit = node.edit_shape::<S>();
+        let edit = node.edit_shape::<S>(&self.config);
+        let leaf_level = self.config.leaf_level();
         if !shape.collides(&edit) {
             match *node {
                 Some(vptr) => {
-                    let voxel_count = if node.level == LEAF_LEVEL {
+                    let voxel_count = if node.level == leaf_level {
                         count_leaves(self.leaf(vptr)?)
                     } else {
                         self.get(vptr)? >> 8
@@ -108,9 +113,9 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
         } else if shape.will_be_full(operation, &edit) {
             Ok((
                 Some(self.full_node_ptr(node.level)?),
-                1 << (3 * (SUPPORTED_LEVELS - node.level)),
+                self.config.voxels_per_node(node.level),
             ))
-        } else if node.level == LEAF_LEVEL {
+        } else if node.level == leaf_level {
             self.edit_leaf((operation, shape), *node, &node.path)
         } else {
             self.edit_interior(node, move |dag, node| {
@@ -188,7 +193,7 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
             for bottom_idx in 0..8 {
                 let path: Vector3<_> = descend(&path, bottom_idx);
                 let child_bit = 1 << (base_idx + bottom_idx);
-                if shape.collides(&S::Edit::from(OctVox::new(SUPPORTED_LEVELS, &path))) {
+                if shape.collides(&S::Edit::from(OctVox { depth: 0, path })) {
                     match operation {
                         Operation::Link => leaf[leaf_idx as usize] |= child_bit,
                         _ => leaf[leaf_idx as usize] &= !child_bit,
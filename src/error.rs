@@ -0,0 +1,200 @@
+use ::{
+    shared_memory::ShmemError,
+    std::{fmt, io, ops::Range},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A bucket has no space left for the node being added.
+    BucketOverflow { level: u32, bucket: u32 },
+    /// A bucket is searched with a length exceeding its capacity.
+    BucketLenOutOfBounds { level: u32, bucket_len: u32 },
+    /// Every page of the pool has been allocated.
+    PoolExhausted,
+    /// A pool of this many words cannot be created.
+    InvalidCapacity(usize),
+    /// No virtual pointer exists for this level, bucket and bucket offset.
+    InvalidVptrParts {
+        level: u32,
+        bucket: u32,
+        offset: u32,
+    },
+    /// The virtual pointer points to a page which has not been allocated.
+    UnallocatedVptr(u32),
+    /// The virtual pointer points beyond the allocated part of the pool.
+    OutOfBoundsVptr(u32),
+    /// The mapped pool is stale, as it has been grown elsewhere.
+    StalePool(u32),
+    /// The page does not exist in the page table.
+    InvalidPage(usize),
+    /// The level does not exist or is not supported by the operation.
+    InvalidLevel(u32),
+    /// The node did not pass validation.
+    InvalidNode(String),
+    /// A node index of a `BasicDAG` is out of bounds.
+    InvalidIndex { level: u32, idx: usize },
+    /// The range spans beyond a single page.
+    InvalidRange(Range<usize>),
+    /// The operation would leave a DAG without any voxel.
+    EmptyDAG,
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// The data does not have the expected format.
+    Format(String),
+    /// Creating or opening shared memory failed.
+    Shmem(ShmemError),
+    /// The `HashTableConfig` breaks one of its rules.
+    InvalidConfig(String),
+    /// Shared memory opened from elsewhere cannot be replaced.
+    NotOwner,
+    /// The `History` has no entry at this index.
+    InvalidHistoryEntry(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BucketOverflow { level, bucket } => {
+                write!(f, "Overflowing bucket {bucket} on level {level}!")
+            }
+            Self::BucketLenOutOfBounds { level, bucket_len } => write!(
+                f,
+                "Trying to search a bucket of size {bucket_len} on level {level}, which exceeds its capacity."
+            ),
+            Self::PoolExhausted => {
+                write!(f, "No space is left to allocate! Consider resizing your pool.")
+            }
+            Self::InvalidCapacity(capacity) => {
+                write!(f, "Cannot allocate {capacity} words to a pool!")
+            }
+            Self::InvalidVptrParts {
+                level,
+                bucket,
+                offset,
+            } => write!(
+                f,
+                "Creating pointer: Out of bounds! Level: {level}, Bucket: {bucket}, Offset: {offset}"
+            ),
+            Self::UnallocatedVptr(vptr) => write!(
+                f,
+                "Virtual pointer points to unallocated memory. Pointer: {vptr}"
+            ),
+            Self::OutOfBoundsVptr(vptr) => write!(
+                f,
+                "Virtual pointer points to out of bound memory. Pointer: {vptr}"
+            ),
+            Self::StalePool(vptr) => write!(
+                f,
+                "The mapped pool is stale, as it has been grown elsewhere. Attach again to map it. Pointer: {vptr}"
+            ),
+            Self::InvalidPage(page) => {
+                write!(f, "Trying to lookup a non-existing page. Page: {page}")
+            }
+            Self::InvalidLevel(level) => write!(f, "Invalid level for this operation: {level}"),
+            Self::InvalidNode(msg) => write!(f, "{msg}"),
+            Self::InvalidIndex { level, idx } => write!(
+                f,
+                "Node index out of bounds! Level: {level}, Index: {idx}"
+            ),
+            Self::InvalidRange(range) => write!(
+                f,
+                "Cannot register a range spanning beyond a page: {range:?}"
+            ),
+            Self::EmptyDAG => write!(f, "An empty DAG is invalid state."),
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Format(msg) => write!(f, "{msg}"),
+            Self::Shmem(ShmemError::LinkExists) => {
+                write!(f, "A HashDAG with the same file link already exists.")
+            }
+            Self::Shmem(error) => write!(f, "{error}"),
+            Self::InvalidConfig(rule) => write!(f, "Invalid HashTableConfig: {rule}."),
+            Self::NotOwner => write!(
+                f,
+                "Only the HashDAG which created the shared memory can resize it."
+            ),
+            Self::InvalidHistoryEntry(idx) => write!(f, "No history entry at index {idx}."),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Shmem(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Foreign errors are compared by kind or by message, as they do not implement `PartialEq` themselves.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::BucketOverflow { level, bucket },
+                Self::BucketOverflow {
+                    level: other_level,
+                    bucket: other_bucket,
+                },
+            ) => level == other_level && bucket == other_bucket,
+            (
+                Self::BucketLenOutOfBounds { level, bucket_len },
+                Self::BucketLenOutOfBounds {
+                    level: other_level,
+                    bucket_len: other_bucket_len,
+                },
+            ) => level == other_level && bucket_len == other_bucket_len,
+            (
+                Self::InvalidVptrParts {
+                    level,
+                    bucket,
+                    offset,
+                },
+                Self::InvalidVptrParts {
+                    level: other_level,
+                    bucket: other_bucket,
+                    offset: other_offset,
+                },
+            ) => level == other_level && bucket == other_bucket && offset == other_offset,
+            (
+                Self::InvalidIndex { level, idx },
+                Self::InvalidIndex {
+                    level: other_level,
+                    idx: other_idx,
+                },
+            ) => level == other_level && idx == other_idx,
+            (Self::PoolExhausted, Self::PoolExhausted)
+            | (Self::EmptyDAG, Self::EmptyDAG)
+            | (Self::NotOwner, Self::NotOwner) => true,
+            (Self::InvalidCapacity(a), Self::InvalidCapacity(b))
+            | (Self::InvalidPage(a), Self::InvalidPage(b))
+            | (Self::InvalidHistoryEntry(a), Self::InvalidHistoryEntry(b)) => a == b,
+            (Self::UnallocatedVptr(a), Self::UnallocatedVptr(b))
+            | (Self::OutOfBoundsVptr(a), Self::OutOfBoundsVptr(b))
+            | (Self::StalePool(a), Self::StalePool(b))
+            | (Self::InvalidLevel(a), Self::InvalidLevel(b)) => a == b,
+            (Self::InvalidNode(a), Self::InvalidNode(b))
+            | (Self::Format(a), Self::Format(b))
+            | (Self::InvalidConfig(a), Self::InvalidConfig(b)) => a == b,
+            (Self::InvalidRange(a), Self::InvalidRange(b)) => a == b,
+            (Self::Io(a), Self::Io(b)) => a.kind() == b.kind(),
+            (Self::Shmem(a), Self::Shmem(b)) => a.to_string() == b.to_string(),
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ShmemError> for Error {
+    #[inline]
+    fn from(error: ShmemError) -> Self {
+        Self::Shmem(error)
+    }
+}
@@ -0,0 +1,104 @@
+use super::{
+    hash_table::{basic::HashTable, storage::Backing},
+    shared_hash_dag::SharedHashDAG,
+    tracking::{dummy::HostOnlyHashDAG, Tracker},
+    validation::Node::Pass,
+    Error, HashDAG, HashDAGMut, Result,
+};
+use ::std::collections::{HashMap, HashSet};
+
+pub trait Collector {
+    /// Removes every node which is neither reachable from `roots` nor a full node, and compacts the pool,
+    /// page table and free store accordingly.
+    /// Returns the new virtual pointers of `roots` (in the same order), as any vptr may change during compaction.
+    ///
+    /// The live nodes are rebuilt into a table on the heap, which then replaces the contents of this one,
+    /// so should anything fail, e.g. as a root is not a node, the table is left untouched.
+    /// Meanwhile the pool is held twice.
+    fn collect(&mut self, roots: &[u32]) -> Result<Vec<u32>>;
+}
+
+impl<T: Tracker> Collector for SharedHashDAG<HashTable<'_>, T> {
+    fn collect(&mut self, roots: &[u32]) -> Result<Vec<u32>> {
+        debug_assert!(self.journal.is_none(), "Collecting within a transaction.");
+        let live = self.mark(roots)?;
+        let leaf_level = self.config.leaf_level();
+        let mut rebuilt =
+            HostOnlyHashDAG::with_backing(Backing::Heap, self.pool.len(), self.config)?;
+        rebuilt.set_growable(self.growable);
+        let mut remap = HashMap::with_capacity(live.iter().map(Vec::len).sum());
+        for (&old, &new) in self
+            .full_node_pointers
+            .iter()
+            .zip(&rebuilt.full_node_pointers)
+        {
+            if old != !0 {
+                remap.insert(old, new);
+            }
+        }
+        let remapped = |remap: &HashMap<u32, u32>, vptr: &u32| {
+            remap
+                .get(vptr)
+                .copied()
+                .ok_or_else(|| Error::InvalidNode(format!("{vptr} does not point to a live node.")))
+        };
+        // Bottom-up, so all children have been remapped before their parents are added.
+        for (level, nodes) in live.into_iter().enumerate().rev() {
+            for (vptr, mut node) in nodes {
+                let new_vptr = if level == leaf_level as usize {
+                    rebuilt.find_or_add_leaf(Pass(&node))?
+                } else {
+                    for child in node.iter_mut().skip(1) {
+                        *child = remapped(&remap, child)?;
+                    }
+                    rebuilt.find_or_add_interior(level as _, Pass(&node))?
+                };
+                remap.insert(vptr, new_vptr);
+            }
+        }
+        let roots = roots
+            .iter()
+            .map(|vptr| remapped(&remap, vptr))
+            .collect::<Result<_>>()?;
+        let used = (rebuilt.lut.hi() * self.config.page_len) as usize;
+        if self.pool.len() < used {
+            self.grow(rebuilt.pool.len())?;
+        }
+        self.hash_dag.lut.copy_from(&rebuilt.lut, rebuilt.lut.hi());
+        self.bucket_len_copy_from(0, &rebuilt.bucket_len);
+        self.pool_copy_from(0, &rebuilt.pool[..used]);
+        self.hash_dag.full_node_pointers = rebuilt.full_node_pointers;
+        if self.fingerprints.is_some() {
+            self.hash_dag.fingerprints = rebuilt.hash_dag.fingerprints;
+        }
+        self.tracker.register_all(self.pool.len());
+        Ok(roots)
+    }
+}
+
+impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
+    /// Copies out every node reachable from `roots`, grouped per level. Full nodes are skipped.
+    #[allow(clippy::type_complexity)]
+    fn mark(&self, roots: &[u32]) -> Result<Vec<Vec<(u32, Vec<u32>)>>> {
+        let leaf_level = self.config.leaf_level();
+        let mut live = vec![Vec::new(); leaf_level as usize + 1];
+        let mut visited = HashSet::new();
+        let mut items = roots.to_vec();
+        while let Some(vptr) = items.pop() {
+            if !visited.insert(vptr) || self.full_node_pointers.contains(&vptr) {
+                continue;
+            }
+            let level = self.config.vptr_to_lvl(vptr);
+            if leaf_level < level {
+                return Err(Error::InvalidLevel(level));
+            } else if level == leaf_level {
+                live[level as usize].push((vptr, self.leaf(vptr)?.to_vec()));
+            } else {
+                let interior = self.interior(vptr)?;
+                items.extend_from_slice(&interior[1..]);
+                live[level as usize].push((vptr, interior.to_vec()));
+            }
+        }
+        Ok(live)
+    }
+}
@@ -1,106 +1,450 @@
-use super::super::{
-    constants::{LEAF_LEVEL, PAGE_LEN, TOTAL_BUCKETS, TOTAL_PAGES, TOTAL_VIRT_SPACE},
-    utils::{new_bucket_len, new_bucket_len_idx, new_vptr, shmem::ShmemArray},
-    Result,
+use super::{
+    super::{constants::LEAF_LEVEL, hashing::NodeHasher, Error, Result},
+    config::HashTableConfig,
+    fingerprints::{BucketFingerprints, Fingerprints},
+    storage::{Backing, Storage},
 };
-use ::{
-    shared_memory::ShmemError,
-    std::{cmp::Ordering, ops::Deref, pin::Pin},
+use ::std::{
+    ops::{Deref, Range},
+    sync::atomic,
 };
 
-const LUT_LEN: usize = TOTAL_PAGES as usize;
+/// Buckets shorter than this are searched without their fingerprints.
+pub const FINGERPRINTED_BUCKET_LEN: u32 = 64;
 
-pub struct PageLUT<'shmem>(Pin<Box<ShmemArray<'shmem, u32>>>);
+/// The page table, followed by `hi`: the number of allocated pages,
+/// and the length of the pool, so that tables attached elsewhere notice when it has been grown.
+#[derive(Clone)]
+pub struct PageLUT<'shmem> {
+    mem: Storage<'shmem>,
+    page_len: u32,
+}
 
 impl Deref for PageLUT<'_> {
     type Target = [u32];
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.0[..LUT_LEN] // exclude hi
+        &self.mem[..self.hi_idx()] // exclude hi and the pool length
     }
 }
 
 impl PageLUT<'_> {
     #[inline]
-    pub fn new(root: Option<&String>) -> std::result::Result<Self, ShmemError> {
-        Ok(Self({
-            let root = root.map(|root| format!("{root}page_table.flink"));
-            let mut mem = ShmemArray::new(LUT_LEN + 1, root)?;
-            mem.copy_from(0, &[!0; LUT_LEN]);
-            mem.copy_from(LUT_LEN, &[0]); // set hi to 0
-            mem
-        }))
+    pub fn new(backing: Backing, config: &HashTableConfig, pool_len: usize) -> Result<Self> {
+        let len = config.total_pages() as usize;
+        let mut mem = Storage::new(backing, "page_table", len + 2)?;
+        mem.copy_from(0, &vec![!0; len]);
+        mem.copy_from(len, &[0, pool_len as u32]); // set hi to 0
+        Ok(Self {
+            mem,
+            page_len: config.page_len,
+        })
+    }
+    /// Opens the page table of a `HashTable` which has been created elsewhere.
+    #[inline]
+    pub fn open(root: &str, config: &HashTableConfig) -> Result<Self> {
+        Self::from_storage(Storage::open(format!("{root}page_table.flink"))?, config)
+    }
+    /// Takes over opened memory, which has to fit the page table of `config`.
+    fn from_storage<'a>(mem: Storage<'a>, config: &HashTableConfig) -> Result<PageLUT<'a>> {
+        let len = config.total_pages() as usize + 2;
+        Ok(PageLUT {
+            mem: mem.expect_len(len, "a page table")?,
+            page_len: config.page_len,
+        })
+    }
+    #[inline]
+    fn hi_idx(&self) -> usize {
+        self.mem.len() - 2
+    }
+    #[inline]
+    fn pool_len_idx(&self) -> usize {
+        self.mem.len() - 1
     }
     #[inline]
     pub fn allocate(&mut self, page: usize) {
-        // TODO lock on a page in current scope
+        // SAFETY: the page table is borrowed mutably.
+        unsafe { self.allocate_shared(page) };
+    }
+    /// Allocates a page while other threads may be allocating other pages. Returns the pool index of the page.
+    ///
+    /// # Safety
+    ///
+    /// - No other thread may access this page's entry at the same time.
+    #[inline]
+    pub unsafe fn allocate_shared(&self, page: usize) -> u32 {
         debug_assert!(
             !self.is_allocated(page).expect("Page does not exist."),
             "Trying to allocate an allocated page."
         );
+        let hi = self
+            .mem
+            .atomic(self.hi_idx())
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        self.mem.copy_from_shared(page, &[hi * self.page_len]);
+        hi * self.page_len
+    }
+    /// Like `allocate_shared`, but leaves the page unallocated if it would not fit into a pool of `pool_len` words.
+    ///
+    /// # Safety
+    ///
+    /// - No other thread may access this page's entry at the same time.
+    #[inline]
+    pub unsafe fn try_allocate_shared(&self, page: usize, pool_len: usize) -> Option<u32> {
+        debug_assert!(
+            !self.is_allocated(page).expect("Page does not exist."),
+            "Trying to allocate an allocated page."
+        );
+        let page_len = self.page_len as usize;
+        let hi = self
+            .mem
+            .atomic(self.hi_idx())
+            .fetch_update(atomic::Ordering::AcqRel, atomic::Ordering::Acquire, |hi| {
+                ((hi as usize + 1) * page_len <= pool_len).then_some(hi + 1)
+            })
+            .ok()?;
+        self.mem.copy_from_shared(page, &[hi * self.page_len]);
+        Some(hi * self.page_len)
+    }
+    /// Allocates `pages` in the given order, raising `hi` once.
+    pub fn allocate_all(&mut self, pages: &[usize]) {
         let hi = self.hi();
-        self.0.copy_from(page, &[hi * PAGE_LEN]);
-        self.0.copy_from(LUT_LEN, &[hi + 1]); // write hi
+        for (pool_page, &page) in (hi..).zip(pages) {
+            debug_assert!(
+                !self.is_allocated(page).expect("Page does not exist."),
+                "Trying to allocate an allocated page."
+            );
+            self.mem.copy_from(page, &[pool_page * self.page_len]);
+        }
+        let hi_idx = self.hi_idx();
+        self.mem.copy_from(hi_idx, &[hi + pages.len() as u32]);
+    }
+    /// Overwrites every page table entry and `hi`.
+    #[inline]
+    pub fn copy_from(&mut self, lut: &[u32], hi: u32) {
+        debug_assert_eq!(lut.len(), self.hi_idx());
+        let hi_idx = self.hi_idx();
+        self.mem.copy_from(0, lut);
+        self.mem.copy_from(hi_idx, &[hi]);
+    }
+    /// Unallocates `pages`, which have to be the ones allocated last, and lowers `hi` accordingly.
+    pub fn unallocate_last(&mut self, pages: &[usize]) {
+        if pages.is_empty() {
+            return;
+        }
+        for &page in pages {
+            self.mem.copy_from(page, &[!0]);
+        }
+        let hi_idx = self.hi_idx();
+        self.mem
+            .copy_from(hi_idx, &[self.hi() - pages.len() as u32]);
+    }
+    /// Unallocates every page.
+    #[inline]
+    pub fn clear(&mut self) {
+        let hi_idx = self.hi_idx();
+        self.mem.copy_from(0, &vec![!0; hi_idx]);
+        self.mem.copy_from(hi_idx, &[0]); // set hi to 0
     }
     #[inline]
     #[must_use]
     pub fn hi(&self) -> u32 {
-        self.0[LUT_LEN]
+        self.mem
+            .atomic(self.hi_idx())
+            .load(atomic::Ordering::Acquire)
+    }
+    /// The length of the pool, as last set by the table which owns it.
+    #[inline]
+    #[must_use]
+    pub fn pool_len(&self) -> usize {
+        self.mem
+            .atomic(self.pool_len_idx())
+            .load(atomic::Ordering::Acquire) as _
+    }
+    /// Publishes the length of a grown pool.
+    #[inline]
+    pub fn set_pool_len(&mut self, pool_len: usize) {
+        let pool_len_idx = self.pool_len_idx();
+        self.mem.copy_from(pool_len_idx, &[pool_len as u32]);
     }
     #[inline]
     pub fn is_allocated(&self, page: usize) -> Result<bool> {
         match self.get(page) {
             Some(&vptr) => Ok(vptr != !0),
-            None => Err("Trying to lookup a non-existing page.".into()),
+            None => Err(Error::InvalidPage(page)),
         }
     }
 }
 
+/// Cloning copies the table into heap memory, see `Storage`.
+#[derive(Clone)]
 pub struct HashTable<'shmem> {
-    /// The virtual pointers of each level's full node.
+    /// The geometry of the table.
+    pub config: HashTableConfig,
+    /// The virtual pointers of each level's full node. Levels below the configured leaf level are unused.
     pub full_node_pointers: [u32; LEAF_LEVEL as usize + 1],
     /// The page table spanning the full virtual space.
     pub lut: PageLUT<'shmem>,
     /// The free store which tells you how full a given bucket is.
-    pub bucket_len: Pin<Box<ShmemArray<'shmem, u32>>>,
+    pub bucket_len: Storage<'shmem>,
     /// The pool containing **all** nodes.
-    pub pool: Pin<Box<ShmemArray<'shmem, u32>>>,
+    pub pool: Storage<'shmem>,
+    /// Whether `SharedHashDAG` grows the pool once it runs out of pages, instead of failing.
+    pub growable: bool,
+    /// Speeds up searching long buckets. On unless other processes can attach, see `set_fingerprinted`.
+    pub fingerprints: Option<Fingerprints>,
 }
 
 impl HashTable<'_> {
     /// Initializes the pool to a multiple of 128 pages to prevent UB.
-    pub fn blank(root: Option<&String>, mut capacity: usize) -> Result<Self> {
-        #[inline]
-        fn map(error: ShmemError) -> String {
-            match error {
-                ShmemError::LinkExists => {
-                    "A HashDAG with the same file link already exists.".into()
-                }
-                _ => error.to_string(),
-            }
-        }
-
-        const BLOCK_LEN: usize = PAGE_LEN as usize * 128;
-        capacity += (BLOCK_LEN - capacity % BLOCK_LEN) % BLOCK_LEN;
-        if (TOTAL_VIRT_SPACE as usize) < capacity || capacity == 0 {
-            Err(format!("Cannot allocate {capacity} words to a pool!"))
+    #[inline]
+    pub fn blank(root: Option<&String>, capacity: usize) -> Result<Self> {
+        Self::blank_with_config(root, capacity, HashTableConfig::DEFAULT)
+    }
+    /// Initializes the pool to a multiple of 128 pages to prevent UB.
+    #[inline]
+    pub fn blank_with_config(
+        root: Option<&String>,
+        capacity: usize,
+        config: HashTableConfig,
+    ) -> Result<Self> {
+        Self::blank_in(Backing::Shared(root), capacity, config)
+    }
+    /// Like `blank_with_config`, keeping everything in the given backing.
+    pub fn blank_in(backing: Backing, capacity: usize, config: HashTableConfig) -> Result<Self> {
+        config.validate()?;
+        let capacity = config.block_aligned(capacity);
+        if (config.total_virt_space() as usize) < capacity || capacity == 0 {
+            Err(Error::InvalidCapacity(capacity))
         } else {
+            let pool = Storage::new(backing, "data_pool", capacity)?;
             Ok(Self {
+                config,
                 full_node_pointers: [!0; LEAF_LEVEL as usize + 1],
-                lut: PageLUT::new(root).map_err(map)?,
+                lut: PageLUT::new(backing, &config, capacity)?,
                 bucket_len: {
-                    let root = root.map(|root| format!("{root}free_store.flink"));
-                    let mut mem = ShmemArray::new(TOTAL_BUCKETS as _, root).map_err(map)?;
-                    mem.copy_from(0, &[0; TOTAL_BUCKETS as _]);
+                    let len = config.total_buckets() as usize;
+                    let mut mem = Storage::new(backing, "free_store", len)?;
+                    mem.copy_from(0, &vec![0; len]);
                     mem
                 },
-                pool: {
-                    let root = root.map(|root| format!("{root}data_pool.flink"));
-                    ShmemArray::new(capacity, root).map_err(map)?
-                },
+                pool,
+                growable: false,
+                fingerprints: (!matches!(backing, Backing::Shared(Some(_))))
+                    .then(Fingerprints::default),
             })
         }
     }
+    /// Attaches to a `HashTable` created elsewhere with the same `root`, e.g. by another process.
+    /// The full nodes are looked up again, as their pointers do not live in shared memory.
+    #[inline]
+    pub fn attach(root: &str) -> Result<Self> {
+        Self::attach_with_config(root, HashTableConfig::DEFAULT)
+    }
+    /// Like `attach`, for a table created with `config`, e.g. as read by `ShmemConfig::read`.
+    /// Every segment is checked against the geometry of `config`, see `HashTable::check_geometry`.
+    #[inline]
+    pub fn attach_with_config(root: &str, config: HashTableConfig) -> Result<Self> {
+        Self::attach_in(root, config, Storage::open)
+    }
+    /// Attaches through `open`, which opens each segment by its flink.
+    pub(crate) fn attach_in<'a, F>(
+        root: &str,
+        config: HashTableConfig,
+        open: F,
+    ) -> Result<HashTable<'a>>
+    where
+        F: Fn(String) -> Result<Storage<'a>>,
+    {
+        config.validate()?;
+        let mut hash_table = HashTable {
+            config,
+            full_node_pointers: [!0; LEAF_LEVEL as usize + 1],
+            lut: PageLUT::from_storage(open(format!("{root}page_table.flink"))?, &config)?,
+            bucket_len: open(format!("{root}free_store.flink"))?
+                .expect_len(config.total_buckets() as _, "a free store")?,
+            pool: open(format!("{root}data_pool.flink"))?,
+            growable: false,
+            fingerprints: None,
+        };
+        hash_table.check_geometry()?;
+        hash_table.full_node_pointers = hash_table.find_full_nodes()?;
+        Ok(hash_table)
+    }
+    /// Checks that the pool, page table and free store follow the geometry of the config, as any table created with it does:
+    /// the pool is block aligned and holds every allocated page, each allocated page starts its own page of the pool
+    /// and no bucket is longer than its level allows.
+    pub fn check_geometry(&self) -> Result<()> {
+        let (config, hi) = (&self.config, self.lut.hi());
+        let pool_len = self.pool.len();
+        if pool_len == 0
+            || !pool_len.is_multiple_of(config.block_len())
+            || (config.total_virt_space() as usize) < pool_len
+            || config.total_pages() < hi
+            || pool_len < (hi as usize) * config.page_len as usize
+        {
+            return Err(Error::Format(format!(
+                "A pool of {pool_len} words cannot hold {hi} pages."
+            )));
+        } else if self.lut.pool_len() != pool_len {
+            return Err(Error::Format(format!(
+                "The page table expects a pool of {} words, not {pool_len}.",
+                self.lut.pool_len()
+            )));
+        }
+        let misplaced = self.lut.iter().position(|&pool_idx| {
+            pool_idx != !0
+                && (!pool_idx.is_multiple_of(config.page_len) || hi * config.page_len <= pool_idx)
+        });
+        if let Some(page) = misplaced {
+            return Err(Error::Format(format!(
+                "Page {page} is mapped to {}, which is not an allocated page of the pool.",
+                self.lut[page]
+            )));
+        }
+        let mut mapped = vec![false; hi as usize];
+        for (page, &pool_idx) in self.lut.iter().enumerate().filter(|(_, &idx)| idx != !0) {
+            let pool_page = &mut mapped[(pool_idx / config.page_len) as usize];
+            if *pool_page {
+                return Err(Error::Format(format!(
+                    "Page {page} is mapped to {pool_idx}, as is another page."
+                )));
+            }
+            *pool_page = true;
+        }
+        for level in 0..config.supported_levels {
+            let capacity = config.new_bucket_len(level);
+            for bucket in 0..config.buckets_per_level(level) {
+                let bucket_len = self.bucket_len(level, bucket);
+                if capacity < bucket_len {
+                    return Err(Error::BucketLenOutOfBounds { level, bucket_len });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HashTable<'_> {
+    /// Moves the pool into a larger segment of at least `capacity` words, keeping every allocated page.
+    /// Virtual pointers stay valid, as only the page table refers to pool indices.
+    /// Tables attached elsewhere keep mapping the old pool and have to attach again,
+    /// until then any access to their pool fails with `StalePool`.
+    pub fn grow(&mut self, capacity: usize) -> Result<()> {
+        let capacity = self.config.block_aligned(capacity);
+        if !self.pool.is_owner() {
+            Err(Error::NotOwner)
+        } else if capacity <= self.pool.len()
+            || (self.config.total_virt_space() as usize) < capacity
+        {
+            Err(Error::InvalidCapacity(capacity))
+        } else {
+            let used = (self.lut.hi() * self.config.page_len) as usize;
+            self.pool.resize(capacity, used)?;
+            self.lut.set_pool_len(self.pool.len());
+            Ok(())
+        }
+    }
+}
+
+/// Fingerprints
+impl HashTable<'_> {
+    /// The fingerprint of a node of `level`, see `Fingerprints::of`.
+    #[inline]
+    #[must_use]
+    pub fn fingerprint(&self, level: u32, node: &[u32]) -> u16 {
+        Fingerprints::of(if level == self.config.leaf_level() {
+            self.config.hasher.hash_leaf(node)
+        } else {
+            self.config.hasher.hash_interior(node)
+        })
+    }
+    /// The fingerprints to search a bucket of `bucket_len` words with. Short buckets are cheaper to scan directly.
+    #[inline]
+    fn bucket_fingerprints(
+        &self,
+        level: u32,
+        bucket: u32,
+        bucket_len: u32,
+    ) -> Option<&BucketFingerprints> {
+        match &self.fingerprints {
+            Some(fingerprints) if FINGERPRINTED_BUCKET_LEN <= bucket_len => {
+                fingerprints.bucket(self.config.new_bucket_len_idx(level, bucket))
+            }
+            _ => None,
+        }
+    }
+    /// Indexes the nodes of a bucket up to `bucket_len`, e.g. once a node has been written to it.
+    /// Buckets are only indexed once they are long enough to be searched by fingerprint.
+    pub fn record_fingerprints(&mut self, level: u32, bucket: u32, bucket_len: u32) -> Result<()> {
+        match self.fingerprints.take() {
+            Some(mut fingerprints) => {
+                let indexed = self.index_bucket(&mut fingerprints, level, bucket, bucket_len);
+                self.fingerprints = Some(fingerprints);
+                indexed
+            }
+            None => Ok(()),
+        }
+    }
+    /// Pushes the nodes a bucket gained since it has last been indexed.
+    /// A bucket which shrank meanwhile, e.g. as it has been cleared elsewhere, forgets the nodes beyond first.
+    fn index_bucket(
+        &self,
+        fingerprints: &mut Fingerprints,
+        level: u32,
+        bucket: u32,
+        bucket_len: u32,
+    ) -> Result<()> {
+        if bucket_len < FINGERPRINTED_BUCKET_LEN {
+            return Ok(());
+        }
+        let bucket_len_idx = self.config.new_bucket_len_idx(level, bucket);
+        let base_ptr = self.config.new_vptr(level, bucket, 0)?;
+        let index = fingerprints
+            .bucket_mut(bucket_len_idx, true)
+            .expect("Buckets are created on demand.");
+        index.truncate(bucket_len);
+        self.walk(
+            level,
+            bucket,
+            index.indexed_len()..bucket_len,
+            |vptr, _, node| {
+                index.push(self.fingerprint(level, node), vptr - base_ptr);
+                false
+            },
+        )?;
+        index.set_indexed_len(bucket_len);
+        Ok(())
+    }
+    /// Forgets the nodes of a bucket at or beyond `bucket_len`, e.g. once they are rolled back.
+    pub fn forget_fingerprints(&mut self, level: u32, bucket: u32, bucket_len: u32) {
+        let bucket_len_idx = self.config.new_bucket_len_idx(level, bucket);
+        if let Some(index) = self
+            .fingerprints
+            .as_mut()
+            .and_then(|fingerprints| fingerprints.bucket_mut(bucket_len_idx, false))
+        {
+            index.truncate(bucket_len);
+        }
+    }
+    /// Indexes every bucket long enough to be searched by fingerprint, or drops the fingerprints.
+    /// Padding at the end of a page may be fingerprinted as well, which only adds candidates.
+    /// Must stay off for named shared memory, as attached processes may clear buckets without it being noticed.
+    pub fn set_fingerprinted(&mut self, fingerprinted: bool) -> Result<()> {
+        self.fingerprints = None;
+        if !fingerprinted {
+            return Ok(());
+        }
+        let mut fingerprints = Fingerprints::default();
+        for level in 0..=self.config.leaf_level() {
+            for bucket in 0..self.config.buckets_per_level(level) {
+                let bucket_len = self.bucket_len(level, bucket);
+                self.index_bucket(&mut fingerprints, level, bucket, bucket_len)?;
+            }
+        }
+        self.fingerprints = Some(fingerprints);
+        Ok(())
+    }
 }
 
 impl HashTable<'_> {
@@ -111,20 +455,78 @@ impl HashTable<'_> {
     #[inline]
     #[must_use]
     pub fn bucket_len(&self, level: u32, bucket: u32) -> u32 {
-        self.bucket_len[new_bucket_len_idx(level, bucket)]
+        self.bucket_len[self.config.new_bucket_len_idx(level, bucket)]
+    }
+    /// Determines where a node of `node_len` words would be added to the bucket.
+    /// Returns its virtual pointer, the bucket length afterwards and whether its page has yet to be allocated.
+    pub fn placement(&self, level: u32, bucket: u32, node_len: u32) -> Result<(u32, u32, bool)> {
+        self.placement_after(level, bucket, self.bucket_len(level, bucket), node_len)
+    }
+    /// Like `placement`, for the bucket once it holds `bucket_len` words.
+    pub fn placement_after(
+        &self,
+        level: u32,
+        bucket: u32,
+        mut bucket_len: u32,
+        node_len: u32,
+    ) -> Result<(u32, u32, bool)> {
+        let page_len = self.config.page_len;
+        let page_space_left = page_len - bucket_len % page_len;
+        let would_overflow = page_space_left < node_len;
+        if would_overflow {
+            bucket_len += page_space_left;
+        }
+        if self.config.new_bucket_len(level) <= bucket_len + node_len {
+            Err(Error::BucketOverflow { level, bucket })
+        } else {
+            let vptr = self.config.new_vptr(level, bucket, bucket_len)?;
+            let allocate = page_len == page_space_left || would_overflow;
+            Ok((vptr, bucket_len + node_len, allocate))
+        }
+    }
+    /// Whether a node of `node_len` words still fits into the bucket.
+    #[inline]
+    #[must_use]
+    pub fn has_room(&self, level: u32, bucket: u32, node_len: u32) -> bool {
+        self.placement(level, bucket, node_len).is_ok()
+    }
+    /// The buckets probed for a node whose hash leads to `bucket`: the bucket itself and its successors, wrapping around.
+    #[inline]
+    pub fn probe(&self, level: u32, bucket: u32) -> impl Iterator<Item = u32> {
+        let buckets = self.config.buckets_per_level(level);
+        (0..buckets).map(move |probe| (bucket + probe) & (buckets - 1))
+    }
+    /// The first probed bucket with room for a node of `node_len` words.
+    pub fn free_bucket(&self, level: u32, bucket: u32, node_len: u32) -> Result<u32> {
+        self.probe(level, bucket)
+            .find(|&probed| self.has_room(level, probed, node_len))
+            .ok_or(Error::BucketOverflow { level, bucket })
+    }
+    /// Fails with `StalePool` if the pool has been grown elsewhere since it was mapped.
+    #[inline]
+    pub fn check_pool(&self, vptr: u32) -> Result<()> {
+        if self.pool.len() == self.lut.pool_len() {
+            Ok(())
+        } else {
+            Err(Error::StalePool(vptr))
+        }
     }
     #[inline]
     pub fn pool_idx(&self, vptr: u32) -> Result<usize> {
-        let (page, offset) = ((vptr / PAGE_LEN) as _, vptr % PAGE_LEN);
+        self.check_pool(vptr)?;
+        let page_len = self.config.page_len;
+        let (page, offset) = ((vptr / page_len) as _, vptr % page_len);
         if self.lut.is_allocated(page)? {
             let idx = self.lut[page] + offset;
-            if idx < self.lut.hi() * PAGE_LEN {
-                Ok(idx as _)
+            if self.lut.hi() * page_len <= idx {
+                Err(Error::OutOfBoundsVptr(vptr))
+            } else if self.pool.len() <= idx as usize {
+                Err(Error::StalePool(vptr))
             } else {
-                Err("Virtual pointer points to out of bound memory.".into())
+                Ok(idx as _)
             }
         } else {
-            Err("Virtual pointer points to unallocated memory.".into())
+            Err(Error::UnallocatedVptr(vptr))
         }
     }
 }
@@ -133,24 +535,82 @@ impl HashTable<'_> {
     #[inline]
     pub fn full_node_ptr(&self, level: u32) -> Result<u32> {
         match self.full_node_pointers.get(level as usize) {
-            Some(&vptr) => Ok(vptr),
-            None => Err("Trying a full node lookup with a non-existing level.".into()),
+            Some(&vptr) if level <= self.config.leaf_level() => Ok(vptr),
+            _ => Err(Error::InvalidLevel(level)),
         }
     }
+    /// Looks up the full node of every level. Levels without one are set to `!0`, as are all levels above.
+    pub fn find_full_nodes(&self) -> Result<[u32; LEAF_LEVEL as usize + 1]> {
+        const LEAF: [u32; 2] = [!0, !0];
+        let leaf_level = self.config.leaf_level();
+        let mut full_node_pointers = [!0; LEAF_LEVEL as usize + 1];
+        let bucket = self.config.leaf_bucket(&LEAF);
+        match self.probe_leaf(bucket, &LEAF)? {
+            Some(vptr) => full_node_pointers[leaf_level as usize] = vptr,
+            None => return Ok(full_node_pointers),
+        }
+        for level in (0..leaf_level).rev() {
+            let interior = self
+                .config
+                .full_interior(level, full_node_pointers[level as usize + 1]);
+            let bucket = self.config.interior_bucket(level, &interior);
+            match self.probe_interior(level, bucket, &interior)? {
+                Some(vptr) => full_node_pointers[level as usize] = vptr,
+                None => break,
+            }
+        }
+        Ok(full_node_pointers)
+    }
+    /// Searches every bucket a leaf whose hash leads to `bucket` may have been added to.
+    #[inline]
+    pub fn probe_leaf(&self, bucket: u32, leaf: &[u32]) -> Result<Option<u32>> {
+        let leaf_level = self.config.leaf_level();
+        self.probe_with(leaf_level, bucket, leaf.len() as _, |bucket, bucket_len| {
+            self.find_leaf(bucket, bucket_len, leaf)
+        })
+    }
+    /// Searches every bucket an interior node whose hash leads to `bucket` may have been added to.
+    #[inline]
+    pub fn probe_interior(&self, level: u32, bucket: u32, interior: &[u32]) -> Result<Option<u32>> {
+        self.probe_with(level, bucket, interior.len() as _, |bucket, bucket_len| {
+            self.find_interior(level, bucket, bucket_len, interior)
+        })
+    }
+    /// Nodes only skip buckets without room for them and buckets never shrink,
+    /// so probing stops at the first bucket which still has room for the node.
+    fn probe_with<F>(
+        &self,
+        level: u32,
+        bucket: u32,
+        node_len: u32,
+        mut find: F,
+    ) -> Result<Option<u32>>
+    where
+        F: FnMut(u32, u32) -> Result<Option<u32>>,
+    {
+        for bucket in self.probe(level, bucket) {
+            let bucket_len = self.bucket_len(level, bucket);
+            if bucket_len != 0 {
+                if let Some(vptr) = find(bucket, bucket_len)? {
+                    return Ok(Some(vptr));
+                }
+            }
+            if self.has_room(level, bucket, node_len) {
+                break;
+            }
+        }
+        Ok(None)
+    }
     /// Does a sequential search for the specified node.
     pub fn find_leaf(&self, bucket: u32, bucket_len: u32, leaf: &[u32]) -> Result<Option<u32>> {
-        if new_bucket_len(LEAF_LEVEL) < bucket_len {
-            Err("Trying to find a leaf with an overflowing bucket size.".into())
+        let leaf_level = self.config.leaf_level();
+        if self.config.new_bucket_len(leaf_level) < bucket_len {
+            Err(Error::BucketLenOutOfBounds {
+                level: leaf_level,
+                bucket_len,
+            })
         } else {
-            let vptr = new_vptr(LEAF_LEVEL, bucket, 0)?;
-            let pool_idx = self.pool_idx(vptr)?;
-            Ok((0..bucket_len).step_by(2).find_map(|offset| {
-                let pool_idx = pool_idx + offset as usize;
-                match leaf.cmp(&self.pool[pool_idx..=pool_idx + 1]) {
-                    Ordering::Equal => Some(vptr + offset),
-                    _ => None,
-                }
-            }))
+            self.find(leaf_level, bucket, bucket_len, leaf)
         }
     }
     /// Does a sequential search for the specified node.
@@ -161,33 +621,104 @@ impl HashTable<'_> {
         bucket_len: u32,
         interior: &[u32],
     ) -> Result<Option<u32>> {
-        let node_len = interior.len() as u32;
-        if new_bucket_len(level) < bucket_len {
-            Err("Trying to find an interior node with an overflowing bucket size.".into())
+        if self.config.new_bucket_len(level) < bucket_len {
+            Err(Error::BucketLenOutOfBounds { level, bucket_len })
         } else {
-            let base_ptr = new_vptr(level, bucket, 0)?;
-            let base_idx = self.pool_idx(base_ptr)?;
-            let find_in_page = |lower_bound, upper_bound| {
-                let mut offset = lower_bound;
-                while offset < upper_bound {
-                    let idx = base_idx + offset as usize;
-                    match interior.cmp(&self.pool[idx..idx + interior.len()]) {
-                        Ordering::Equal => return Some(base_ptr + offset),
-                        _ => offset += (self.pool[idx] as u8).count_ones() + 1,
+            self.find(level, bucket, bucket_len, interior)
+        }
+    }
+    /// Compares only the nodes whose fingerprint matches within the indexed part of a bucket, if any,
+    /// and every node beyond.
+    fn find(&self, level: u32, bucket: u32, bucket_len: u32, node: &[u32]) -> Result<Option<u32>> {
+        let indexed_len = match self.bucket_fingerprints(level, bucket, bucket_len) {
+            Some(index) => {
+                let base_ptr = self.config.new_vptr(level, bucket, 0)?;
+                for offset in index.candidates(self.fingerprint(level, node), bucket_len) {
+                    let pool_idx = self.pool_idx(base_ptr + offset)?;
+                    if self.pool.get(pool_idx..pool_idx + node.len()) == Some(node) {
+                        return Ok(Some(base_ptr + offset));
                     }
                 }
-                None
-            };
-
-            let overflow = bucket_len % PAGE_LEN;
-            let clipped = bucket_len - overflow;
-            let mut walker = (0..clipped).step_by(PAGE_LEN as _);
-            let found = walker.find_map(|offset| find_in_page(offset, offset + PAGE_LEN));
-            Ok(if found.is_none() && node_len <= overflow {
-                find_in_page(clipped, bucket_len)
-            } else {
-                found
-            })
+                index.indexed_len().min(bucket_len)
+            }
+            None => 0,
+        };
+        self.walk(level, bucket, indexed_len..bucket_len, |_, _, stored| {
+            stored == node
+        })
+    }
+    /// Every node within the first `bucket_len` words of a bucket, as its virtual pointer, pool index and words.
+    /// Nodes are told apart by their child masks. Padding at the end of a page may be read as nodes as well.
+    pub fn bucket_nodes(
+        &self,
+        level: u32,
+        bucket: u32,
+        bucket_len: u32,
+    ) -> Result<Vec<(u32, usize, &[u32])>> {
+        let mut nodes = Vec::new();
+        self.walk(level, bucket, 0..bucket_len, |vptr, pool_idx, node| {
+            nodes.push((vptr, pool_idx, node));
+            false
+        })?;
+        Ok(nodes)
+    }
+    /// Passes every node of a bucket within `range`, which has to start at a node, to `f` until it returns true.
+    /// Returns the virtual pointer of that node. Nodes are told apart like `bucket_nodes` does.
+    fn walk<'a, F>(
+        &'a self,
+        level: u32,
+        bucket: u32,
+        range: Range<u32>,
+        mut f: F,
+    ) -> Result<Option<u32>>
+    where
+        F: FnMut(u32, usize, &'a [u32]) -> bool,
+    {
+        let (is_leaf, pool) = (level == self.config.leaf_level(), &self.pool[..]);
+        for page in self.bucket_pages(level, bucket, range)? {
+            let (vptr, pool_idx, len) = page?;
+            // Nodes never span pages, anything too short to hold a node is padding.
+            let mut offset = 0;
+            while offset < len {
+                let idx = pool_idx + offset as usize;
+                let node_len = if is_leaf {
+                    2
+                } else {
+                    (pool[idx] as u8).count_ones() + 1
+                };
+                if len < offset + node_len {
+                    break;
+                } else if f(vptr + offset, idx, &pool[idx..idx + node_len as usize]) {
+                    return Ok(Some(vptr + offset));
+                }
+                offset += node_len;
+            }
         }
+        Ok(None)
+    }
+    /// The pages holding the words of a bucket within `range`, as the virtual pointer and pool index of their first word
+    /// in range, along with the number of words in range.
+    /// Pages are allocated whenever a bucket needs one, so those of a bucket are not contiguous in the pool.
+    /// A range starting at the bucket always looks up its first page, so searching an unallocated bucket fails.
+    fn bucket_pages(
+        &self,
+        level: u32,
+        bucket: u32,
+        range: Range<u32>,
+    ) -> Result<impl Iterator<Item = Result<(u32, usize, u32)>> + '_> {
+        let base_ptr = self.config.new_vptr(level, bucket, 0)?;
+        let page_len = self.config.page_len;
+        let first_page = range.start - range.start % page_len;
+        Ok((first_page..range.end.max(1))
+            .step_by(page_len as _)
+            .map(move |page| {
+                let start = page.max(range.start);
+                let vptr = base_ptr + start;
+                Ok((
+                    vptr,
+                    self.pool_idx(vptr)?,
+                    (page + page_len).min(range.end) - start,
+                ))
+            }))
     }
 }
@@ -0,0 +1,319 @@
+use super::super::{
+    constants::{
+        BUCKETS_PER_HI_LEVEL, BUCKETS_PER_LO_LEVEL, HI_BUCKET_LEN, HI_LEVELS, LEAF_LEVELS,
+        LO_BUCKET_LEN, PAGE_LEN, SUPPORTED_LEVELS,
+    },
+    hashing::{NodeHash, NodeHasher},
+    validation::LevelInfo,
+    Error, Result,
+};
+use ::serde_derive::{Deserialize, Serialize};
+
+/// The geometry of a `HashTable`, chosen at construction.
+///
+/// Levels above `hi_levels` use hi buckets, the remaining levels use lo buckets.
+/// Anything not stored here is derived, following the same rules as `constants.rs`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashTableConfig {
+    pub page_len: u32,
+    /// At most `SUPPORTED_LEVELS`, as each table keeps room for that many full nodes.
+    pub supported_levels: u32,
+    pub hi_levels: u32,
+    pub hi_bucket_len: u32,
+    pub lo_bucket_len: u32,
+    pub buckets_per_hi_level: u32,
+    pub buckets_per_lo_level: u32,
+    /// Picks the home bucket of each node.
+    #[serde(default)]
+    pub hasher: NodeHash,
+}
+
+impl Default for HashTableConfig {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Derived settings
+impl HashTableConfig {
+    /// The geometry described by `constants.rs`.
+    pub const DEFAULT: Self = Self {
+        page_len: PAGE_LEN,
+        supported_levels: SUPPORTED_LEVELS,
+        hi_levels: HI_LEVELS,
+        hi_bucket_len: HI_BUCKET_LEN,
+        lo_bucket_len: LO_BUCKET_LEN,
+        buckets_per_hi_level: BUCKETS_PER_HI_LEVEL,
+        buckets_per_lo_level: BUCKETS_PER_LO_LEVEL,
+        hasher: NodeHash::Murmur,
+    };
+
+    #[inline]
+    #[must_use]
+    pub const fn color_tree_levels(&self) -> u32 {
+        self.supported_levels.saturating_sub(7)
+    }
+    #[inline]
+    #[must_use]
+    pub const fn leaf_level(&self) -> u32 {
+        self.supported_levels - LEAF_LEVELS
+    }
+    #[inline]
+    #[must_use]
+    pub const fn lo_levels(&self) -> u32 {
+        self.supported_levels - self.hi_levels
+    }
+    #[inline]
+    #[must_use]
+    pub const fn hi_pages_per_bucket(&self) -> u32 {
+        self.hi_bucket_len / self.page_len
+    }
+    #[inline]
+    #[must_use]
+    pub const fn lo_pages_per_bucket(&self) -> u32 {
+        self.lo_bucket_len / self.page_len
+    }
+    #[inline]
+    #[must_use]
+    pub const fn total_hi_buckets(&self) -> u32 {
+        self.hi_levels * self.buckets_per_hi_level
+    }
+    #[inline]
+    #[must_use]
+    pub const fn total_lo_buckets(&self) -> u32 {
+        self.lo_levels() * self.buckets_per_lo_level
+    }
+    #[inline]
+    #[must_use]
+    pub const fn total_buckets(&self) -> u32 {
+        self.total_hi_buckets() + self.total_lo_buckets()
+    }
+    #[inline]
+    #[must_use]
+    pub const fn total_pages(&self) -> u32 {
+        self.total_hi_buckets() * self.hi_pages_per_bucket()
+            + self.total_lo_buckets() * self.lo_pages_per_bucket()
+    }
+    #[inline]
+    #[must_use]
+    pub const fn total_virt_space(&self) -> u32 {
+        self.total_pages() * self.page_len
+    }
+    /// The pool is a multiple of this many words to prevent UB.
+    #[inline]
+    #[must_use]
+    pub const fn block_len(&self) -> usize {
+        self.page_len as usize * 128
+    }
+    /// Rounds `capacity` up to a multiple of `block_len`.
+    #[inline]
+    #[must_use]
+    pub const fn block_aligned(&self, capacity: usize) -> usize {
+        let block_len = self.block_len();
+        capacity + (block_len - capacity % block_len) % block_len
+    }
+    #[inline]
+    #[must_use]
+    pub const fn level_info(&self, level: u32) -> LevelInfo {
+        LevelInfo {
+            is_color_tree_level: self.color_tree_levels() <= level,
+            is_last_interior: level + 1 == self.leaf_level(),
+        }
+    }
+    /// The number of voxels spanned by a node of `level`. Only fits for color tree levels.
+    #[inline]
+    #[must_use]
+    pub const fn voxels_per_node(&self, level: u32) -> u32 {
+        1 << (3 * (self.supported_levels - level))
+    }
+}
+
+/// Validation
+impl HashTableConfig {
+    /// Checks the same rules as `constants::tests::verify_settings`, plus everything needed to not overflow.
+    pub fn validate(&self) -> Result<()> {
+        let is_pow2 = |n: u32| n.is_power_of_two();
+        // Every product `vptr_to_lvl` and the totals are derived from has to fit a u32, like `TOTAL_PAGES` does.
+        let hi_level_space = self.buckets_per_hi_level.checked_mul(self.hi_bucket_len);
+        let lo_level_space = self.buckets_per_lo_level.checked_mul(self.lo_bucket_len);
+        let hi_space = self
+            .hi_levels
+            .checked_mul(self.buckets_per_hi_level)
+            .and_then(|hi_buckets| hi_buckets.checked_mul(self.hi_bucket_len));
+        let lo_space = self
+            .supported_levels
+            .checked_sub(self.hi_levels)
+            .and_then(|lo_levels| lo_levels.checked_mul(self.buckets_per_lo_level))
+            .and_then(|lo_buckets| lo_buckets.checked_mul(self.lo_bucket_len));
+        let virt_space = hi_space
+            .zip(lo_space)
+            .and_then(|(hi_space, lo_space)| hi_space.checked_add(lo_space));
+        let rules = [
+            (
+                is_pow2(self.page_len) && 32 <= self.page_len,
+                "PAGE_LEN must be a power of two of at least 32 to hold full nodes",
+            ),
+            (
+                is_pow2(self.hi_bucket_len) && self.hi_bucket_len.is_multiple_of(self.page_len),
+                "HI_BUCKET_LEN must be a power of two and a multiple of PAGE_LEN",
+            ),
+            (
+                self.lo_bucket_len != 0 && self.lo_bucket_len.is_multiple_of(self.page_len),
+                "LO_BUCKET_LEN must be a multiple of PAGE_LEN",
+            ),
+            (
+                is_pow2(self.buckets_per_hi_level) && is_pow2(self.buckets_per_lo_level),
+                "Bucket counts must be powers of two",
+            ),
+            (
+                LEAF_LEVELS < self.supported_levels && self.supported_levels <= SUPPORTED_LEVELS,
+                "SUPPORTED_LEVELS must hold at least one interior level and at most SUPPORTED_LEVELS",
+            ),
+            (
+                self.hi_levels <= self.supported_levels,
+                "HI_LEVELS cannot exceed SUPPORTED_LEVELS",
+            ),
+            (
+                hi_level_space.is_some() && lo_level_space.is_some(),
+                "The virtual space of each level must be addressable by a u32",
+            ),
+            (
+                virt_space.is_some_and(|virt_space| virt_space < !0u32),
+                "The virtual space must be addressable by a u32",
+            ),
+            (
+                virt_space
+                    .and_then(|virt_space| virt_space.checked_div(self.page_len))
+                    .is_some_and(|total_pages| total_pages.is_multiple_of(128)),
+                "TOTAL_PAGES must be a multiple of 128",
+            ),
+        ];
+        match rules.iter().find(|(holds, _)| !holds) {
+            Some((_, rule)) => Err(Error::InvalidConfig((*rule).into())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Addressing
+impl HashTableConfig {
+    #[inline]
+    #[must_use]
+    pub fn new_bucket_len(&self, level: u32) -> u32 {
+        debug_assert!(level < self.supported_levels);
+        if level < self.hi_levels {
+            self.hi_bucket_len
+        } else {
+            self.lo_bucket_len
+        }
+    }
+    #[inline]
+    #[must_use]
+    pub fn buckets_per_level(&self, level: u32) -> u32 {
+        debug_assert!(level < self.supported_levels);
+        if level < self.hi_levels {
+            self.buckets_per_hi_level
+        } else {
+            self.buckets_per_lo_level
+        }
+    }
+    #[inline]
+    /// The supplied hash is bounded to the range [0-b[ with b := correct number of buckets at this level
+    #[must_use]
+    pub fn bucket_from_hash(&self, level: u32, hash: u32) -> u32 {
+        hash & (self.buckets_per_level(level) - 1)
+    }
+    #[must_use]
+    pub fn new_bucket_len_idx_u32(&self, level: u32, bucket: u32) -> u32 {
+        debug_assert!(level < self.supported_levels);
+        let idx = if level < self.hi_levels {
+            debug_assert!(bucket < self.buckets_per_hi_level);
+            level * self.buckets_per_hi_level
+        } else {
+            debug_assert!(bucket < self.buckets_per_lo_level);
+            self.total_hi_buckets() + (level - self.hi_levels) * self.buckets_per_lo_level
+        } + bucket;
+        debug_assert!(idx < self.total_buckets());
+        idx
+    }
+    #[inline]
+    #[must_use]
+    pub fn new_bucket_len_idx(&self, level: u32, bucket: u32) -> usize {
+        self.new_bucket_len_idx_u32(level, bucket) as usize
+    }
+    /// The home bucket of a leaf, hashed by the table's hasher.
+    #[inline]
+    #[must_use]
+    pub fn leaf_bucket(&self, leaf: &[u32]) -> u32 {
+        self.bucket_from_hash(self.leaf_level(), self.hasher.hash_leaf(leaf))
+    }
+    /// The home bucket of an interior node of `level`, hashed by the table's hasher.
+    #[inline]
+    #[must_use]
+    pub fn interior_bucket(&self, level: u32, interior: &[u32]) -> u32 {
+        self.bucket_from_hash(level, self.hasher.hash_interior(interior))
+    }
+    /// This returns the virtual pointer composed exclusively out of all three given parameters
+    pub fn new_vptr(&self, level: u32, bucket: u32, offset_bucket: u32) -> Result<u32> {
+        debug_assert!(level < self.supported_levels);
+        let error = Error::InvalidVptrParts {
+            level,
+            bucket,
+            offset: offset_bucket,
+        };
+        let idx = if level < self.hi_levels {
+            if self.hi_bucket_len <= offset_bucket {
+                return Err(error);
+            } else {
+                self.new_bucket_len_idx_u32(level, bucket) * self.hi_bucket_len
+            }
+        } else if self.lo_bucket_len <= offset_bucket || self.buckets_per_lo_level <= bucket {
+            return Err(error);
+        } else {
+            let lo = ((level - self.hi_levels) * self.buckets_per_lo_level + bucket)
+                * self.lo_bucket_len;
+            self.hi_virt_space() + lo
+        } + offset_bucket;
+        if idx < self.total_virt_space() {
+            Ok(idx)
+        } else {
+            Err(error)
+        }
+    }
+    #[inline]
+    #[must_use]
+    pub const fn vptr_to_lvl(&self, vptr: u32) -> u32 {
+        let hi_space = self.hi_virt_space();
+        let hi = vptr / (self.buckets_per_hi_level * self.hi_bucket_len);
+        let lo = vptr.overflowing_sub(hi_space).0
+            / (self.buckets_per_lo_level * self.lo_bucket_len)
+            + self.hi_levels;
+        let is_hi_lvl = (vptr < hi_space) as u32;
+        is_hi_lvl * hi + (1 - is_hi_lvl) * lo
+    }
+    #[inline]
+    const fn hi_virt_space(&self) -> u32 {
+        self.total_hi_buckets() * self.hi_bucket_len
+    }
+    /// Builds the full node of `level`, whose children are all the full node `child` one level below.
+    #[must_use]
+    pub fn full_interior(&self, level: u32, child: u32) -> [u32; 9] {
+        debug_assert!(
+            level < self.leaf_level(),
+            "Trying to add a full node below leaf level."
+        );
+        let mut interior: [u32; 9] = [0; 9];
+        interior[0] = 0xff;
+        if self.color_tree_levels() <= level {
+            let voxel_count = self.voxels_per_node(level);
+            debug_assert!(
+                voxel_count < (1 << 24),
+                "Voxel count overflow! Consider increasing COLOR_TREE_LEVELS."
+            );
+            interior[0] |= voxel_count << 8;
+        }
+        interior[1..9].copy_from_slice(&[child; 8]);
+        interior
+    }
+}
@@ -0,0 +1,96 @@
+use ::std::collections::HashMap;
+
+/// A short hash of every node in long buckets, so that searching one only compares the nodes whose fingerprint matches,
+/// instead of walking its pages node by node.
+///
+/// Host only. Each bucket is indexed up to the length it had when a node was last recorded into it,
+/// anything added beyond, e.g. through `ConcurrentHashDAG`, is scanned until the next node is recorded.
+#[derive(Debug, Default, Clone)]
+pub struct Fingerprints {
+    /// The indexed buckets, by their index in the free store.
+    buckets: HashMap<usize, BucketFingerprints>,
+}
+
+/// The nodes of a bucket in the order they were added, as their fingerprint and offset within the bucket.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BucketFingerprints {
+    fingerprints: Vec<u16>,
+    offsets: Vec<u32>,
+    /// The length of the bucket up to which every node is indexed.
+    indexed_len: u32,
+}
+
+impl Fingerprints {
+    /// The fingerprint of a node with `hash`. The low bits pick the bucket, so the high ones are used.
+    #[inline]
+    #[must_use]
+    pub const fn of(hash: u32) -> u16 {
+        (hash >> 16) as u16
+    }
+    /// The index of a bucket, if it has one.
+    #[inline]
+    #[must_use]
+    pub fn bucket(&self, bucket_len_idx: usize) -> Option<&BucketFingerprints> {
+        self.buckets.get(&bucket_len_idx)
+    }
+    /// The index of a bucket, which is created if `create` holds.
+    #[inline]
+    pub fn bucket_mut(
+        &mut self,
+        bucket_len_idx: usize,
+        create: bool,
+    ) -> Option<&mut BucketFingerprints> {
+        if create {
+            Some(self.buckets.entry(bucket_len_idx).or_default())
+        } else {
+            self.buckets.get_mut(&bucket_len_idx)
+        }
+    }
+    /// Forgets every bucket, e.g. once the table has been cleared.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+}
+
+impl BucketFingerprints {
+    /// The length of the bucket up to which every node is indexed.
+    #[inline]
+    #[must_use]
+    pub const fn indexed_len(&self) -> u32 {
+        self.indexed_len
+    }
+    /// Records a node with `fingerprint` at `offset`, which has to lie at or beyond `indexed_len`.
+    #[inline]
+    pub fn push(&mut self, fingerprint: u16, offset: u32) {
+        debug_assert!(
+            self.indexed_len <= offset,
+            "Recording a node within the indexed part."
+        );
+        self.fingerprints.push(fingerprint);
+        self.offsets.push(offset);
+    }
+    /// Marks the bucket as indexed up to `indexed_len`, once every node before has been pushed.
+    #[inline]
+    pub const fn set_indexed_len(&mut self, indexed_len: u32) {
+        self.indexed_len = indexed_len;
+    }
+    /// Forgets the nodes at or beyond `bucket_len`, e.g. once they are rolled back.
+    #[inline]
+    pub fn truncate(&mut self, bucket_len: u32) {
+        let kept = self.offsets.partition_point(|&offset| offset < bucket_len);
+        self.fingerprints.truncate(kept);
+        self.offsets.truncate(kept);
+        self.indexed_len = self.indexed_len.min(bucket_len);
+    }
+    /// The offsets below `bucket_len` at which a node with `fingerprint` may start.
+    #[inline]
+    pub fn candidates(&self, fingerprint: u16, bucket_len: u32) -> impl Iterator<Item = u32> + '_ {
+        self.fingerprints
+            .iter()
+            .zip(&self.offsets)
+            .take_while(move |&(_, &offset)| offset < bucket_len)
+            .filter(move |&(&other, _)| other == fingerprint)
+            .map(|(_, &offset)| offset)
+    }
+}
@@ -1 +1,6 @@
 pub mod basic;
+pub mod config;
+pub mod fingerprints;
+pub mod read_only;
+pub mod snapshot;
+pub mod storage;
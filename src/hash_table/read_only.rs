@@ -0,0 +1,72 @@
+use super::{
+    super::{view::HashDAGView, HashDAG, Result},
+    basic::HashTable,
+    config::HashTableConfig,
+    storage::Storage,
+};
+
+/// A `HashTable` attached to from elsewhere, e.g. by a renderer, which can only be read.
+///
+/// On unix every segment is mapped read-only, elsewhere nothing but `HashDAG` is exposed to write through.
+/// Nodes added by the creator show up as they are added; a grown pool requires attaching again.
+pub struct ReadOnlyHashTable<'shmem>(HashTable<'shmem>);
+
+impl ReadOnlyHashTable<'_> {
+    /// Attaches like `HashTable::attach`.
+    #[inline]
+    pub fn attach(root: &str) -> Result<Self> {
+        Self::attach_with_config(root, HashTableConfig::DEFAULT)
+    }
+    /// Attaches like `HashTable::attach_with_config`.
+    #[inline]
+    pub fn attach_with_config(root: &str, config: HashTableConfig) -> Result<Self> {
+        Ok(Self(HashTable::attach_in(
+            root,
+            config,
+            Storage::open_read_only,
+        )?))
+    }
+    #[inline]
+    pub fn full_node_ptr(&self, level: u32) -> Result<u32> {
+        self.0.full_node_ptr(level)
+    }
+    /// The number of allocated pages.
+    #[inline]
+    #[must_use]
+    pub fn hi(&self) -> u32 {
+        self.0.lut.hi()
+    }
+    /// Views the table, e.g. to validate or export it.
+    #[inline]
+    #[must_use]
+    pub fn view(&self) -> HashDAGView<'_> {
+        self.0.view()
+    }
+}
+
+impl HashDAG for ReadOnlyHashTable<'_> {
+    #[inline]
+    fn get(&self, vptr: u32) -> Result<u32> {
+        self.0.get(vptr)
+    }
+    #[inline]
+    fn leaf(&self, vptr: u32) -> Result<&[u32]> {
+        self.0.leaf(vptr)
+    }
+    #[inline]
+    fn interior(&self, vptr: u32) -> Result<&[u32]> {
+        self.0.interior(vptr)
+    }
+    #[inline]
+    fn dump(&self) -> (&[u32], &[u32]) {
+        self.0.dump()
+    }
+    #[inline]
+    fn config(&self) -> &HashTableConfig {
+        &self.0.config
+    }
+    #[inline]
+    fn full_node_pointers(&self) -> &[u32] {
+        &self.0.full_node_pointers
+    }
+}
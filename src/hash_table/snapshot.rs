@@ -0,0 +1,146 @@
+use super::{
+    super::{
+        constants::LEAF_LEVEL,
+        hashing::NodeHash,
+        utils::serialization::{
+            read_boxed_slice, read_exact_slice, read_size, read_word, write_size, write_slice,
+            write_word,
+        },
+        Error, Result,
+    },
+    basic::HashTable,
+    config::HashTableConfig,
+    storage::Backing,
+};
+use ::std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: [u8; 4] = *b"HDAG";
+/// Bump whenever the layout below changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Roots by name, so they can be found again after loading.
+pub type NamedRoots = Vec<(String, u32)>;
+
+impl HashTable<'_> {
+    /// Writes everything needed to continue working on the table, along with `roots`.
+    ///
+    /// Layout (little endian): magic, version, config, hasher as (kind, seed), pool capacity, `hi`,
+    /// allocated pages as (page, pool index), the free store, full node pointers, the allocated pool and roots.
+    pub fn save<P: AsRef<Path>>(&self, path: P, roots: &[(&str, u32)]) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&MAGIC)?;
+        write_word(&mut file, SNAPSHOT_VERSION)?;
+        let config = &self.config;
+        [
+            config.page_len,
+            config.supported_levels,
+            config.hi_levels,
+            config.hi_bucket_len,
+            config.lo_bucket_len,
+            config.buckets_per_hi_level,
+            config.buckets_per_lo_level,
+        ]
+        .iter()
+        .chain(&config.hasher.to_words())
+        .try_for_each(|&word| write_word(&mut file, word))?;
+        write_size(&mut file, self.pool.len())?;
+        let hi = self.lut.hi();
+        write_word(&mut file, hi)?;
+        for (page, &pool_idx) in self.lut.iter().enumerate().filter(|(_, &idx)| idx != !0) {
+            write_word(&mut file, page as _)?;
+            write_word(&mut file, pool_idx)?;
+        }
+        write_slice(&mut file, &self.bucket_len)?;
+        write_slice(&mut file, &self.full_node_pointers)?;
+        write_slice(&mut file, &self.pool[..(hi * config.page_len) as usize])?;
+        write_size(&mut file, roots.len())?;
+        for (name, vptr) in roots {
+            write_size(&mut file, name.len())?;
+            file.write_all(name.as_bytes())?;
+            write_word(&mut file, *vptr)?;
+        }
+        Ok(file.flush()?)
+    }
+    /// Reads a snapshot written by `save` into a new table, returning it along with its roots.
+    #[inline]
+    pub fn load<P: AsRef<Path>>(path: P, root: Option<&String>) -> Result<(Self, NamedRoots)> {
+        Self::load_in(path, Backing::Shared(root))
+    }
+    /// Like `load`, keeping the table in the given backing.
+    /// The loaded table is checked like an attached one, see `HashTable::check_geometry`.
+    pub fn load_in<P: AsRef<Path>>(path: P, backing: Backing) -> Result<(Self, NamedRoots)> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::Format("Not a HashTable snapshot.".into()));
+        }
+        let version = read_word(&mut file)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::Format(format!(
+                "Unsupported snapshot version {version}, expected {SNAPSHOT_VERSION}."
+            )));
+        }
+        let config = HashTableConfig {
+            page_len: read_word(&mut file)?,
+            supported_levels: read_word(&mut file)?,
+            hi_levels: read_word(&mut file)?,
+            hi_bucket_len: read_word(&mut file)?,
+            lo_bucket_len: read_word(&mut file)?,
+            buckets_per_hi_level: read_word(&mut file)?,
+            buckets_per_lo_level: read_word(&mut file)?,
+            hasher: NodeHash::from_words([read_word(&mut file)?, read_word(&mut file)?])?,
+        };
+        let mut hash_table = Self::blank_in(backing, read_size(&mut file)?, config)?;
+        let (hi, page_len) = (read_word(&mut file)?, config.page_len);
+        let mut lut = vec![!0; config.total_pages() as usize];
+        for _ in 0..hi {
+            let (page, pool_idx) = (read_word(&mut file)?, read_word(&mut file)?);
+            match lut.get_mut(page as usize) {
+                Some(entry) if pool_idx % page_len == 0 && pool_idx < hi * page_len => {
+                    *entry = pool_idx;
+                }
+                _ => {
+                    return Err(Error::Format(format!(
+                        "Invalid page table entry {page}: {pool_idx}"
+                    )))
+                }
+            }
+        }
+        let bucket_len: Box<[u32]> = read_boxed_slice(&mut file)?;
+        let full_node_pointers: Box<[u32]> = read_boxed_slice(&mut file)?;
+        let pool: Box<[u32]> = read_boxed_slice(&mut file)?;
+        if bucket_len.len() != config.total_buckets() as usize
+            || full_node_pointers.len() != LEAF_LEVEL as usize + 1
+            || pool.len() != (hi * page_len) as usize
+            || hash_table.pool.len() < pool.len()
+        {
+            return Err(Error::Format(
+                "Snapshot sections have invalid lengths.".into(),
+            ));
+        }
+        hash_table.lut.copy_from(&lut, hi);
+        hash_table.bucket_len.copy_from(0, &bucket_len);
+        hash_table
+            .full_node_pointers
+            .copy_from_slice(&full_node_pointers);
+        hash_table.pool.copy_from(0, &pool);
+        if hash_table.fingerprints.is_some() {
+            hash_table.set_fingerprinted(true)?;
+        }
+        let roots = (0..read_size(&mut file)?)
+            .map(|_| {
+                let len = read_size(&mut file)?;
+                let name = String::from_utf8(read_exact_slice(&mut file, len)?.into_vec())
+                    .map_err(|e| Error::Format(format!("Invalid root name: {e}")))?;
+                Ok((name, read_word(&mut file)?))
+            })
+            .collect::<Result<_>>()?;
+        hash_table.check_geometry()?;
+        Ok((hash_table, roots))
+    }
+}
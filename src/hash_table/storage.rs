@@ -0,0 +1,162 @@
+#[cfg(unix)]
+use super::super::utils::mmap::MmapArray;
+use super::super::{utils::shmem::ShmemArray, Error, Result};
+use ::std::{
+    ops::Deref,
+    path::Path,
+    pin::Pin,
+    slice::from_raw_parts,
+    sync::atomic::{self, AtomicU32},
+};
+
+/// Where a new `HashTable` keeps its pool, page table and free store.
+#[derive(Debug, Copy, Clone)]
+pub enum Backing<'root> {
+    /// Shared memory. Given a root, other processes can attach to it.
+    Shared(Option<&'root String>),
+    /// Memory of this process only, e.g. where shared memory cannot be created.
+    Heap,
+    /// Files in the given directory, mapped into memory so that the OS can page them in and out.
+    /// Every table needs its own directory, the files are removed along with the table.
+    #[cfg(unix)]
+    Mapped(&'root Path),
+}
+
+/// The words of a `HashTable`.
+pub enum Storage<'shmem> {
+    Shared(Pin<Box<ShmemArray<'shmem, u32>>>),
+    Heap(Box<[AtomicU32]>),
+    #[cfg(unix)]
+    Mapped(MmapArray),
+}
+
+impl Deref for Storage<'_> {
+    type Target = [u32];
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Shared(array) => array,
+            // SAFETY: `AtomicU32` has the same in-memory representation as `u32`.
+            Self::Heap(words) => unsafe { from_raw_parts(words.as_ptr().cast(), words.len()) },
+            #[cfg(unix)]
+            Self::Mapped(array) => array,
+        }
+    }
+}
+
+/// Clones into heap memory, whatever the original is backed by.
+impl Clone for Storage<'_> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::Heap(self.iter().map(|&word| AtomicU32::new(word)).collect())
+    }
+}
+
+impl Storage<'_> {
+    /// Zeroed words in the given backing. A shared root is suffixed by `name` to name the memory.
+    pub fn new(backing: Backing, name: &str, len: usize) -> Result<Self> {
+        Ok(match backing {
+            Backing::Shared(root) => {
+                let flink = root.map(|root| format!("{root}{name}.flink"));
+                Self::Shared(ShmemArray::new(len, flink)?)
+            }
+            Backing::Heap => Self::Heap((0..len).map(|_| AtomicU32::new(0)).collect()),
+            #[cfg(unix)]
+            Backing::Mapped(dir) => {
+                Self::Mapped(MmapArray::create(dir.join(format!("{name}.bin")), len)?)
+            }
+        })
+    }
+    /// Opens shared memory created elsewhere, see `ShmemArray::open`.
+    #[inline]
+    pub fn open<S: AsRef<Path>>(flink: S) -> Result<Self> {
+        Ok(Self::Shared(ShmemArray::open(flink)?))
+    }
+    /// Like `open`, see `ShmemArray::open_read_only`. Must never be written.
+    #[inline]
+    pub(crate) fn open_read_only<S: AsRef<Path>>(flink: S) -> Result<Self> {
+        Ok(Self::Shared(ShmemArray::open_read_only(flink)?))
+    }
+    #[inline]
+    pub fn copy_from(&mut self, offset: usize, slice: &[u32]) {
+        match self {
+            Self::Shared(array) => array.copy_from(offset, slice),
+            Self::Heap(words) => {
+                let words = &mut words[offset..offset + slice.len()];
+                for (word, &value) in words.iter_mut().zip(slice) {
+                    *word.get_mut() = value;
+                }
+            }
+            #[cfg(unix)]
+            Self::Mapped(array) => array.copy_from(offset, slice),
+        }
+    }
+    /// Copies through a shared reference, see `ShmemArray::copy_from_shared`.
+    ///
+    /// # Safety
+    ///
+    /// - No other thread may read or write `offset..offset + slice.len()` at the same time.
+    #[inline]
+    pub unsafe fn copy_from_shared(&self, offset: usize, slice: &[u32]) {
+        match self {
+            Self::Shared(array) => array.copy_from_shared(offset, slice),
+            Self::Heap(words) => {
+                for (word, &value) in words[offset..offset + slice.len()].iter().zip(slice) {
+                    word.store(value, atomic::Ordering::Relaxed);
+                }
+            }
+            #[cfg(unix)]
+            Self::Mapped(array) => array.copy_from_shared(offset, slice),
+        }
+    }
+    /// Views a word as an atomic, for words which are accessed by several threads.
+    #[inline]
+    #[must_use]
+    pub fn atomic(&self, idx: usize) -> &AtomicU32 {
+        match self {
+            Self::Shared(array) => array.atomic(idx),
+            Self::Heap(words) => &words[idx],
+            #[cfg(unix)]
+            Self::Mapped(array) => array.atomic(idx),
+        }
+    }
+    /// Resizes to `len` words, keeping at least the first `keep` words. See `ShmemArray::resize` for shared memory.
+    pub fn resize(&mut self, len: usize, keep: usize) -> Result<()> {
+        match self {
+            Self::Shared(array) => Ok(array.resize(len, keep)?),
+            Self::Heap(words) => {
+                let kept = words
+                    .iter()
+                    .take(keep)
+                    .map(|word| word.load(atomic::Ordering::Relaxed));
+                let zeroed = (keep..len).map(|_| 0);
+                *words = kept.chain(zeroed).map(AtomicU32::new).collect();
+                Ok(())
+            }
+            #[cfg(unix)]
+            Self::Mapped(array) => array.resize(len),
+        }
+    }
+    /// Whether this memory may be replaced, which only its creator may do.
+    #[inline]
+    #[must_use]
+    pub fn is_owner(&self) -> bool {
+        match self {
+            Self::Shared(array) => array.is_owner(),
+            Self::Heap(_) => true,
+            #[cfg(unix)]
+            Self::Mapped(_) => true,
+        }
+    }
+    /// Fails unless the memory holds `len` words, naming the memory by `what`.
+    pub fn expect_len(self, len: usize, what: &str) -> Result<Self> {
+        if self.len() == len {
+            Ok(self)
+        } else {
+            Err(Error::Format(format!(
+                "Expected {what} of {len} words, found {}.",
+                self.len()
+            )))
+        }
+    }
+}
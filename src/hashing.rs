@@ -0,0 +1,187 @@
+use super::{
+    utils::{as_leaf_mask, hash_interior, hash_leaf, murmur_hash_32, murmur_hash_64},
+    Error, Result,
+};
+use ::serde_derive::{Deserialize, Serialize};
+use ::std::num::Wrapping;
+
+/// Hashes nodes to pick their home bucket, see `HashTableConfig::bucket_from_hash`.
+/// Only the low bits end up being used, so those should be well distributed.
+pub trait NodeHasher {
+    fn hash_leaf(&self, leaf: &[u32]) -> u32;
+    fn hash_interior(&self, interior: &[u32]) -> u32;
+}
+
+/// A 64 bit murmur finalizer for leaves and murmur3 with `SEED` for interior nodes.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Murmur;
+
+/// Like `Murmur`, but seeded, e.g. to shuffle nodes which collide under the default seed.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Seeded(pub u32);
+
+/// Mixes every word like the tail of xxHash32.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct XxHash;
+
+/// FNV-1a over the little endian bytes of a node. Cheap, but its low bits are weak.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Fnv;
+
+impl NodeHasher for Murmur {
+    #[inline]
+    fn hash_leaf(&self, leaf: &[u32]) -> u32 {
+        hash_leaf(leaf)
+    }
+    #[inline]
+    fn hash_interior(&self, interior: &[u32]) -> u32 {
+        hash_interior(interior)
+    }
+}
+
+impl NodeHasher for Seeded {
+    #[inline]
+    fn hash_leaf(&self, leaf: &[u32]) -> u32 {
+        let seed = u64::from(self.0).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        murmur_hash_64(as_leaf_mask(leaf) ^ seed) as u32
+    }
+    #[inline]
+    fn hash_interior(&self, interior: &[u32]) -> u32 {
+        murmur_hash_32(self.0, interior)
+    }
+}
+
+impl NodeHasher for XxHash {
+    #[inline]
+    fn hash_leaf(&self, leaf: &[u32]) -> u32 {
+        xx_hash_32(&leaf[..2])
+    }
+    #[inline]
+    fn hash_interior(&self, interior: &[u32]) -> u32 {
+        xx_hash_32(interior)
+    }
+}
+
+impl NodeHasher for Fnv {
+    #[inline]
+    fn hash_leaf(&self, leaf: &[u32]) -> u32 {
+        fnv_1a(&leaf[..2])
+    }
+    #[inline]
+    fn hash_interior(&self, interior: &[u32]) -> u32 {
+        fnv_1a(interior)
+    }
+}
+
+/// The hasher of a table, kept in its `HashTableConfig`.
+/// Every process working on a table has to use the same one, or nodes end up in different buckets.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeHash {
+    #[default]
+    Murmur,
+    Seeded(u32),
+    XxHash,
+    Fnv,
+}
+
+impl NodeHasher for NodeHash {
+    #[inline]
+    fn hash_leaf(&self, leaf: &[u32]) -> u32 {
+        match *self {
+            Self::Murmur => Murmur.hash_leaf(leaf),
+            Self::Seeded(seed) => Seeded(seed).hash_leaf(leaf),
+            Self::XxHash => XxHash.hash_leaf(leaf),
+            Self::Fnv => Fnv.hash_leaf(leaf),
+        }
+    }
+    #[inline]
+    fn hash_interior(&self, interior: &[u32]) -> u32 {
+        match *self {
+            Self::Murmur => Murmur.hash_interior(interior),
+            Self::Seeded(seed) => Seeded(seed).hash_interior(interior),
+            Self::XxHash => XxHash.hash_interior(interior),
+            Self::Fnv => Fnv.hash_interior(interior),
+        }
+    }
+}
+
+impl NodeHash {
+    /// The name written to the shared memory config.
+    #[inline]
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Murmur => "murmur",
+            Self::Seeded(_) => "seeded",
+            Self::XxHash => "xxhash",
+            Self::Fnv => "fnv",
+        }
+    }
+    /// The seed of `Seeded`, 0 otherwise.
+    #[inline]
+    #[must_use]
+    pub const fn seed(self) -> u32 {
+        match self {
+            Self::Seeded(seed) => seed,
+            _ => 0,
+        }
+    }
+    /// Encodes as a kind and a seed, e.g. for snapshots.
+    #[inline]
+    #[must_use]
+    pub const fn to_words(self) -> [u32; 2] {
+        let kind = match self {
+            Self::Murmur => 0,
+            Self::Seeded(_) => 1,
+            Self::XxHash => 2,
+            Self::Fnv => 3,
+        };
+        [kind, self.seed()]
+    }
+    /// Decodes what `name` and `seed` describe, e.g. in the shared memory config.
+    pub fn from_name(name: &str, seed: u32) -> Result<Self> {
+        match name {
+            "murmur" => Ok(Self::Murmur),
+            "seeded" => Ok(Self::Seeded(seed)),
+            "xxhash" => Ok(Self::XxHash),
+            "fnv" => Ok(Self::Fnv),
+            _ => Err(Error::Format(format!("Unknown node hasher {name}."))),
+        }
+    }
+    /// Decodes what `to_words` wrote.
+    pub fn from_words([kind, seed]: [u32; 2]) -> Result<Self> {
+        match kind {
+            0 => Ok(Self::Murmur),
+            1 => Ok(Self::Seeded(seed)),
+            2 => Ok(Self::XxHash),
+            3 => Ok(Self::Fnv),
+            _ => Err(Error::Format(format!("Unknown node hasher {kind}."))),
+        }
+    }
+}
+
+fn xx_hash_32(node: &[u32]) -> u32 {
+    const PRIME_2: Wrapping<u32> = Wrapping(0x85eb_ca77);
+    const PRIME_3: Wrapping<u32> = Wrapping(0xc2b2_ae3d);
+    const PRIME_4: Wrapping<u32> = Wrapping(0x27d4_eb2f);
+    const PRIME_5: Wrapping<u32> = Wrapping(0x1656_67b1);
+    let mut h = PRIME_5 + Wrapping(4 * node.len() as u32);
+    for &k in node {
+        h += Wrapping(k) * PRIME_3;
+        h = Wrapping(h.0.rotate_left(17)) * PRIME_4;
+    }
+    h ^= h >> 15;
+    h *= PRIME_2;
+    h ^= h >> 13;
+    h *= PRIME_3;
+    h ^= h >> 16;
+    h.0
+}
+
+fn fnv_1a(node: &[u32]) -> u32 {
+    node.iter()
+        .flat_map(|word| word.to_le_bytes())
+        .fold(0x811c_9dc5, |h: u32, byte| {
+            (h ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
+}
@@ -1,18 +1,24 @@
+pub use error::Error;
 use {
-    constants::{LEAF_LEVEL, PAGE_LEN},
-    hash_table::basic::HashTable,
+    hash_table::{basic::HashTable, config::HashTableConfig},
     shared_hash_dag::SharedHashDAG,
+    std::collections::{btree_map::BTreeMap, hash_map::Entry, HashMap},
     tracking::Tracker,
-    utils::{bucket_from_hash, new_bucket_len, new_bucket_len_idx, new_vptr},
-    validation::{LevelInfo, Node},
+    validation::Node,
 };
 
 pub mod basic_dag;
+pub mod concurrent_hash_dag;
 pub mod constants;
 pub mod conversion;
 pub mod editing;
+pub mod error;
+pub mod garbage_collection;
 pub mod hash_table;
+pub mod hashing;
 pub mod prelude;
+pub mod query;
+pub mod rendering;
 pub mod reporting;
 pub mod shared_hash_dag;
 pub mod shmem_config;
@@ -20,11 +26,12 @@ pub mod staging;
 pub mod tracking;
 pub mod utils;
 pub mod validation;
+pub mod view;
 
 #[cfg(test)]
 mod tests;
 
-pub type Result<T> = std::result::Result<T, String>;
+pub type Result<T> = std::result::Result<T, Error>;
 
 pub trait HashDAG {
     /// Gets an item, usually this would be used for retrieving masks, but it retrieves anything.
@@ -35,6 +42,10 @@ pub trait HashDAG {
     fn interior(&self, vptr: u32) -> Result<&[u32]>;
     /// Gets a data dump of the pool and page table.
     fn dump(&self) -> (&[u32], &[u32]);
+    /// Gets the geometry of the table.
+    fn config(&self) -> &HashTableConfig;
+    /// Gets the full node of every level up to the leaf level, `!0` for levels without one.
+    fn full_node_pointers(&self) -> &[u32];
 }
 
 pub trait HashDAGMut {
@@ -46,6 +57,27 @@ pub trait HashDAGMut {
     fn add_leaf(&mut self, node: Node, hash: u32) -> Result<u32>;
     /// Adds a node without checking for duplicates.
     fn add_interior(&mut self, level: u32, node: Node, hash: u32) -> Result<u32>;
+    /// Like `find_or_add_leaf` for many leaves, returning their vptrs in the same order.
+    fn find_or_add_leaves(&mut self, nodes: &[Node]) -> Result<Vec<u32>> {
+        nodes
+            .iter()
+            .map(|&node| self.find_or_add_leaf(node))
+            .collect()
+    }
+    /// Like `find_or_add_interior` for many nodes of `level`, returning their vptrs in the same order.
+    fn find_or_add_interiors(&mut self, level: u32, nodes: &[Node]) -> Result<Vec<u32>> {
+        nodes
+            .iter()
+            .map(|&node| self.find_or_add_interior(level, node))
+            .collect()
+    }
+}
+
+pub trait HashDAGSync: Sync {
+    /// Like `HashDAGMut::find_or_add_leaf`, but callable from several threads at once.
+    fn find_or_add_leaf(&self, node: Node) -> Result<u32>;
+    /// Like `HashDAGMut::find_or_add_interior`, but callable from several threads at once.
+    fn find_or_add_interior(&self, level: u32, node: Node) -> Result<u32>;
 }
 
 // TODO Figure out a way to zero-cost refactor find_or_add_xx (maybe change node to cary more compile-time context)
@@ -70,109 +102,242 @@ impl HashDAG for HashTable<'_> {
     fn dump(&self) -> (&[u32], &[u32]) {
         (&self.pool, &self.lut)
     }
+    #[inline]
+    fn config(&self) -> &HashTableConfig {
+        &self.config
+    }
+    #[inline]
+    fn full_node_pointers(&self) -> &[u32] {
+        &self.full_node_pointers
+    }
 }
 
 impl<T: Tracker> HashDAGMut for SharedHashDAG<HashTable<'_>, T> {
     fn find_or_add_leaf(&mut self, node: Node) -> Result<u32> {
+        let config = self.config;
+        let leaf_level = config.leaf_level();
         let node = node.validated_as_leaf()?;
-        let hash = node.hash_as_leaf();
-        let bucket = bucket_from_hash(LEAF_LEVEL, hash);
-        let find = |bucket_len| self.find_leaf(bucket, bucket_len, *node);
-        let full_node_ptr = self.full_node_ptr(LEAF_LEVEL).unwrap();
-        Ok(if *node == self.leaf(full_node_ptr).unwrap() {
+        let hash = node.hash_as_leaf(&config.hasher);
+        let bucket = config.bucket_from_hash(leaf_level, hash);
+        let full_node_ptr = self.full_node_ptr(leaf_level)?;
+        Ok(if *node == self.leaf(full_node_ptr)? {
             full_node_ptr
-        } else if !self.is_allocated((new_vptr(LEAF_LEVEL, bucket, 0)? / PAGE_LEN) as _)? {
-            // TODO Lock on bucket
-            self.add_leaf(node, hash)?
-        } else if let Some(vptr) = find(self.bucket_len(LEAF_LEVEL, bucket))? {
+        } else if let Some(vptr) = self.probe_leaf(bucket, *node)? {
             vptr
         } else {
-            // TODO Lock on bucket
-            // Take a second peek after locking
-            if let Some(vptr) = find(self.bucket_len(LEAF_LEVEL, bucket))? {
-                vptr
-            } else {
-                self.add_leaf(node, hash)?
-            }
+            self.add_leaf(node, hash)?
         })
     }
     fn find_or_add_interior(&mut self, level: u32, node: Node) -> Result<u32> {
-        let node = node.validated_as_interior(&self.hash_dag, LevelInfo::new(level))?;
-        let hash = node.hash_as_interior();
-        let bucket = bucket_from_hash(level, hash);
-        let find = |bucket_len| self.find_interior(level, bucket, bucket_len, *node);
+        let config = self.config;
+        let node = node.validated_as_interior(&self.hash_dag, config.level_info(level))?;
+        let hash = node.hash_as_interior(&config.hasher);
+        let bucket = config.bucket_from_hash(level, hash);
         let full_node_ptr = self.full_node_ptr(level)?;
-        Ok(if *node == self.interior(full_node_ptr).unwrap() {
+        Ok(if *node == self.interior(full_node_ptr)? {
             full_node_ptr
-        } else if !self.is_allocated((new_vptr(level, bucket, 0)? / PAGE_LEN) as _)? {
-            // TODO Lock on bucket
-            self.add_interior(level, node, hash)?
-        } else if let Some(vptr) = find(self.bucket_len(level, bucket))? {
+        } else if let Some(vptr) = self.probe_interior(level, bucket, *node)? {
             vptr
         } else {
-            // TODO Lock on bucket
-            // Take a second peek after locking
-            if let Some(vptr) = find(self.bucket_len(level, bucket))? {
-                vptr
-            } else {
-                self.add_interior(level, node, hash)?
-            }
+            self.add_interior(level, node, hash)?
         })
     }
     #[inline]
     fn add_leaf(&mut self, node: Node, hash: u32) -> Result<u32> {
         let node = node.validated_as_leaf()?;
-        let bucket = bucket_from_hash(LEAF_LEVEL, hash);
-        let bucket_len_idx = new_bucket_len_idx(LEAF_LEVEL, bucket);
-        let bucket_len = self.bucket_len[bucket_len_idx];
-        let vptr = new_vptr(LEAF_LEVEL, bucket, bucket_len)?;
-        if bucket_len % PAGE_LEN == 0 {
-            self.allocate((vptr / PAGE_LEN) as _)?;
-        }
-        let pool_idx = self.pool_idx(vptr)?;
-        let range = pool_idx..pool_idx + 2;
-        self.pool_copy_from(range.start, *node);
-        self.bucket_len_add(bucket_len_idx, 2);
-        if self.bucket_len[bucket_len_idx] < new_bucket_len(LEAF_LEVEL) {
-            self.tracker.register(vptr, range)?;
-            Ok(vptr)
-        } else {
-            Err("Overflowing bucket on leaf level!".into())
-        }
+        let leaf_level = self.config.leaf_level();
+        self.add(
+            leaf_level,
+            self.config.bucket_from_hash(leaf_level, hash),
+            *node,
+        )
     }
     #[inline]
     fn add_interior(&mut self, level: u32, node: Node, hash: u32) -> Result<u32> {
-        let node = node.validated_as_interior(&self.hash_dag, LevelInfo::new(level))?;
-        let node_len = node.len() as u32;
-        let bucket = bucket_from_hash(level, hash);
-        let bucket_len_idx = new_bucket_len_idx(level, bucket);
-        let mut bucket_len = self.bucket_len[bucket_len_idx];
-        let vptr = {
-            let page_space_left = PAGE_LEN - bucket_len % PAGE_LEN;
-            let would_overflow = page_space_left < node_len;
-            if PAGE_LEN == page_space_left || would_overflow {
-                if would_overflow {
-                    bucket_len += page_space_left;
+        let node = node.validated_as_interior(&self.hash_dag, self.config.level_info(level))?;
+        self.add(level, self.config.bucket_from_hash(level, hash), *node)
+    }
+    fn find_or_add_leaves(&mut self, nodes: &[Node]) -> Result<Vec<u32>> {
+        let nodes = nodes
+            .iter()
+            .map(|node| node.validated_as_leaf())
+            .collect::<Result<Vec<_>>>()?;
+        self.find_or_add_batch(self.config.leaf_level(), &nodes)
+    }
+    fn find_or_add_interiors(&mut self, level: u32, nodes: &[Node]) -> Result<Vec<u32>> {
+        let level_info = self.config.level_info(level);
+        let nodes = nodes
+            .iter()
+            .map(|node| node.validated_as_interior(&self.hash_dag, level_info))
+            .collect::<Result<Vec<_>>>()?;
+        self.find_or_add_batch(level, &nodes)
+    }
+}
+
+/// Batches
+impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
+    /// Finds or adds validated nodes of `level`. Nodes sharing a home bucket search each probed bucket once.
+    /// Missing nodes are added in their given order, so the table ends up as if they were added one by one.
+    /// The pages they need are allocated at once beforehand, so nothing is added if the pool cannot hold them.
+    /// Adding runs as a transaction, so should any node fail, none of them is kept.
+    fn find_or_add_batch(&mut self, level: u32, nodes: &[Node]) -> Result<Vec<u32>> {
+        let full_node_ptr = self.full_node_ptr(level)?;
+        let full_node = if level == self.config.leaf_level() {
+            self.leaf(full_node_ptr)?
+        } else {
+            self.interior(full_node_ptr)?
+        }
+        .to_vec();
+        let mut vptrs = vec![!0; nodes.len()];
+        let mut firsts = HashMap::with_capacity(nodes.len());
+        let mut homes: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for (idx, node) in nodes.iter().enumerate() {
+            if **node == full_node.as_slice() {
+                vptrs[idx] = full_node_ptr;
+            } else if let Entry::Vacant(entry) = firsts.entry(&**node) {
+                entry.insert(idx);
+                let bucket = if level == self.config.leaf_level() {
+                    self.config.leaf_bucket(node)
+                } else {
+                    self.config.interior_bucket(level, node)
+                };
+                homes.entry(bucket).or_default().push(idx);
+            }
+        }
+        let mut missing = Vec::new();
+        for (&bucket, pending) in &mut homes {
+            self.search_batch(level, bucket, nodes, pending, &mut vptrs)?;
+            missing.extend(pending.iter().map(|&idx| (idx, bucket)));
+        }
+        missing.sort_unstable();
+        // Nodes are placed as if added one by one, so that their pages can be allocated at once.
+        let mut bucket_lens = HashMap::new();
+        let mut placements = Vec::with_capacity(missing.len());
+        let mut pages = Vec::new();
+        for (idx, home) in missing {
+            let node_len = nodes[idx].len() as u32;
+            let (bucket, placement) = self
+                .probe(level, home)
+                .find_map(|probed| {
+                    let bucket_len = *bucket_lens
+                        .entry(probed)
+                        .or_insert_with(|| self.bucket_len(level, probed));
+                    let placement = self.placement_after(level, probed, bucket_len, node_len);
+                    placement.ok().map(|placement| (probed, placement))
+                })
+                .ok_or(Error::BucketOverflow {
+                    level,
+                    bucket: home,
+                })?;
+            let (vptr, bucket_len, allocate) = placement;
+            bucket_lens.insert(bucket, bucket_len);
+            if allocate {
+                pages.push((vptr / self.config.page_len) as usize);
+            }
+            placements.push((idx, bucket, placement));
+        }
+        self.transaction(|dag| {
+            dag.allocate_all(&pages)?;
+            for (idx, bucket, placement) in placements {
+                vptrs[idx] = dag.write(level, bucket, &nodes[idx], placement)?;
+            }
+            Ok(())
+        })?;
+        for (idx, node) in nodes.iter().enumerate() {
+            if vptrs[idx] == !0 {
+                vptrs[idx] = vptrs[firsts[&**node]];
+            }
+        }
+        Ok(vptrs)
+    }
+    /// Probes for the `pending` nodes whose home is `bucket`, like `HashTable::probe_leaf` does for one.
+    /// Found nodes are removed from `pending`, leaving those which have to be added.
+    fn search_batch(
+        &self,
+        level: u32,
+        bucket: u32,
+        nodes: &[Node],
+        pending: &mut Vec<usize>,
+        vptrs: &mut [u32],
+    ) -> Result<()> {
+        let mut searched = Vec::with_capacity(pending.len());
+        for probed in self.probe(level, bucket) {
+            let bucket_len = self.bucket_len(level, probed);
+            if bucket_len != 0 {
+                let stored: HashMap<_, _> = self
+                    .bucket_nodes(level, probed, bucket_len)?
+                    .into_iter()
+                    .rev()
+                    .map(|(vptr, _, node)| (node, vptr))
+                    .collect();
+                pending.retain(|&idx| match stored.get(&*nodes[idx]) {
+                    Some(&vptr) => {
+                        vptrs[idx] = vptr;
+                        false
+                    }
+                    None => true,
+                });
+            }
+            // Probing ends for a node at the first bucket with room for it.
+            pending.retain(|&idx| {
+                let has_room = self.has_room(level, probed, nodes[idx].len() as _);
+                if has_room {
+                    searched.push(idx);
                 }
-                let vptr = new_vptr(level, bucket, bucket_len)?;
-                self.allocate((vptr / PAGE_LEN) as _)?;
-                vptr
-            } else {
-                let vptr = new_vptr(level, bucket, bucket_len)?;
-                debug_assert!(self.is_allocated((vptr / PAGE_LEN) as _)?);
-                vptr
+                !has_room
+            });
+            if pending.is_empty() {
+                break;
             }
-        };
+        }
+        pending.append(&mut searched);
+        Ok(())
+    }
+    /// Grows the pool at once, so that `pages` more pages fit. Does nothing unless the table is growable.
+    pub fn reserve(&mut self, pages: u32) -> Result<()> {
+        let (pool_len, max_len) = (self.pool.len(), self.config.total_virt_space() as usize);
+        let needed = ((self.lut.hi() + pages) * self.config.page_len) as usize;
+        if self.growable && pool_len < needed && pool_len < max_len {
+            self.grow(max_len.min(needed.max(2 * pool_len)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
+    #[inline]
+    /// Adds the node to the first bucket, starting at `bucket`, with room for it.
+    fn add(&mut self, level: u32, bucket: u32, node: &[u32]) -> Result<u32> {
+        let bucket = self.free_bucket(level, bucket, node.len() as _)?;
+        let placement = self.placement(level, bucket, node.len() as _)?;
+        if placement.2 {
+            self.allocate((placement.0 / self.config.page_len) as _)?;
+        }
+        self.write(level, bucket, node, placement)
+    }
+    /// Writes the node to the bucket at its `placement`, whose page has to be allocated.
+    fn write(
+        &mut self,
+        level: u32,
+        bucket: u32,
+        node: &[u32],
+        (vptr, bucket_len, _): (u32, u32, bool),
+    ) -> Result<u32> {
         let pool_idx = self.pool_idx(vptr)?;
         debug_assert!(pool_idx + node.len() < self.pool.len());
         let range = pool_idx..pool_idx + node.len();
-        self.pool_copy_from(range.start, *node);
-        self.bucket_len_copy_from(bucket_len_idx, &[bucket_len + node_len]);
-        if self.bucket_len[bucket_len_idx] < new_bucket_len(level) {
-            self.tracker.register(vptr, range)?;
-            Ok(vptr)
-        } else {
-            Err(format!("Overflowing bucket on level {level}!"))
+        self.pool_copy_from(range.start, node);
+        self.hash_dag
+            .record_fingerprints(level, bucket, bucket_len)?;
+        let bucket_len_idx = self.config.new_bucket_len_idx(level, bucket);
+        if let Some(journal) = &mut self.journal {
+            journal
+                .buckets
+                .push((level, bucket, self.hash_dag.bucket_len[bucket_len_idx]));
         }
+        self.bucket_len_copy_from(bucket_len_idx, &[bucket_len]);
+        self.tracker.register(vptr, range)?;
+        Ok(vptr)
     }
 }
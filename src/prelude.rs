@@ -1,4 +1,5 @@
 pub use super::{
-    basic_dag::{BasicDAG, OctVox},
+    basic_dag::{BasicDAG, BasicDAGHeader, OctVox},
+    error::Error,
     validation::Node::{self, Pass, Strict},
 };
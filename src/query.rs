@@ -0,0 +1,437 @@
+use super::{
+    editing::shapes::AABB,
+    utils::{bottom_child_mask, count_leaves, descend, upper_child_mask},
+    Error, HashDAG, Result,
+};
+use ::nalgebra::Vector3;
+
+/// Where a ray first hits the DAG, see `Query::raycast`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hit {
+    /// The node which was hit, in units of its own size, i.e. the voxel for hits at voxel level.
+    pub voxel: Vector3<u32>,
+    /// The level of the node which was hit, `HashTableConfig::supported_levels` for single voxels.
+    pub level: u32,
+    /// The distance along the ray, in multiples of its direction.
+    pub t: f64,
+    /// The face through which the ray entered, zero if it started inside the node.
+    pub normal: Vector3<i32>,
+}
+
+/// A node whose voxels are all set, see `Query::cubes`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Cube {
+    /// The first voxel of the node.
+    pub min: Vector3<u32>,
+    /// The voxels per axis.
+    pub size: u32,
+    /// The level of the node, `HashTableConfig::supported_levels` for single voxels.
+    pub level: u32,
+}
+
+impl Cube {
+    /// Every voxel of the cube, in the order of `utils::descend`.
+    pub fn voxels(self) -> impl Iterator<Item = Vector3<u32>> {
+        (0..self.voxel_count()).map(move |idx| self.voxel(idx))
+    }
+    /// The amount of voxels.
+    #[inline]
+    #[must_use]
+    pub const fn voxel_count(&self) -> u64 {
+        (self.size as u64).pow(3)
+    }
+    /// The voxel at `idx` in the order of `Cube::voxels`.
+    fn voxel(&self, idx: u64) -> Vector3<u32> {
+        let offset = (0..self.size.trailing_zeros())
+            .rev()
+            .fold(Vector3::zeros(), |offset, level| {
+                descend(&offset, ((idx >> (3 * level)) & 7) as u32)
+            });
+        self.min + offset
+    }
+}
+
+/// Looks up voxels in the DAG spanned by a node, e.g. for collisions or picking.
+/// Voxels are relative to the node, one unit each. Those outside of it are never set.
+pub trait Query: HashDAG {
+    /// Whether the voxel is set.
+    fn contains(&self, vptr: u32, voxel: &Vector3<u32>) -> Result<bool> {
+        let (level, supported_levels) = (self.level_of(vptr)?, self.config().supported_levels);
+        let depth = supported_levels - level;
+        if voxel.iter().any(|&v| v >> depth != 0) {
+            Ok(false)
+        } else {
+            Ok(self
+                .empty_level(vptr, level, voxel, supported_levels)?
+                .is_none())
+        }
+    }
+    /// Like `contains` for many voxels, answered in the same order.
+    /// Voxels sharing a node are looked up together, so each node is read once.
+    fn contains_all(&self, vptr: u32, voxels: &[Vector3<u32>]) -> Result<Vec<bool>> {
+        let config = self.config();
+        let leaf_level = config.leaf_level();
+        let level = self.level_of(vptr)?;
+        let depth = config.supported_levels - level;
+        let mut contained = vec![false; voxels.len()];
+        let inside = (0..voxels.len())
+            .filter(|&idx| voxels[idx].iter().all(|&v| v >> depth == 0))
+            .collect();
+        let mut items = vec![(level, vptr, inside)];
+        while let Some((level, vptr, indices)) = items.pop() {
+            if level == leaf_level {
+                let leaf = self.leaf(vptr)?;
+                for idx in indices {
+                    contained[idx] = leaf_contains(leaf, &voxels[idx]);
+                }
+                continue;
+            }
+            let interior = self.interior(vptr)?;
+            let mut groups: [Vec<usize>; 8] = Default::default();
+            for idx in indices {
+                let child = child_at(&voxels[idx], config.supported_levels - level - 1);
+                groups[child as usize].push(idx);
+            }
+            let child_mask = interior[0] as u8;
+            let children = (0..8).filter(|child| child_mask & (1 << child) != 0);
+            for (child, &vptr) in children.zip(&interior[1..]) {
+                let group = std::mem::take(&mut groups[child]);
+                if !group.is_empty() {
+                    items.push((level + 1, vptr, group));
+                }
+            }
+        }
+        Ok(contained)
+    }
+    /// Every set voxel, in the order of `utils::descend`, i.e. depth first with children by index.
+    fn voxels(&self, vptr: u32) -> Result<Voxels<'_, Self>> {
+        Ok(Voxels {
+            cubes: self.cubes(vptr)?,
+            cube: None,
+        })
+    }
+    /// Like `voxels`, but full nodes, as in `HashDAG::full_node_pointers`, are yielded as a whole instead of voxel by voxel.
+    /// Everything else is yielded as single voxels. A node which cannot be read is yielded as an error, which ends the iteration.
+    fn cubes(&self, vptr: u32) -> Result<Cubes<'_, Self>> {
+        let level = self.level_of(vptr)?;
+        Ok(Cubes {
+            dag: self,
+            nodes: vec![(level, vptr, Vector3::zeros())],
+            voxels: Vec::new(),
+        })
+    }
+    /// The amount of set voxels inside `aabb`, e.g. how much material an explosion there would remove.
+    /// Nodes entirely inside of it are counted by their stored voxel count, so only its boundary is descended.
+    fn count_in_aabb(&self, vptr: u32, aabb: &AABB) -> Result<u64> {
+        let config = self.config();
+        let (leaf_level, supported_levels) = (config.leaf_level(), config.supported_levels);
+        let mut count = 0;
+        let mut nodes = vec![(self.level_of(vptr)?, vptr, Vector3::<i64>::zeros())];
+        while let Some((level, vptr, min)) = nodes.pop() {
+            let size = 1i64 << (supported_levels - level);
+            let overlap =
+                Vector3::from_fn(|a, _| (min[a] + size).min(aabb.max[a]) - min[a].max(aabb.min[a]));
+            if overlap.iter().any(|&extent| extent <= 0) {
+                continue;
+            }
+            let inside = overlap == Vector3::repeat(size);
+            if self.full_node_pointers().get(level as usize) == Some(&vptr) {
+                count += overlap.product() as u64;
+            } else if level == leaf_level {
+                let leaf = self.leaf(vptr)?;
+                if inside {
+                    count += u64::from(count_leaves(leaf));
+                    continue;
+                }
+                let start = min.zip_map(&aabb.min, i64::max);
+                for x in start.x..start.x + overlap.x {
+                    for y in start.y..start.y + overlap.y {
+                        for z in start.z..start.z + overlap.z {
+                            let voxel = (Vector3::new(x, y, z) - min).map(|v| v as u32);
+                            count += u64::from(leaf_contains(leaf, &voxel));
+                        }
+                    }
+                }
+            } else if inside && config.level_info(level).is_color_tree_level {
+                count += u64::from(self.get(vptr)? >> 8);
+            } else {
+                let interior = self.interior(vptr)?;
+                let child_mask = interior[0] as u8;
+                let children = (0..8).filter(|child| child_mask & (1 << child) != 0);
+                for (child, &vptr) in children.zip(&interior[1..]) {
+                    let offset = descend(&Vector3::zeros(), child).map(i64::from) * (size / 2);
+                    nodes.push((level + 1, vptr, min + offset));
+                }
+            }
+        }
+        Ok(count)
+    }
+    /// Casts a ray from `origin` along `direction` against the voxels below `vptr`, up to `origin + max_t * direction`.
+    /// Coordinates are in voxels relative to the node, like those of `contains`.
+    #[inline]
+    fn raycast(
+        &self,
+        vptr: u32,
+        origin: &Vector3<f64>,
+        direction: &Vector3<f64>,
+        max_t: f64,
+    ) -> Result<Option<Hit>> {
+        let voxel_level = self.config().supported_levels;
+        self.raycast_lod(vptr, (origin, direction, max_t), voxel_level)
+    }
+    /// Like `raycast`, hitting any node of `level` instead of descending to single voxels.
+    /// Empty space is skipped a whole node at a time, at the level where it starts to be empty.
+    fn raycast_lod(
+        &self,
+        vptr: u32,
+        (origin, direction, max_t): (&Vector3<f64>, &Vector3<f64>, f64),
+        level: u32,
+    ) -> Result<Option<Hit>> {
+        let supported_levels = self.config().supported_levels;
+        let root_level = self.level_of(vptr)?;
+        if !(root_level..=supported_levels).contains(&level) {
+            return Err(Error::InvalidLevel(level));
+        }
+        let size = f64::from(1u32 << (supported_levels - root_level));
+        // Entering the node's bounds through the slab which is entered last.
+        let (mut t, mut t_exit, mut axis) = (0.0_f64, f64::INFINITY, None);
+        for a in 0..3 {
+            if direction[a] == 0.0 {
+                if !(0.0..size).contains(&origin[a]) {
+                    return Ok(None);
+                }
+                continue;
+            }
+            let (near, far) = if 0.0 < direction[a] {
+                (0.0, size)
+            } else {
+                (size, 0.0)
+            };
+            let (t_near, t_far) = (
+                (near - origin[a]) / direction[a],
+                (far - origin[a]) / direction[a],
+            );
+            if t < t_near {
+                (t, axis) = (t_near, Some(a));
+            }
+            t_exit = t_exit.min(t_far);
+        }
+        if t_exit <= t || max_t < t {
+            return Ok(None);
+        }
+        let cell = |a: usize, t: f64| {
+            let p = t.mul_add(direction[a], origin[a]);
+            let floor = p.floor();
+            (if direction[a] < 0.0 && floor == p {
+                floor - 1.0
+            } else {
+                floor
+            }) as i64
+        };
+        let bound = size as i64 - 1;
+        let mut voxel = Vector3::from_fn(|a, _| match axis {
+            Some(entered) if entered == a && 0.0 < direction[a] => 0,
+            Some(entered) if entered == a => bound,
+            _ => cell(a, t).clamp(0, bound),
+        });
+        loop {
+            let position = voxel.map(|v| v as u32);
+            let empty_level = self.empty_level(vptr, root_level, &position, level)?;
+            let Some(empty_level) = empty_level else {
+                let normal = Vector3::from_fn(|a, _| match axis {
+                    Some(entered) if entered == a => -direction[a].signum() as i32,
+                    _ => 0,
+                });
+                let voxel = position.map(|v| v >> (supported_levels - level));
+                return Ok(Some(Hit {
+                    voxel,
+                    level,
+                    t,
+                    normal,
+                }));
+            };
+            // Skips the empty node, leaving through the face which is reached first.
+            let size = 1i64 << (supported_levels - empty_level);
+            let min = voxel.map(|v| v & !(size - 1));
+            let (mut t_next, mut exit) = (f64::INFINITY, 0);
+            for a in (0..3).filter(|&a| direction[a] != 0.0) {
+                let face = if 0.0 < direction[a] {
+                    min[a] + size
+                } else {
+                    min[a]
+                };
+                let t_face = (face as f64 - origin[a]) / direction[a];
+                if t_face < t_next {
+                    (t_next, exit) = (t_face, a);
+                }
+            }
+            (t, axis) = (t_next.max(t), Some(exit));
+            voxel = Vector3::from_fn(|a, _| match a {
+                _ if a != exit => cell(a, t).clamp(min[a], min[a] + size - 1),
+                _ if 0.0 < direction[a] => min[a] + size,
+                _ => min[a] - 1,
+            });
+            if max_t < t || voxel.iter().any(|&v| !(0..=bound).contains(&v)) {
+                return Ok(None);
+            }
+        }
+    }
+    /// Descends towards `voxel` until reaching `level`, returning the level of the first missing node on the way.
+    fn empty_level(
+        &self,
+        vptr: u32,
+        root_level: u32,
+        voxel: &Vector3<u32>,
+        level: u32,
+    ) -> Result<Option<u32>> {
+        let config = self.config();
+        let (leaf_level, supported_levels) = (config.leaf_level(), config.supported_levels);
+        let mut vptr = vptr;
+        for current in root_level..level.min(leaf_level) {
+            let interior = self.interior(vptr)?;
+            let child = child_at(voxel, supported_levels - current - 1);
+            let child_mask = interior[0] as u8;
+            if child_mask & (1 << child) == 0 {
+                return Ok(Some(current + 1));
+            }
+            vptr = interior[1 + (child_mask & ((1 << child) - 1)).count_ones() as usize];
+        }
+        if level <= leaf_level {
+            return Ok(None);
+        }
+        let leaf = self.leaf(vptr)?;
+        let upper = child_at(voxel, 1);
+        Ok(if upper_child_mask(leaf) & (1 << upper) == 0 {
+            Some(leaf_level + 1)
+        } else if level == leaf_level + 1 {
+            None
+        } else if bottom_child_mask(leaf, upper) & (1 << child_at(voxel, 0)) == 0 {
+            Some(supported_levels)
+        } else {
+            None
+        })
+    }
+    /// The level of a node which can be queried, i.e. not below the leaf level.
+    fn level_of(&self, vptr: u32) -> Result<u32> {
+        let config = self.config();
+        let level = config.vptr_to_lvl(vptr);
+        if config.leaf_level() < level {
+            Err(Error::InvalidLevel(level))
+        } else {
+            Ok(level)
+        }
+    }
+}
+
+impl<DAG: HashDAG> Query for DAG {}
+
+/// See `Query::cubes`.
+#[derive(Debug)]
+pub struct Cubes<'a, DAG: ?Sized> {
+    dag: &'a DAG,
+    /// The nodes left to visit as their level, virtual pointer and first voxel, the next one last.
+    nodes: Vec<(u32, u32, Vector3<u32>)>,
+    /// The voxels left of the current leaf, the next one last.
+    voxels: Vec<Vector3<u32>>,
+}
+
+impl<DAG: HashDAG + ?Sized> Cubes<'_, DAG> {
+    /// Expands a node, returning it if it is full.
+    fn visit(&mut self, level: u32, vptr: u32, min: Vector3<u32>) -> Result<Option<Cube>> {
+        let config = self.dag.config();
+        let depth = config.supported_levels - level;
+        if self.dag.full_node_pointers().get(level as usize) == Some(&vptr) {
+            return Ok(Some(Cube {
+                min,
+                size: 1 << depth,
+                level,
+            }));
+        }
+        if level == config.leaf_level() {
+            let leaf = self.dag.leaf(vptr)?;
+            for upper in (0..8).rev() {
+                let bottom_mask = bottom_child_mask(leaf, upper);
+                for bottom in (0..8)
+                    .rev()
+                    .filter(|bottom| bottom_mask & (1 << bottom) != 0)
+                {
+                    let offset = descend(&descend(&Vector3::zeros(), upper), bottom);
+                    self.voxels.push(min + offset);
+                }
+            }
+        } else {
+            let interior = self.dag.interior(vptr)?;
+            let child_mask = interior[0] as u8;
+            let children = (0..8).rev().filter(|child| child_mask & (1 << child) != 0);
+            let half = 1 << (depth - 1);
+            for (child, &vptr) in children.zip(interior[1..].iter().rev()) {
+                let offset = descend(&Vector3::zeros(), child) * half;
+                self.nodes.push((level + 1, vptr, min + offset));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<DAG: HashDAG + ?Sized> Iterator for Cubes<'_, DAG> {
+    type Item = Result<Cube>;
+    fn next(&mut self) -> Option<Result<Cube>> {
+        loop {
+            if let Some(min) = self.voxels.pop() {
+                let level = self.dag.config().supported_levels;
+                return Some(Ok(Cube {
+                    min,
+                    size: 1,
+                    level,
+                }));
+            }
+            let (level, vptr, min) = self.nodes.pop()?;
+            match self.visit(level, vptr, min) {
+                Ok(Some(cube)) => return Some(Ok(cube)),
+                Ok(None) => {}
+                Err(error) => {
+                    // The nodes left are skipped, a partial result would pass for a complete one.
+                    self.nodes.clear();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// See `Query::voxels`.
+#[derive(Debug)]
+pub struct Voxels<'a, DAG: ?Sized> {
+    cubes: Cubes<'a, DAG>,
+    /// The cube being expanded and the index of its next voxel.
+    cube: Option<(Cube, u64)>,
+}
+
+impl<DAG: HashDAG + ?Sized> Iterator for Voxels<'_, DAG> {
+    type Item = Result<Vector3<u32>>;
+    fn next(&mut self) -> Option<Result<Vector3<u32>>> {
+        let (cube, idx) = match self.cube.take() {
+            Some((cube, idx)) if idx < cube.voxel_count() => (cube, idx),
+            _ => match self.cubes.next()? {
+                Ok(cube) => (cube, 0),
+                Err(error) => return Some(Err(error)),
+            },
+        };
+        self.cube = Some((cube, idx + 1));
+        Some(Ok(cube.voxel(idx)))
+    }
+}
+
+/// The child containing a voxel, picked by the bits at `shift` in the order of `utils::descend`.
+#[inline]
+fn child_at(voxel: &Vector3<u32>, shift: u32) -> u32 {
+    (((voxel.x >> shift) & 1) << 2) | (((voxel.y >> shift) & 1) << 1) | ((voxel.z >> shift) & 1)
+}
+
+/// Whether a leaf sets a voxel, of which only the lowest two bits per axis matter.
+#[inline]
+fn leaf_contains(leaf: &[u32], voxel: &Vector3<u32>) -> bool {
+    let (upper, bottom) = (child_at(voxel, 1), child_at(voxel, 0));
+    upper_child_mask(leaf) & (1 << upper) != 0
+        && bottom_child_mask(leaf, upper) & (1 << bottom) != 0
+}
@@ -0,0 +1,203 @@
+use super::{query::Query, utils::serialization::read_ron, Result};
+use ::{
+    nalgebra::Vector3,
+    serde_derive::{Deserialize, Serialize},
+    std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+    },
+};
+
+/// The viewer settings of `assets/user-pref.ron`, shared with the GPU renderer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserPreferences {
+    /// The budget of video memory in bytes.
+    pub vram: u64,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    /// The vertical field of view in radians.
+    pub fov: f64,
+    /// The lens aperture for depth of field. Pinhole cameras, as used by `Camera`, ignore it.
+    pub aperture: f64,
+    pub benchmark: bool,
+    /// Where the camera starts, in voxels relative to the center of the model.
+    pub start_pos_eye: (i32, i32, i32),
+    pub mov_sens_x: u32,
+    pub mov_sens_z: u32,
+    pub look_sens_y: f64,
+    pub look_sens_x: f64,
+    pub lock_to_xz: bool,
+    /// The dead zone of gamepad sticks.
+    pub deadzone: f64,
+    pub load_renderdoc: bool,
+    /// The index of the demo scene to load.
+    pub demo: u32,
+}
+
+impl UserPreferences {
+    /// Loads the preferences at `path`, e.g. `assets/user-pref.ron`.
+    #[inline]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        read_ron(path)
+    }
+}
+
+/// How hits are turned into pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shading {
+    /// Grayscale, from white at the nearest hit to almost black at the farthest. Misses are black.
+    Depth,
+    /// The normal of the face which was hit, each axis mapped from `-1..=1` to `0..=255`. Misses are black.
+    Normal,
+}
+
+/// A pinhole camera casting one ray through the center of each pixel, e.g. for reference images without a GPU.
+/// Coordinates are in voxels relative to the rendered node, like those of `Query::raycast`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub eye: Vector3<f64>,
+    /// Unit vectors spanning the view, `right` and `up` along the image rows and columns.
+    pub forward: Vector3<f64>,
+    pub right: Vector3<f64>,
+    pub up: Vector3<f64>,
+    /// The vertical field of view in radians.
+    pub fov: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Camera {
+    /// Looks from `eye` at `target`, keeping the positive y axis up. `target` must not be straight above or below `eye`.
+    #[must_use]
+    pub fn looking_at(
+        eye: Vector3<f64>,
+        target: &Vector3<f64>,
+        fov: f64,
+        (width, height): (u32, u32),
+    ) -> Self {
+        let forward = (target - eye).normalize();
+        let right = Vector3::y().cross(&forward).normalize();
+        let up = forward.cross(&right);
+        Self {
+            eye,
+            forward,
+            right,
+            up,
+            fov,
+            width,
+            height,
+        }
+    }
+    /// The start of the viewer, looking at the center of a node spanning `size` voxels per axis.
+    #[must_use]
+    pub fn from_preferences(preferences: &UserPreferences, size: u32) -> Self {
+        let center = Vector3::repeat(f64::from(size) / 2.0);
+        let (x, y, z) = preferences.start_pos_eye;
+        let mut eye = center + Vector3::new(x, y, z).map(f64::from);
+        if eye == center {
+            eye.z -= 1.0;
+        }
+        Self::looking_at(
+            eye,
+            &center,
+            preferences.fov,
+            (preferences.width, preferences.height),
+        )
+    }
+    /// The ray through the center of a pixel, rows going down from the top.
+    /// Its direction is scaled to advance one voxel along `forward`, so that hits are at their depth.
+    #[must_use]
+    pub fn ray(&self, x: u32, y: u32) -> (Vector3<f64>, Vector3<f64>) {
+        let half_height = (self.fov / 2.0).tan();
+        let half_width = half_height * f64::from(self.width) / f64::from(self.height);
+        let u = (2.0 * (f64::from(x) + 0.5) / f64::from(self.width) - 1.0) * half_width;
+        let v = (1.0 - 2.0 * (f64::from(y) + 0.5) / f64::from(self.height)) * half_height;
+        (self.eye, self.forward + u * self.right + v * self.up)
+    }
+    /// Ray casts every pixel against the voxels below `vptr`.
+    pub fn render<DAG: Query>(&self, dag: &DAG, vptr: u32, shading: Shading) -> Result<Image> {
+        let mut hits = Vec::with_capacity((self.width * self.height) as usize);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (origin, direction) = self.ray(x, y);
+                hits.push(dag.raycast(vptr, &origin, &direction, f64::INFINITY)?);
+            }
+        }
+        let pixels = match shading {
+            Shading::Depth => {
+                let depths = hits.iter().flatten().map(|hit| hit.t);
+                let near = depths.clone().fold(f64::INFINITY, f64::min);
+                let range = depths.fold(near, f64::max) - near;
+                hits.iter()
+                    .map(|hit| match hit {
+                        Some(hit) if 0.0 < range => (255.0 - 254.0 * (hit.t - near) / range) as u8,
+                        Some(_) => 255,
+                        None => 0,
+                    })
+                    .collect()
+            }
+            Shading::Normal => hits
+                .iter()
+                .flat_map(|hit| {
+                    hit.map_or([0; 3], |hit| {
+                        hit.normal.map(|n| ((n + 1) * 255 / 2) as u8).into()
+                    })
+                })
+                .collect(),
+        };
+        Ok(Image {
+            width: self.width,
+            height: self.height,
+            shading,
+            pixels,
+        })
+    }
+}
+
+/// A rendered image, one byte per pixel for `Shading::Depth` and three for `Shading::Normal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub shading: Shading,
+    /// Rows from top to bottom.
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// The bytes per pixel.
+    #[inline]
+    #[must_use]
+    pub const fn channels(&self) -> usize {
+        match self.shading {
+            Shading::Depth => 1,
+            Shading::Normal => 3,
+        }
+    }
+    /// The pixel in row `y` and column `x`.
+    #[inline]
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let start = (y * self.width + x) as usize * self.channels();
+        &self.pixels[start..start + self.channels()]
+    }
+    /// Writes a binary PGM for `Shading::Depth` or PPM for `Shading::Normal`.
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        let magic = match self.shading {
+            Shading::Depth => "P5",
+            Shading::Normal => "P6",
+        };
+        write!(out, "{magic}\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.pixels)?;
+        Ok(())
+    }
+    /// Writes the image to a file, see `Image::write`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
-use super::{constants::PAGE_LEN, hash_table::basic::HashTable};
-use ::std::mem::size_of;
+use super::{hash_table::basic::HashTable, hashing::NodeHasher, HashDAG, Result};
+use ::std::{collections::HashSet, mem::size_of};
 
 #[derive(Debug)]
 pub struct HashTableReport {
@@ -34,7 +34,7 @@ impl Reporter for HashTable<'_> {
     }
     #[inline]
     fn total_pages(&self) -> u32 {
-        self.pool.len() as u32 / PAGE_LEN
+        self.pool.len() as u32 / self.config.page_len
     }
     #[inline]
     fn allocated_pages(&self) -> u32 {
@@ -51,3 +51,75 @@ impl Reporter for HashTable<'_> {
         }
     }
 }
+
+/// How evenly a hasher spreads the nodes of one level over its buckets, counted in words.
+/// Page padding is ignored, so buckets close to their length may overflow a little earlier in practice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccupancyReport {
+    pub level: u32,
+    pub nodes: usize,
+    pub mean: f64,
+    pub variance: f64,
+    pub max: u32,
+    /// Buckets receiving more words than they hold, whose nodes would be probed into neighbouring buckets.
+    pub overflowing: usize,
+}
+
+impl HashTable<'_> {
+    /// Rehashes every node reachable from `roots` with `hasher` and reports the resulting occupancy of each level,
+    /// e.g. to compare hashers on real data before picking one for a new table.
+    pub fn occupancy<H: NodeHasher>(
+        &self,
+        roots: &[u32],
+        hasher: &H,
+    ) -> Result<Vec<OccupancyReport>> {
+        let config = &self.config;
+        let leaf_level = config.leaf_level();
+        let mut words: Vec<Vec<u32>> = (0..=leaf_level)
+            .map(|level| vec![0; config.buckets_per_level(level) as usize])
+            .collect();
+        let mut nodes = vec![0; leaf_level as usize + 1];
+        let mut visited = HashSet::new();
+        let mut items = roots.to_vec();
+        while let Some(vptr) = items.pop() {
+            if !visited.insert(vptr) {
+                continue;
+            }
+            let level = config.vptr_to_lvl(vptr);
+            let (node, hash) = if level == leaf_level {
+                let leaf = self.leaf(vptr)?;
+                (leaf, hasher.hash_leaf(leaf))
+            } else {
+                let interior = self.interior(vptr)?;
+                items.extend_from_slice(&interior[1..]);
+                (interior, hasher.hash_interior(interior))
+            };
+            let bucket = config.bucket_from_hash(level, hash);
+            words[level as usize][bucket as usize] += node.len() as u32;
+            nodes[level as usize] += 1;
+        }
+        Ok(words
+            .iter()
+            .zip(nodes)
+            .enumerate()
+            .map(|(level, (words, nodes))| {
+                let bucket_len = config.new_bucket_len(level as _);
+                let mean =
+                    words.iter().map(|&len| f64::from(len)).sum::<f64>() / words.len() as f64;
+                let variance = words
+                    .iter()
+                    .map(|&len| (f64::from(len) - mean).powi(2))
+                    .sum::<f64>()
+                    / words.len() as f64;
+                OccupancyReport {
+                    level: level as _,
+                    nodes,
+                    mean,
+                    variance,
+                    max: words.iter().copied().max().unwrap_or(0),
+                    overflowing: words.iter().filter(|&&len| bucket_len <= len).count(),
+                }
+            })
+            .collect())
+    }
+}
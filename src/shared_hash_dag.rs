@@ -1,17 +1,30 @@
 use super::{
-    constants::{COLOR_TREE_LEVELS, LEAF_LEVEL, PAGE_LEN, SUPPORTED_LEVELS},
-    hash_table::basic::HashTable,
+    hash_table::{
+        basic::HashTable, config::HashTableConfig, snapshot::NamedRoots, storage::Backing,
+    },
+    hashing::NodeHasher,
     tracking::Tracker,
-    utils::hash_interior,
     validation::Node::{self, Pass},
-    HashDAG, HashDAGMut, Result,
+    Error, HashDAG, HashDAGMut, Result,
 };
-use ::std::ops::Deref;
+use ::std::{ops::Deref, path::Path};
 
 /// `hash_dag` _must not_ implement any mutating trait. Incidentally invoking it would bypass the tracker.
+#[derive(Clone)]
 pub struct SharedHashDAG<DAG: HashDAG, T: Tracker> {
     pub hash_dag: DAG,
     pub tracker: T,
+    /// What the running transaction changed, see `SharedHashDAG::transaction`.
+    pub(crate) journal: Option<Journal>,
+}
+
+/// The changes made during a transaction, in order.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Journal {
+    /// Buckets a node has been added to, as (level, bucket, length before).
+    pub(crate) buckets: Vec<(u32, u32, u32)>,
+    /// Allocated pages. Each one raised the page table's `hi` by one.
+    pub(crate) pages: Vec<usize>,
 }
 
 impl<DAG: HashDAG, T: Tracker> Deref for SharedHashDAG<DAG, T> {
@@ -26,16 +39,101 @@ impl<DAG: HashDAG, T: Tracker> Deref for SharedHashDAG<DAG, T> {
 impl<T: Tracker + Default> SharedHashDAG<HashTable<'_>, T> {
     #[inline]
     pub fn blank(root: Option<&String>, capacity: usize, tracker: Option<T>) -> Result<Self> {
+        Self::blank_with_config(root, capacity, HashTableConfig::DEFAULT, tracker)
+    }
+    #[inline]
+    pub fn blank_with_config(
+        root: Option<&String>,
+        capacity: usize,
+        config: HashTableConfig,
+        tracker: Option<T>,
+    ) -> Result<Self> {
+        Self::blank_in(Backing::Shared(root), capacity, config, tracker)
+    }
+    #[inline]
+    pub fn blank_in(
+        backing: Backing,
+        capacity: usize,
+        config: HashTableConfig,
+        tracker: Option<T>,
+    ) -> Result<Self> {
         Ok(Self {
-            hash_dag: HashTable::blank(root, capacity)?,
-            tracker: tracker.unwrap_or_default(),
+            hash_dag: HashTable::blank_in(backing, capacity, config)?,
+            tracker: tracker.unwrap_or_else(|| T::for_config(&config)),
+            journal: None,
         })
     }
+    /// Attaches to a `HashTable` created elsewhere with the same `root`, for editing it.
+    /// Changes made through either side are visible to both.
+    ///
+    /// Nothing coordinates processes writing to the same table, so only one process may add nodes at a time,
+    /// including the one which created it. Others have to wait for it, e.g. by taking turns through a lock of their own,
+    /// as adding at once may place two nodes at the same offset of a bucket or allocate a page twice.
+    #[inline]
+    pub fn attach(root: &str, tracker: Option<T>) -> Result<Self> {
+        Self::attach_with_config(root, HashTableConfig::DEFAULT, tracker)
+    }
+    /// Like `attach`, for a table created with `config`.
+    #[inline]
+    pub fn attach_with_config(
+        root: &str,
+        config: HashTableConfig,
+        tracker: Option<T>,
+    ) -> Result<Self> {
+        Ok(Self {
+            hash_dag: HashTable::attach_with_config(root, config)?,
+            tracker: tracker.unwrap_or_else(|| T::for_config(&config)),
+            journal: None,
+        })
+    }
+    /// Loads a snapshot written by `HashTable::save`. Everything is registered as modified.
+    #[inline]
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        root: Option<&String>,
+        tracker: Option<T>,
+    ) -> Result<(Self, NamedRoots)> {
+        Self::load_in(path, Backing::Shared(root), tracker)
+    }
+    /// Like `load`, keeping the table in the given backing.
+    #[inline]
+    pub fn load_in<P: AsRef<Path>>(
+        path: P,
+        backing: Backing,
+        tracker: Option<T>,
+    ) -> Result<(Self, NamedRoots)> {
+        let (hash_dag, roots) = HashTable::load_in(path, backing)?;
+        let mut dag = Self {
+            tracker: tracker.unwrap_or_else(|| T::for_config(&hash_dag.config)),
+            hash_dag,
+            journal: None,
+        };
+        dag.tracker.register_all(dag.pool.len());
+        Ok((dag, roots))
+    }
     #[inline]
     pub fn with_capacity(root: Option<&String>, capacity: usize) -> Result<Self> {
-        let mut dag = Self::blank(root, capacity, None)?;
+        Self::with_config(root, capacity, HashTableConfig::DEFAULT)
+    }
+    /// Like `with_capacity`, for a table with the geometry of `config`.
+    #[inline]
+    pub fn with_config(
+        root: Option<&String>,
+        capacity: usize,
+        config: HashTableConfig,
+    ) -> Result<Self> {
+        Self::with_backing(Backing::Shared(root), capacity, config)
+    }
+    /// Like `with_config`, keeping the table in the given backing.
+    #[inline]
+    pub fn with_backing(
+        backing: Backing,
+        capacity: usize,
+        config: HashTableConfig,
+    ) -> Result<Self> {
+        let mut dag = Self::blank_in(backing, capacity, config, None)?;
         dag.add_full_leaf();
-        for level in (0..LEAF_LEVEL).rev() {
+        for level in (0..config.leaf_level()).rev() {
             dag.add_full_interior(level);
         }
         Ok(dag)
@@ -56,42 +154,123 @@ impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
     pub fn bucket_len_add(&mut self, offset: usize, increase: u32) {
         self.bucket_len_copy_from(offset, &[self.bucket_len[offset] + increase]);
     }
+    /// Allocates a page, growing the pool first if it is full and growable.
+    /// The page stays unallocated if the pool cannot hold it.
     #[inline]
     pub fn allocate(&mut self, page: usize) -> Result<()> {
-        self.hash_dag.lut.allocate(page);
-        if self.hash_dag.pool.len() < (self.hash_dag.lut.hi() * PAGE_LEN) as usize {
-            Err("No space is left to allocate! Consider resizing your pool.".into())
-        } else {
-            Ok(())
+        self.allocate_all(&[page])
+    }
+    /// Allocates `pages` at once, growing the pool first if they do not fit and it is growable.
+    /// Nothing is allocated if the pool cannot hold every page.
+    pub fn allocate_all(&mut self, pages: &[usize]) -> Result<()> {
+        debug_assert!(
+            pages
+                .iter()
+                .all(|&page| !self.is_allocated(page).expect("Page does not exist.")),
+            "Trying to allocate an allocated page."
+        );
+        if let Some(&page) = pages.first() {
+            self.check_pool(page as u32 * self.config.page_len)?;
+        }
+        self.reserve(pages.len() as u32)?;
+        let needed = ((self.lut.hi() + pages.len() as u32) * self.config.page_len) as usize;
+        if self.pool.len() < needed {
+            return Err(Error::PoolExhausted);
+        }
+        self.hash_dag.lut.allocate_all(pages);
+        if let Some(journal) = &mut self.journal {
+            journal.pages.extend_from_slice(pages);
+        }
+        Ok(())
+    }
+    /// Moves the pool into a larger segment, see `HashTable::grow`.
+    /// The tracker is notified, as the whole pool has to be staged again.
+    pub fn grow(&mut self, capacity: usize) -> Result<()> {
+        self.hash_dag.grow(capacity)?;
+        self.tracker.register_grown(self.pool.len());
+        Ok(())
+    }
+    /// Lets `allocate` grow the pool instead of failing with `PoolExhausted`.
+    #[inline]
+    pub const fn set_growable(&mut self, growable: bool) {
+        self.hash_dag.growable = growable;
+    }
+    /// Empties the table, including its full nodes. Nothing in the pool is overwritten.
+    /// This cannot be rolled back, so it must not be called within a transaction.
+    pub fn clear(&mut self) {
+        debug_assert!(self.journal.is_none(), "Clearing within a transaction.");
+        self.hash_dag.lut.clear();
+        self.bucket_len_copy_from(0, &vec![0; self.config.total_buckets() as _]);
+        self.hash_dag.full_node_pointers.fill(!0);
+        if let Some(fingerprints) = &mut self.hash_dag.fingerprints {
+            fingerprints.clear();
+        }
+        self.tracker.register_all(self.pool.len());
+    }
+}
+
+/// Transactions
+impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
+    /// Runs `f`, undoing every node it added if it fails: bucket lengths, allocated pages and, if the tracker
+    /// supports it, registrations are restored. A pool grown meanwhile stays grown, as does anything staged of it.
+    /// Words written past the restored bucket lengths remain in the pool, where they are never read.
+    ///
+    /// Nested transactions roll back only their own changes, without the tracker.
+    pub fn transaction<F, R>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        let (is_outermost, marks) = match &self.journal {
+            Some(journal) => (false, (journal.buckets.len(), journal.pages.len())),
+            None => {
+                self.journal = Some(Journal::default());
+                self.tracker.begin();
+                (true, (0, 0))
+            }
+        };
+        let result = f(self);
+        if result.is_err() {
+            self.rollback(marks);
+        }
+        if is_outermost {
+            self.journal = None;
+            match result {
+                Ok(_) => self.tracker.commit(),
+                Err(_) => self.tracker.rollback(),
+            }
         }
+        result
+    }
+    /// Undoes the journaled changes beyond `marks`, the latest first.
+    fn rollback(&mut self, (buckets, pages): (usize, usize)) {
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+        for (level, bucket, bucket_len) in journal.buckets.drain(buckets..).rev() {
+            self.hash_dag.forget_fingerprints(level, bucket, bucket_len);
+            let bucket_len_idx = self.config.new_bucket_len_idx(level, bucket);
+            self.bucket_len_copy_from(bucket_len_idx, &[bucket_len]);
+        }
+        self.hash_dag.lut.unallocate_last(&journal.pages[pages..]);
+        journal.pages.truncate(pages);
+        self.journal = Some(journal);
     }
 }
 
 /// Full nodes
 impl<T: Tracker> SharedHashDAG<HashTable<'_>, T> {
     pub fn add_full_leaf(&mut self) {
-        const LEVEL: usize = LEAF_LEVEL as _;
         const NODE: Node = Pass(&[!0u32, !0u32]);
-        let vptr = self.add_leaf(NODE, NODE.hash_as_leaf()).unwrap();
-        self.hash_dag.full_node_pointers[LEVEL] = vptr;
+        let vptr = self
+            .add_leaf(NODE, NODE.hash_as_leaf(&self.config.hasher))
+            .unwrap();
+        self.hash_dag.full_node_pointers[self.config.leaf_level() as usize] = vptr;
     }
     pub fn add_full_interior(&mut self, level: u32) {
-        debug_assert!(
-            level < LEAF_LEVEL,
-            "Trying to add a full node below leaf level."
-        );
-        let mut interior: [u32; 9] = [0; 9];
-        interior[0] = 0xff;
-        if COLOR_TREE_LEVELS <= level {
-            let voxel_count = 1 << (3 * (SUPPORTED_LEVELS - level));
-            debug_assert!(
-                voxel_count < (1 << 24),
-                "Voxel count overflow! Consider increasing COLOR_TREE_LEVELS."
-            );
-            interior[0] |= voxel_count << 8;
-        }
-        interior[1..9].copy_from_slice(&[self.full_node_pointers[level as usize + 1]; 8]);
-        let hash = hash_interior(&interior);
+        let interior = self
+            .config
+            .full_interior(level, self.full_node_pointers[level as usize + 1]);
+        let hash = self.config.hasher.hash_interior(&interior);
         self.hash_dag.full_node_pointers[level as usize] =
             self.add_interior(level, Pass(&interior), hash).unwrap();
     }
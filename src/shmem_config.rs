@@ -1,50 +1,79 @@
-use super::constants::{
-    BUCKETS_PER_HI_LEVEL, BUCKETS_PER_LO_LEVEL, COLOR_TREE_LEVELS, HI_BUCKET_LEN, HI_LEVELS,
-    HI_PAGES_PER_BUCKET, LEAF_LEVEL, LEAF_LEVELS, LO_BUCKET_LEN, LO_LEVELS, LO_PAGES_PER_BUCKET,
-    PAGE_LEN, SUPPORTED_LEVELS, TOTAL_BUCKETS, TOTAL_HI_BUCKETS, TOTAL_LO_BUCKETS, TOTAL_PAGES,
-    TOTAL_VIRT_SPACE,
+use super::{
+    constants::LEAF_LEVELS, hash_table::config::HashTableConfig, hashing::NodeHash, Error,
 };
 use std::{
-    fs::{remove_file, File},
+    collections::HashMap,
+    fs::{read_to_string, remove_file, File},
     io::{Result, Write},
 };
 
+/// Raised whenever keys are added or the shared memory changes its layout.
+///
+/// Version 1 added `HASHER` and `HASH_SEED`, and the pool length after `hi` in the page table.
 #[allow(dead_code)]
-pub const VERSION: u32 = 0;
+pub const VERSION: u32 = 1;
 
 #[derive(Debug)]
 pub struct ShmemConfig {
     pub path: String,
     pub class: String,
+    /// The geometry of the shared table.
+    pub config: HashTableConfig,
 }
 
 impl ShmemConfig {
     pub fn write(self) -> Result<Self> {
         let mut file = File::create(self.path.as_str())?;
+        let config = &self.config;
 
         let bytes = [
             b"{",
             format!("\"{}\":{},", "VERSION", VERSION).as_bytes(),
             format!("\"{}\":\"{}\",", "CLASS", self.class).as_bytes(),
-            format!("\"{}\":{},", "PAGE_LEN", PAGE_LEN).as_bytes(),
-            format!("\"{}\":{},", "SUPPORTED_LEVELS", SUPPORTED_LEVELS).as_bytes(),
-            format!("\"{}\":{},", "COLOR_TREE_LEVELS", COLOR_TREE_LEVELS).as_bytes(),
+            format!("\"{}\":{},", "PAGE_LEN", config.page_len).as_bytes(),
+            format!("\"{}\":{},", "SUPPORTED_LEVELS", config.supported_levels).as_bytes(),
+            format!(
+                "\"{}\":{},",
+                "COLOR_TREE_LEVELS",
+                config.color_tree_levels()
+            )
+            .as_bytes(),
             format!("\"{}\":{},", "LEAF_LEVELS", LEAF_LEVELS).as_bytes(),
-            format!("\"{}\":{},", "LEAF_LEVEL", LEAF_LEVEL).as_bytes(),
-            format!("\"{}\":{},", "HI_BUCKET_LEN", HI_BUCKET_LEN).as_bytes(),
-            format!("\"{}\":{},", "LO_BUCKET_LEN", LO_BUCKET_LEN).as_bytes(),
-            format!("\"{}\":{},", "HI_PAGES_PER_BUCKET", HI_PAGES_PER_BUCKET).as_bytes(),
-            format!("\"{}\":{},", "LO_PAGES_PER_BUCKET", LO_PAGES_PER_BUCKET).as_bytes(),
-            format!("\"{}\":{},", "BUCKETS_PER_HI_LEVEL", BUCKETS_PER_HI_LEVEL).as_bytes(),
-            format!("\"{}\":{},", "BUCKETS_PER_LO_LEVEL", BUCKETS_PER_LO_LEVEL).as_bytes(),
-            format!("\"{}\":{},", "HI_LEVELS", HI_LEVELS).as_bytes(),
-            format!("\"{}\":{},", "LO_LEVELS", LO_LEVELS).as_bytes(),
-            format!("\"{}\":{},", "TOTAL_HI_BUCKETS", TOTAL_HI_BUCKETS).as_bytes(),
-            format!("\"{}\":{},", "TOTAL_LO_BUCKETS", TOTAL_LO_BUCKETS).as_bytes(),
-            format!("\"{}\":{},", "TOTAL_BUCKETS", TOTAL_BUCKETS).as_bytes(),
-            format!("\"{}\":{},", "TOTAL_PAGES", TOTAL_PAGES).as_bytes(),
+            format!("\"{}\":{},", "LEAF_LEVEL", config.leaf_level()).as_bytes(),
+            format!("\"{}\":{},", "HI_BUCKET_LEN", config.hi_bucket_len).as_bytes(),
+            format!("\"{}\":{},", "LO_BUCKET_LEN", config.lo_bucket_len).as_bytes(),
+            format!(
+                "\"{}\":{},",
+                "HI_PAGES_PER_BUCKET",
+                config.hi_pages_per_bucket()
+            )
+            .as_bytes(),
+            format!(
+                "\"{}\":{},",
+                "LO_PAGES_PER_BUCKET",
+                config.lo_pages_per_bucket()
+            )
+            .as_bytes(),
+            format!(
+                "\"{}\":{},",
+                "BUCKETS_PER_HI_LEVEL", config.buckets_per_hi_level
+            )
+            .as_bytes(),
+            format!(
+                "\"{}\":{},",
+                "BUCKETS_PER_LO_LEVEL", config.buckets_per_lo_level
+            )
+            .as_bytes(),
+            format!("\"{}\":{},", "HI_LEVELS", config.hi_levels).as_bytes(),
+            format!("\"{}\":{},", "LO_LEVELS", config.lo_levels()).as_bytes(),
+            format!("\"{}\":{},", "TOTAL_HI_BUCKETS", config.total_hi_buckets()).as_bytes(),
+            format!("\"{}\":{},", "TOTAL_LO_BUCKETS", config.total_lo_buckets()).as_bytes(),
+            format!("\"{}\":{},", "TOTAL_BUCKETS", config.total_buckets()).as_bytes(),
+            format!("\"{}\":{},", "TOTAL_PAGES", config.total_pages()).as_bytes(),
+            format!("\"{}\":\"{}\",", "HASHER", config.hasher.name()).as_bytes(),
+            format!("\"{}\":{},", "HASH_SEED", config.hasher.seed()).as_bytes(),
             // last one has no comma
-            format!("\"{}\":{}", "TOTAL_VIRT_SPACE", TOTAL_VIRT_SPACE).as_bytes(),
+            format!("\"{}\":{}", "TOTAL_VIRT_SPACE", config.total_virt_space()).as_bytes(),
             b"}",
         ]
         .concat();
@@ -53,6 +82,71 @@ impl ShmemConfig {
     }
 }
 
+/// Reading
+impl ShmemConfig {
+    /// Reads the geometry written by `write`, e.g. to attach to the table it describes.
+    /// Every derived constant has to agree with the geometry, or the file was written by a different version.
+    pub fn read(path: &str) -> super::Result<HashTableConfig> {
+        let json = read_to_string(path)?;
+        let entries: HashMap<String, ron::Value> = ron::from_str(&json)
+            .map_err(|e| Error::Format(format!("Failed to parse {path}, reason: {e}")))?;
+        let entry = |name: &str| {
+            entries
+                .get(name)
+                .ok_or_else(|| Error::Format(format!("{path} misses {name}.")))
+        };
+        let number = |name: &str| match entry(name)? {
+            ron::Value::Number(number) => number
+                .as_i64()
+                .and_then(|number| u32::try_from(number).ok())
+                .ok_or_else(|| Error::Format(format!("{name} of {path} is not a u32."))),
+            _ => Err(Error::Format(format!("{name} of {path} is not a number."))),
+        };
+        if number("VERSION")? != VERSION {
+            return Err(Error::Format(format!(
+                "{path} is not of version {VERSION}."
+            )));
+        }
+        let hasher = match entry("HASHER")? {
+            ron::Value::String(name) => NodeHash::from_name(name, number("HASH_SEED")?)?,
+            _ => return Err(Error::Format(format!("HASHER of {path} is not a string."))),
+        };
+        let config = HashTableConfig {
+            page_len: number("PAGE_LEN")?,
+            supported_levels: number("SUPPORTED_LEVELS")?,
+            hi_levels: number("HI_LEVELS")?,
+            hi_bucket_len: number("HI_BUCKET_LEN")?,
+            lo_bucket_len: number("LO_BUCKET_LEN")?,
+            buckets_per_hi_level: number("BUCKETS_PER_HI_LEVEL")?,
+            buckets_per_lo_level: number("BUCKETS_PER_LO_LEVEL")?,
+            hasher,
+        };
+        config.validate()?;
+        let derived = [
+            ("COLOR_TREE_LEVELS", config.color_tree_levels()),
+            ("LEAF_LEVELS", LEAF_LEVELS),
+            ("LEAF_LEVEL", config.leaf_level()),
+            ("HI_PAGES_PER_BUCKET", config.hi_pages_per_bucket()),
+            ("LO_PAGES_PER_BUCKET", config.lo_pages_per_bucket()),
+            ("LO_LEVELS", config.lo_levels()),
+            ("TOTAL_HI_BUCKETS", config.total_hi_buckets()),
+            ("TOTAL_LO_BUCKETS", config.total_lo_buckets()),
+            ("TOTAL_BUCKETS", config.total_buckets()),
+            ("TOTAL_PAGES", config.total_pages()),
+            ("TOTAL_VIRT_SPACE", config.total_virt_space()),
+        ];
+        for (name, expected) in derived {
+            let found = number(name)?;
+            if found != expected {
+                return Err(Error::InvalidConfig(format!(
+                    "{name} is {found}, but the geometry implies {expected}"
+                )));
+            }
+        }
+        Ok(config)
+    }
+}
+
 impl Drop for ShmemConfig {
     #[inline]
     fn drop(&mut self) {
@@ -27,16 +27,20 @@ impl Staging for BasicHashDAG<'_> {
         WP: FnMut(Range<usize>, Range<usize>),
         WL: FnMut(Range<usize>, Range<usize>),
     {
+        let (pool_mask_bit_len, lut_mask_bit_len) = (
+            self.tracker.pool_mask_bit_len,
+            self.tracker.lut_mask_bit_len,
+        );
         // Page table (check each bit in each byte)
         {
             let (mut src_idx, mut dst_idx, mut len) = (0, 0, 0);
             for &mask in &self.tracker.page_table_mask.to_le_bytes() {
                 for shift in 0..8 {
                     if mask & (1 << shift) != 0 {
-                        len += LUT_MASK_BIT_LEN;
+                        len += lut_mask_bit_len;
                     } else {
                         write_if_end!(src_idx, dst_idx, len, write_lut);
-                        dst_idx += LUT_MASK_BIT_LEN;
+                        dst_idx += lut_mask_bit_len;
                     }
                 }
             }
@@ -50,18 +54,18 @@ impl Staging for BasicHashDAG<'_> {
             // SAFETY: (in HashTable) the pool length is a multiple of 128 pages and each bit is a page.
             for mask in unsafe { self.tracker.pool_mask.align_to::<u128>() }.1 {
                 if *mask == !0 {
-                    len += 128 * POOL_MASK_BIT_LEN;
+                    len += 128 * pool_mask_bit_len;
                 } else if *mask == 0 {
                     write_if_end!(src_idx, dst_idx, len, write_pool);
-                    dst_idx += 128 * POOL_MASK_BIT_LEN;
+                    dst_idx += 128 * pool_mask_bit_len;
                 } else {
                     for &mask in &mask.to_le_bytes() {
                         for shift in 0..8 {
                             if mask & (1 << shift) != 0 {
-                                len += POOL_MASK_BIT_LEN;
+                                len += pool_mask_bit_len;
                             } else {
                                 write_if_end!(src_idx, dst_idx, len, write_pool);
-                                dst_idx += POOL_MASK_BIT_LEN;
+                                dst_idx += pool_mask_bit_len;
                             }
                         }
                     }
@@ -80,7 +84,16 @@ impl BasicHashDAG<'_> {
     pub fn staging_specs(&self) -> StagingCache {
         // SAFETY: `TOTAL_PAGES` is a multiple of 128 bits.
         // SAFETY: (in HashTable) the pool length is a multiple of 128 pages and each bit is a page.
-        unsafe { StagingCache::new(&self.tracker.pool_mask, self.tracker.page_table_mask) }
+        unsafe {
+            StagingCache::with_granularity(
+                &self.tracker.pool_mask,
+                self.tracker.page_table_mask,
+                (
+                    self.tracker.pool_mask_bit_len,
+                    self.tracker.lut_mask_bit_len,
+                ),
+            )
+        }
     }
 }
 
@@ -100,12 +113,31 @@ impl StagingCache {
     /// Not following this assumption leads to UB.
     #[must_use]
     pub unsafe fn new(pool_mask: &[PoolMask], page_table_mask: PageTableMask) -> Self {
+        Self::with_granularity(
+            pool_mask,
+            page_table_mask,
+            (POOL_MASK_BIT_LEN, LUT_MASK_BIT_LEN),
+        )
+    }
+    #[inline]
+    /// Like `new`, for masks whose bits represent `pool_mask_bit_len` words and `lut_mask_bit_len` pages.
+    ///
+    /// # Safety
+    ///
+    /// It is assumed that the pool length is a multiple of 128 pages.
+    /// Not following this assumption leads to UB.
+    #[must_use]
+    pub unsafe fn with_granularity(
+        pool_mask: &[PoolMask],
+        page_table_mask: PageTableMask,
+        (pool_mask_bit_len, lut_mask_bit_len): (usize, usize),
+    ) -> Self {
         let iter = pool_mask.align_to::<u128>().1.iter();
         let pool_set = iter.map(|mask| mask.count_ones()).sum::<u32>() as usize;
         let page_table_set = page_table_mask.count_ones() as usize;
         Self {
-            pool_items: pool_set * POOL_MASK_BIT_LEN,
-            pages: LUT_MASK_BIT_LEN * page_table_set,
+            pool_items: pool_set * pool_mask_bit_len,
+            pages: lut_mask_bit_len * page_table_set,
         }
     }
     #[inline]
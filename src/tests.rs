@@ -1,21 +1,37 @@
 #![allow(unused_imports, dead_code)]
+#[cfg(unix)]
+use super::utils::mmap::MmapArray;
 use super::{
+    basic_dag::HEADER_LEN,
+    concurrent_hash_dag::ConcurrentHashDAG,
     constants::{
         SparseVoxelsSegmented, SparseVoxelsSequential, BUCKETS_PER_HI_LEVEL, COLOR_TREE_LEVELS,
-        HI_BUCKET_LEN, HI_LEVELS, LEAF_LEVEL, PAGE_LEN, SUPPORTED_LEVELS, TOTAL_PAGES,
-        TOTAL_VIRT_SPACE,
+        HI_BUCKET_LEN, HI_LEVELS, LEAF_LEVEL, PAGE_LEN, SUPPORTED_LEVELS, TOTAL_BUCKETS,
+        TOTAL_PAGES, TOTAL_VIRT_SPACE,
     },
     conversion::Converter,
     editing::{
+        boolean::Combiner,
+        history::History,
         inner::interior_from,
         shapes::{Sphere, AABB},
         Editor, Operation,
         Operation::{Link, Unlink},
         Shape,
     },
-    hash_table::basic::HashTable,
+    garbage_collection::Collector,
+    hash_table::{
+        basic::HashTable,
+        config::HashTableConfig,
+        read_only::ReadOnlyHashTable,
+        storage::{Backing, Storage},
+    },
+    hashing::{Fnv, Murmur, NodeHash, NodeHasher, Seeded, XxHash},
     prelude::*,
+    query::{Cube, Hit, Query},
+    rendering::{Camera, Image, Shading, UserPreferences},
     shared_hash_dag::SharedHashDAG,
+    shmem_config::{ShmemConfig, VERSION},
     staging::Staging,
     tracking::{
         basic::{BasicHashDAG, POOL_MASK_BITS, POOL_MASK_BIT_LEN},
@@ -25,19 +41,22 @@ use super::{
     utils::{
         bucket_from_hash, hash_interior, hash_leaf, new_bucket_len_idx, new_vptr,
         serialization::{load_ron, read_exact_slice, read_word},
-        vptr_to_lvl,
+        shmem::ShmemArray,
+        vptr_to_lvl, XorShift,
     },
     validation::{Validation::Valid, Validator},
-    HashDAG, HashDAGMut, Result,
+    view::HashDAGView,
+    HashDAG, HashDAGMut, HashDAGSync, Result,
 };
 use utils::{
-    add_lantern, basic_blank, basic_with_capacity, full_dag, host_only_blank,
-    host_only_with_capacity, import_matches, stage, verify_full_interior, verify_full_leaf,
+    add_lantern, basic_blank, basic_with_capacity, carve, carved, full_dag, host_only_blank,
+    host_only_with_capacity, import_matches, leaves, same_structure, sphere, stage,
+    verify_full_interior, verify_full_leaf, CROWDED, SPHERES,
 };
 use ::{
     nalgebra::Vector3,
     num_traits::identities::Zero,
-    std::{cmp::Ordering, fs::File, io::Read, path::Path},
+    std::{cmp::Ordering, fs::File, io::Read, path::Path, thread},
 };
 
 const SVDAG_STORE: &str = ".local/svdags/";
@@ -143,6 +162,75 @@ mod utils {
         }
     }
 
+    /// Compares two sub-DAGs (possibly in different tables) node by node.
+    pub fn same_structure<A: HashDAG, B: HashDAG>(a: (&A, u32), b: (&B, u32)) -> bool {
+        let mut visited = std::collections::HashSet::new();
+        let mut items = vec![(a.1, b.1)];
+        while let Some((left, right)) = items.pop() {
+            let level = vptr_to_lvl(left);
+            if !visited.insert((left, right)) {
+                continue;
+            } else if level != vptr_to_lvl(right) {
+                return false;
+            } else if level == LEAF_LEVEL {
+                if a.0.leaf(left).unwrap() != b.0.leaf(right).unwrap() {
+                    return false;
+                }
+            } else {
+                let (left, right) = (a.0.interior(left).unwrap(), b.0.interior(right).unwrap());
+                if left.len() != right.len() || left[0] != right[0] {
+                    return false;
+                }
+                items.extend(left.iter().copied().zip(right.iter().copied()).skip(1));
+            }
+        }
+        true
+    }
+
+    /// Distinct leaves, the same ones every time. None of them is full.
+    pub fn leaves(count: u32) -> Vec<[u32; 2]> {
+        (1..=count)
+            .map(|i| [i, i.wrapping_mul(0x9e37_79b9)])
+            .collect()
+    }
+
+    /// Few buckets, so they grow long, e.g. enough to be searched by fingerprint.
+    pub const CROWDED: HashTableConfig = HashTableConfig {
+        page_len: 64,
+        supported_levels: 12,
+        hi_levels: 6,
+        hi_bucket_len: 128,
+        lo_bucket_len: 512,
+        buckets_per_hi_level: 1 << 6,
+        buckets_per_lo_level: 1 << 8,
+        hasher: NodeHash::Murmur,
+    };
+
+    /// The spheres which `carve` removes, as the center and extent of `sphere`.
+    pub const SPHERES: [(u32, u32); 3] = [(256, 150), (100, 60), (400, 90)];
+
+    /// A sphere centered on the diagonal of the xz plane, halfway up the first 512 voxels.
+    pub fn sphere(center: u32, extent: u32) -> Sphere {
+        Sphere::new(&Vector3::new(center, 256, center), extent)
+    }
+
+    /// Removes `SPHERES` from the full DAG one after the other, returning the root of what remains.
+    pub fn carve(dag: &mut HostOnlyHashDAG) -> u32 {
+        let full = dag.full_node_ptr(0).unwrap();
+        SPHERES.iter().fold(full, |vptr, &(center, extent)| {
+            dag.edit(vptr, Unlink, &sphere(center, extent)).unwrap()
+        })
+    }
+
+    /// The full DAG without each sphere.
+    pub fn carved(dag: &mut HostOnlyHashDAG, spheres: &[Sphere]) -> Vec<u32> {
+        let full = dag.full_node_ptr(0).unwrap();
+        spheres
+            .iter()
+            .map(|shape| dag.edit(full, Unlink, shape).unwrap())
+            .collect()
+    }
+
     pub fn stage(dag: &mut BasicHashDAG, pool_dst: &mut [u32], lut_dst: &mut [u32]) {
         let specs = dag.staging_specs();
         let mut pool_src = vec![0; specs.pool_items as _].into_boxed_slice();
@@ -186,6 +274,147 @@ mod basic_dag {
         assert_ne!(node_found, bd.pool[bd.root_idx] as _);
         // A more rigorous test would be to now pointer-chase and to then determine if it is the correct node.
     }
+
+    #[test]
+    fn write_and_read_back() {
+        let toy_dag: SparseVoxelsSequential = load_ron("nodes").unwrap();
+        let mut bd = BasicDAG::new(toy_dag.levels, toy_dag.nodes.into_boxed_slice());
+        bd.header = BasicDAGHeader {
+            aabb_min: Vector3::new(-1.5, 2.0, -0.25),
+            aabb_max: Vector3::new(6.5, 10.0, 7.75),
+        };
+        let path = std::env::temp_dir().join(format!("write_and_read_back{DAG_SUFFIX}"));
+        bd.to_file(&path).unwrap();
+        let read = BasicDAG::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.header, bd.header);
+        assert_eq!(read.levels, bd.levels);
+        assert_eq!(read.pool, bd.pool);
+        assert_eq!(read.root_idx, 0);
+    }
+
+    #[test]
+    fn write_exported_lantern() {
+        let mut dag = host_only_with_capacity(32_000_000 / 4).unwrap();
+        let vptr = add_lantern(&mut dag).0.unwrap();
+        let bd = dag.export(vptr).unwrap();
+        let mut bytes = Vec::new();
+        bd.to_writer(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 4 + 8 + 4 * bd.pool.len());
+        let path = std::env::temp_dir().join(format!("write_exported_lantern{DAG_SUFFIX}"));
+        bd.to_file(&path).unwrap();
+        let read = BasicDAG::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut reimported = host_only_with_capacity(32_000_000 / 4).unwrap();
+        let reimported_vptr = reimported.import_strict(&read, None).unwrap();
+        assert!(same_structure(
+            (&dag.hash_dag, vptr),
+            (&reimported.hash_dag, reimported_vptr)
+        ));
+    }
+
+    #[test]
+    fn parse_lantern_header() {
+        let mut header = [0; HEADER_LEN];
+        File::open("assets/lantern.comp.bin")
+            .unwrap()
+            .read_exact(&mut header)
+            .unwrap();
+        let header = BasicDAGHeader::from_bytes(&header);
+        assert!(header.aabb_min.iter().all(|&v| (v + 12.832).abs() < 1e-3));
+        assert!(header.aabb_max.iter().all(|&v| (v - 12.832).abs() < 1e-3));
+        assert_eq!(BasicDAGHeader::from_bytes(&header.to_bytes()), header);
+    }
+
+    #[test]
+    fn header_places_voxels_in_world_space() {
+        let header = BasicDAGHeader {
+            aabb_min: Vector3::new(-8.0, 0.0, 8.0),
+            aabb_max: Vector3::new(8.0, 16.0, 24.0),
+        };
+        assert_eq!(header.voxel_size(3), Vector3::repeat(2.0));
+        let voxel = Vector3::new(1, 2, 7);
+        let world = header.to_world(3, &voxel);
+        assert_eq!(world, Vector3::new(-6.0, 4.0, 22.0));
+        assert_eq!(header.to_voxel(3, &world.add_scalar(0.5)), Some(voxel));
+        assert_eq!(header.to_voxel(3, &Vector3::new(8.0, 0.0, 8.0)), None);
+        assert_eq!(
+            BasicDAGHeader::voxel_space(3).to_world(3, &voxel),
+            voxel.map(f64::from)
+        );
+    }
+
+    #[test]
+    fn header_survives_export() {
+        let toy_dag: SparseVoxelsSequential = load_ron("nodes").unwrap();
+        let mut bd = BasicDAG::new(toy_dag.levels, toy_dag.nodes.into_boxed_slice());
+        bd.header.aabb_max = Vector3::repeat(4.0);
+        let mut dag = full_dag();
+        let vptr = dag.import_strict(&bd, None).unwrap();
+        let exported = dag.export(vptr).unwrap();
+        assert_eq!(exported.header, BasicDAGHeader::voxel_space(bd.levels));
+        let exported = dag.export_with_header(vptr, bd.header).unwrap();
+        let mut bytes = Vec::new();
+        exported.to_writer(&mut bytes).unwrap();
+        let header = BasicDAGHeader::from_bytes(&bytes[..HEADER_LEN].try_into().unwrap());
+        assert_eq!(header, bd.header);
+    }
+
+    #[test]
+    fn lantern_header_survives_round_trip() {
+        let file = format!("lantern1k{DAG_SUFFIX}");
+        let bd = BasicDAG::from_file(Path::new(SVDAG_STORE).join(file)).unwrap();
+        let is_lantern = |header: &BasicDAGHeader| {
+            header.aabb_min.iter().all(|&v| (v + 12.832).abs() < 1e-3)
+                && header.aabb_max.iter().all(|&v| (v - 12.832).abs() < 1e-3)
+        };
+        assert!(is_lantern(&bd.header));
+        let mut dag = host_only_with_capacity(32_000_000 / 4).unwrap();
+        let vptr = dag.import_strict(&bd, None).unwrap();
+        let path = std::env::temp_dir().join(format!("lantern_round_trip{DAG_SUFFIX}"));
+        dag.export_with_header(vptr, bd.header)
+            .unwrap()
+            .to_file(&path)
+            .unwrap();
+        let read = BasicDAG::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.header, bd.header);
+        assert!(is_lantern(&read.header));
+        assert_eq!(
+            dag.view()
+                .export_with_header(vptr, bd.header)
+                .unwrap()
+                .header,
+            bd.header
+        );
+    }
+
+    #[test]
+    fn write_with_offset_root() {
+        let mut bd = BasicDAG::new(3, Box::new([0, 0b1, 2, !0, !0]));
+        bd.root_idx = 1;
+        assert_eq!(
+            bd.to_writer(&mut Vec::new()).err(),
+            Some(Error::InvalidIndex {
+                level: SUPPORTED_LEVELS - 3,
+                idx: 1
+            })
+        );
+    }
+
+    #[test]
+    fn write_too_many_levels() {
+        let bd = BasicDAG::new(SUPPORTED_LEVELS + 1, Box::new([0, 0b1, 2, !0, !0]));
+        let mut bytes = Vec::new();
+        assert!(matches!(bd.to_writer(&mut bytes), Err(Error::Format(_))));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn read_missing_file() {
+        let error = BasicDAG::from_file(Path::new(SVDAG_STORE).join("missing.bin")).unwrap_err();
+        assert!(matches!(error, Error::Io(ref e) if e.kind() == std::io::ErrorKind::NotFound));
+    }
 }
 
 mod hash_table {
@@ -201,10 +430,7 @@ mod hash_table {
         }
         #[test]
         fn blank_hash_table_zero_size() {
-            assert_eq!(
-                host_only_blank(0).err(),
-                Some(format!("Cannot allocate {} words to a pool!", 0))
-            );
+            assert_eq!(host_only_blank(0).err(), Some(Error::InvalidCapacity(0)));
         }
         #[test]
         fn blank_hash_table_single_page() {
@@ -218,7 +444,7 @@ mod hash_table {
             const EXPECTED: usize = MAX_LEN + BLOCK_LEN - MAX_LEN % BLOCK_LEN;
             assert_eq!(
                 host_only_blank(TOTAL_VIRT_SPACE as usize + 1).err(),
-                Some(format!("Cannot allocate {EXPECTED} words to a pool!"))
+                Some(Error::InvalidCapacity(EXPECTED))
             );
         }
     }
@@ -256,6 +482,17 @@ mod hash_table {
             dag.allocate(COUNT as _).unwrap();
         }
         #[test]
+        fn allocate_exhausted() {
+            let mut dag = host_only_blank(PAGE_LEN as _).unwrap();
+            let pages = dag.pool.len() / PAGE_LEN as usize;
+            (0..pages).for_each(|page| dag.allocate(page).unwrap());
+            // Failing leaves the page unallocated, so allocating it again fails the same way.
+            assert_eq!(dag.allocate(pages), Err(Error::PoolExhausted));
+            assert_eq!(dag.is_allocated(pages), Ok(false));
+            assert_eq!(dag.allocate(pages), Err(Error::PoolExhausted));
+            assert_eq!(dag.lut.hi() as usize, pages);
+        }
+        #[test]
         #[cfg(debug_assertions)]
         #[should_panic(expected = "Trying to allocate an allocated page.")]
         fn allocate_twice() {
@@ -303,7 +540,7 @@ mod hash_table {
             dag.allocate((VPTR / PAGE_LEN) as _).unwrap();
             assert_eq!(
                 dag.pool_idx(VPTR + 1),
-                Err("Trying to lookup a non-existing page.".into())
+                Err(Error::InvalidPage(TOTAL_PAGES as _))
             );
         }
         #[test]
@@ -314,7 +551,7 @@ mod hash_table {
             // Offsetting by, say just one, may not work as it may still point in the "correct" page
             assert_eq!(
                 dag.pool_idx(VPTR - PAGE_LEN),
-                Err("Virtual pointer points to unallocated memory.".into())
+                Err(Error::UnallocatedVptr(VPTR - PAGE_LEN))
             );
         }
     }
@@ -538,9 +775,10 @@ mod hash_table {
             const LEAF: &[u32] = &[!0, !0];
             let hash = hash_leaf(LEAF);
             let bucket = bucket_from_hash(LEAF_LEVEL, hash);
+            let vptr = new_vptr(LEAF_LEVEL, bucket, 0).unwrap();
             assert_eq!(
                 dag.find_leaf(bucket, 0, LEAF),
-                Err("Virtual pointer points to unallocated memory.".into())
+                Err(Error::UnallocatedVptr(vptr))
             );
         }
         #[test]
@@ -550,9 +788,10 @@ mod hash_table {
             let node = [0xff; 9];
             let hash = hash_interior(&node);
             let bucket = bucket_from_hash(LEVEL, hash);
+            let vptr = new_vptr(LEVEL, bucket, 0).unwrap();
             assert_eq!(
                 dag.find_interior(LEVEL, bucket, 0, &node),
-                Err("Virtual pointer points to unallocated memory.".into())
+                Err(Error::UnallocatedVptr(vptr))
             );
         }
         #[test]
@@ -798,6 +1037,7 @@ mod hash_table {
         fn import_nodes_ron() {
             let toy_dag: SparseVoxelsSequential = load_ron("nodes").unwrap();
             let bd = BasicDAG {
+                header: BasicDAGHeader::voxel_space(toy_dag.levels),
                 pool: toy_dag.nodes.into_boxed_slice(),
                 levels: toy_dag.levels,
                 root_idx: 0,
@@ -811,6 +1051,7 @@ mod hash_table {
         fn import_nodes_svo_ron() {
             let mut toy_dag: SparseVoxelsSegmented = load_ron("nodes-svo").unwrap();
             let bd = BasicDAG {
+                header: BasicDAGHeader::voxel_space(toy_dag.levels),
                 pool: {
                     let mut pool = Vec::new();
                     let children = toy_dag.nodes[0].len() as u32;
@@ -834,6 +1075,7 @@ mod hash_table {
         /// Spotting this error is something a more rigorous/paranoid implementation should do.
         fn import_cyclical_graph() {
             let bd = BasicDAG {
+                header: BasicDAGHeader::voxel_space(4),
                 pool: Box::new([
                     0b0110_1001,
                     5,
@@ -867,11 +1109,52 @@ mod hash_table {
         fn import_too_much() {
             let mut dag = host_only_with_capacity(1).unwrap();
             let (import, _) = add_lantern(&mut dag);
+            assert_eq!(import.err(), Some(Error::PoolExhausted));
+        }
+        #[test]
+        fn export_nodes_ron() {
+            let toy_dag: SparseVoxelsSequential = load_ron("nodes").unwrap();
+            let bd = BasicDAG::new(toy_dag.levels, toy_dag.nodes.into_boxed_slice());
+            let mut dag = full_dag();
+            let vptr = dag.import_strict(&bd, None).unwrap();
+            let exported = dag.export(vptr).unwrap();
+            assert_eq!(exported.levels, bd.levels);
+            assert_eq!(exported.pool, bd.pool);
+        }
+        #[test]
+        fn export_full_node() {
+            let dag = full_dag();
+            let level = LEAF_LEVEL - 3;
+            let exported = dag.export(dag.full_node_ptr(level).unwrap()).unwrap();
+            assert_eq!(exported.levels, SUPPORTED_LEVELS - level);
+            // Each level is deduplicated into a single node.
+            assert_eq!(exported.pool.len(), 3 * 9 + 2);
+        }
+        #[test]
+        fn export_leaf() {
+            let dag = full_dag();
             assert_eq!(
-                import.err(),
-                Some("No space is left to allocate! Consider resizing your pool.".into())
+                dag.export(dag.full_node_ptr(LEAF_LEVEL).unwrap()).err(),
+                Some(Error::InvalidLevel(LEAF_LEVEL))
             );
         }
+        #[test]
+        fn export_lantern_and_reimport() {
+            let mut dag = host_only_with_capacity(32_000_000 / 4).unwrap();
+            let (import, bd) = add_lantern(&mut dag);
+            let vptr = dag
+                .edit(import.unwrap(), Link, &AABB::new(&Vector3::zero(), 200))
+                .unwrap();
+            let exported = dag.export(vptr).unwrap();
+            assert_eq!(exported.levels, bd.levels);
+            let mut reimported = host_only_with_capacity(32_000_000 / 4).unwrap();
+            let reimported_vptr = reimported.import_strict(&exported, None).unwrap();
+            assert!(same_structure(
+                (&dag.hash_dag, vptr),
+                (&reimported.hash_dag, reimported_vptr)
+            ));
+            import_matches(&exported, &reimported, true, reimported_vptr);
+        }
         const MAX_OFFSET: u32 = HI_BUCKET_LEN - 1;
         const MAX_BUCKET: u32 = BUCKETS_PER_HI_LEVEL - 1;
         #[test]
@@ -1225,4 +1508,2217 @@ mod hash_table {
             assert_eq!(dev_lut.as_ref().cmp(&dag.lut), Ordering::Equal);
         }
     }
+
+    mod garbage_collection {
+        use super::*;
+        #[test]
+        fn collect_without_roots_keeps_full_nodes() {
+            let mut dag = full_dag();
+            let allocated_pages = dag.lut.hi();
+            let vptr = dag.full_node_ptr(0).unwrap();
+            dag.edit(vptr, Unlink, &Sphere::new(&Vector3::zero(), 400))
+                .unwrap();
+            assert!(allocated_pages < dag.lut.hi());
+            assert!(dag.collect(&[]).unwrap().is_empty());
+            assert_eq!(allocated_pages, dag.lut.hi());
+            let root = dag.full_node_ptr(0).unwrap();
+            assert_eq!(dag.validate(root), Ok(Valid));
+            for level in 0..LEAF_LEVEL {
+                verify_full_interior(&dag, level, dag.full_node_ptr(level).unwrap());
+            }
+        }
+        #[test]
+        fn collect_after_edits() {
+            let (mut dag, mut reference) = (full_dag(), full_dag());
+            let edit = |dag: &mut HostOnlyHashDAG| {
+                let mut vptr = dag.full_node_ptr(0).unwrap();
+                for extent in [400, 300, 200] {
+                    let shape = Sphere::new(&Vector3::new(512, 512, 512), extent);
+                    vptr = dag.edit(vptr, Unlink, &shape).unwrap();
+                }
+                vptr
+            };
+            let (vptr, expected) = (edit(&mut dag), edit(&mut reference));
+            let allocated_pages = dag.lut.hi();
+            let roots = dag.collect(&[vptr]).unwrap();
+            assert!(dag.lut.hi() < allocated_pages);
+            assert_eq!(dag.validate(roots[0]), Ok(Valid));
+            assert!(same_structure(
+                (&dag.hash_dag, roots[0]),
+                (&reference.hash_dag, expected)
+            ));
+            // The table must still be usable afterwards.
+            let shape = AABB::new(&Vector3::zero(), 100);
+            let vptr = dag.edit(roots[0], Link, &shape).unwrap();
+            let expected = reference.edit(expected, Link, &shape).unwrap();
+            assert!(same_structure(
+                (&dag.hash_dag, vptr),
+                (&reference.hash_dag, expected)
+            ));
+        }
+        #[test]
+        fn collect_imported() {
+            let mut dag = host_only_with_capacity(32_000_000 / 4).unwrap();
+            let vptr = add_lantern(&mut dag).0.unwrap();
+            let mut reference = host_only_with_capacity(32_000_000 / 4).unwrap();
+            let expected = add_lantern(&mut reference).0.unwrap();
+            let shape = AABB::new(&Vector3::zero(), 200);
+            dag.edit(vptr, Link, &shape).unwrap();
+            let roots = dag.collect(&[vptr]).unwrap();
+            assert_eq!(dag.validate(roots[0]), Ok(Valid));
+            assert!(same_structure(
+                (&dag.hash_dag, roots[0]),
+                (&reference.hash_dag, expected)
+            ));
+            assert!(dag.lut.hi() <= reference.lut.hi());
+        }
+        #[test]
+        fn collect_with_missing_full_nodes() {
+            let (mut dag, mut reference) = (full_dag(), full_dag());
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag
+                .edit(dag.full_node_ptr(0).unwrap(), Unlink, &shape)
+                .unwrap();
+            let expected = reference
+                .edit(reference.full_node_ptr(0).unwrap(), Unlink, &shape)
+                .unwrap();
+            // As after attaching to a table whose root level has no full node.
+            dag.hash_dag.full_node_pointers[0] = !0;
+            let roots = dag.collect(&[vptr]).unwrap();
+            assert_eq!(dag.validate(roots[0]), Ok(Valid));
+            assert!(same_structure(
+                (&dag.hash_dag, roots[0]),
+                (&reference.hash_dag, expected)
+            ));
+            for level in 0..LEAF_LEVEL {
+                verify_full_interior(&dag, level, dag.full_node_ptr(level).unwrap());
+            }
+        }
+        #[test]
+        fn failed_collect_leaves_table_untouched() {
+            let mut dag = full_dag();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag
+                .edit(dag.full_node_ptr(0).unwrap(), Unlink, &shape)
+                .unwrap();
+            let before = (
+                dag.lut.to_vec(),
+                dag.lut.hi(),
+                dag.bucket_len.to_vec(),
+                dag.pool.to_vec(),
+                dag.full_node_pointers,
+            );
+            let unchanged = |dag: &HostOnlyHashDAG| {
+                before
+                    == (
+                        dag.lut.to_vec(),
+                        dag.lut.hi(),
+                        dag.bucket_len.to_vec(),
+                        dag.pool.to_vec(),
+                        dag.full_node_pointers,
+                    )
+            };
+            assert!(dag.collect(&[vptr, !0]).is_err());
+            assert!(unchanged(&dag));
+            dag.hash_dag.full_node_pointers[0] = !0;
+            assert!(matches!(
+                dag.collect(&[vptr, !0]),
+                Err(Error::InvalidNode(_))
+            ));
+            dag.hash_dag.full_node_pointers[0] = before.4[0];
+            assert!(unchanged(&dag));
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+        }
+        #[test]
+        fn stage_after_collect() {
+            let mut dag = basic_with_capacity((32_000_000 / 4) as _).unwrap();
+            let vptr = add_lantern(&mut dag).0.unwrap();
+            dag.tracker.clear();
+            let root = dag.collect(&[vptr]).unwrap()[0];
+            // Only the pool is staged, not the whole virtual space.
+            assert_eq!(dag.staging_specs().pool_items, dag.pool.len());
+            let mut dev_pool = vec![0; dag.pool.len()].into_boxed_slice();
+            let mut dev_lut = vec![0; dag.lut.len()].into_boxed_slice();
+            stage(&mut dag, &mut dev_pool, &mut dev_lut);
+            assert_eq!(dev_pool.as_ref().cmp(&dag.pool), Ordering::Equal);
+            assert_eq!(dev_lut.as_ref().cmp(&dag.lut), Ordering::Equal);
+            assert_eq!(dag.validate(root), Ok(Valid));
+        }
+    }
+    mod concurrent_hash_dag {
+        use super::*;
+        const THREADS: usize = 4;
+        /// Every thread inserts the same nodes, starting at a different offset.
+        fn insert_concurrently<F>(len: usize, insert: F) -> Vec<u32>
+        where
+            F: Fn(usize) -> u32 + Sync,
+        {
+            let results: Vec<Vec<u32>> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..THREADS)
+                    .map(|thread| {
+                        let insert = &insert;
+                        scope.spawn(move || {
+                            let mut vptrs = vec![0; len];
+                            for i in (0..len).map(|i| (i + thread * len / THREADS) % len) {
+                                vptrs[i] = insert(i);
+                            }
+                            vptrs
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+            assert!(results.iter().all(|vptrs| vptrs == &results[0]));
+            results.into_iter().next().unwrap()
+        }
+        #[test]
+        fn find_or_add_leaves_concurrently() {
+            let dag = ConcurrentHashDAG::from(full_dag());
+            let leaves = leaves(3999);
+            let vptrs = insert_concurrently(leaves.len(), |i| {
+                HashDAGSync::find_or_add_leaf(&dag, Pass(&leaves[i])).unwrap()
+            });
+            let mut dag = dag.into_inner();
+            let allocated_pages = dag.lut.hi();
+            for (leaf, vptr) in leaves.iter().zip(vptrs) {
+                assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+                assert_eq!(dag.find_or_add_leaf(Pass(leaf)), Ok(vptr));
+            }
+            assert_eq!(allocated_pages, dag.lut.hi());
+        }
+        #[test]
+        fn find_or_add_interiors_concurrently() {
+            const LEVEL: u32 = LEAF_LEVEL - 1;
+            let dag = ConcurrentHashDAG::from(full_dag());
+            let leaves = leaves(3999);
+            let children = insert_concurrently(leaves.len(), |i| {
+                HashDAGSync::find_or_add_leaf(&dag, Pass(&leaves[i])).unwrap()
+            });
+            let interiors: Vec<[u32; 3]> = children
+                .windows(2)
+                .zip(leaves.windows(2))
+                .map(|(vptrs, leaves)| {
+                    let voxel_count: u32 =
+                        leaves.iter().flatten().map(|word| word.count_ones()).sum();
+                    [0b11 | voxel_count << 8, vptrs[0], vptrs[1]]
+                })
+                .collect();
+            let vptrs = insert_concurrently(interiors.len(), |i| {
+                HashDAGSync::find_or_add_interior(&dag, LEVEL, Pass(&interiors[i])).unwrap()
+            });
+            let mut dag = dag.into_inner();
+            let allocated_pages = dag.lut.hi();
+            for (interior, vptr) in interiors.iter().zip(vptrs) {
+                assert_eq!(dag.interior(vptr).unwrap(), interior);
+                assert_eq!(dag.find_or_add_interior(LEVEL, Pass(interior)), Ok(vptr));
+            }
+            assert_eq!(allocated_pages, dag.lut.hi());
+        }
+        #[test]
+        fn find_full_nodes_concurrently() {
+            let dag = ConcurrentHashDAG::from(full_dag());
+            let full_leaf = dag.full_node_ptr(LEAF_LEVEL).unwrap();
+            let vptrs = insert_concurrently(1, |_| {
+                HashDAGSync::find_or_add_leaf(&dag, Pass(&[!0, !0])).unwrap()
+            });
+            assert_eq!(vptrs, [full_leaf]);
+        }
+        #[test]
+        fn pool_exhausted_concurrently() {
+            let dag = ConcurrentHashDAG::from(host_only_blank(PAGE_LEN as usize).unwrap());
+            let mut dag = dag.into_inner();
+            dag.add_full_leaf();
+            let dag = ConcurrentHashDAG::from(dag);
+            let error = leaves(3999)
+                .iter()
+                .find_map(|leaf| HashDAGSync::find_or_add_leaf(&dag, Pass(leaf)).err());
+            assert_eq!(error, Some(Error::PoolExhausted));
+        }
+        #[test]
+        fn add_after_pool_exhausted() {
+            let mut dag = host_only_blank(2 * PAGE_LEN as usize).unwrap();
+            dag.add_full_leaf();
+            let dag = ConcurrentHashDAG::from(dag);
+            let leaves = leaves(3999);
+            let results: Vec<_> = leaves
+                .iter()
+                .map(|leaf| HashDAGSync::find_or_add_leaf(&dag, Pass(leaf)))
+                .collect();
+            let hi = dag.lut.hi();
+            assert_eq!(hi as usize * PAGE_LEN as usize, dag.pool.len());
+            assert!(results.contains(&Err(Error::PoolExhausted)));
+            // Failing to allocate leaves no page behind, so adding again fails the same way.
+            for (leaf, result) in leaves.iter().zip(results) {
+                assert_eq!(HashDAGSync::find_or_add_leaf(&dag, Pass(leaf)), result);
+            }
+            assert_eq!(dag.lut.hi(), hi);
+            let allocated = dag.lut.iter().filter(|&&pool_idx| pool_idx != !0).count();
+            assert_eq!(allocated, hi as usize);
+        }
+    }
+    mod attach {
+        use super::*;
+        fn root(name: &str) -> String {
+            let name = format!("voxel-dags-{}-{name}-", std::process::id());
+            std::env::temp_dir()
+                .join(name)
+                .to_string_lossy()
+                .into_owned()
+        }
+        #[test]
+        fn attach_read_only() {
+            let root = root("attach_read_only");
+            let mut dag =
+                HostOnlyHashDAG::with_capacity(Some(&root), 0xffff * PAGE_LEN as usize).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.full_node_ptr(0).unwrap();
+            let vptr = dag.edit(vptr, Unlink, &shape).unwrap();
+            let attached = ReadOnlyHashTable::attach(&root).unwrap();
+            assert_eq!(
+                attached.full_node_pointers(),
+                dag.full_node_pointers.as_slice()
+            );
+            assert_eq!(attached.hi(), dag.lut.hi());
+            assert_eq!(attached.view().validate(vptr), Ok(Valid));
+            assert!(same_structure((&attached, vptr), (&dag.hash_dag, vptr)));
+            // Nodes added by the creator show up without attaching again.
+            let leaf = [0xf0f0, 0x0f0f];
+            let leaf_ptr = dag.find_or_add_leaf(Pass(&leaf)).unwrap();
+            assert_eq!(attached.leaf(leaf_ptr), Ok(leaf.as_slice()));
+        }
+        #[test]
+        fn attach_read_write() {
+            let root = root("attach_read_write");
+            let mut dag =
+                HostOnlyHashDAG::with_capacity(Some(&root), 0xffff * PAGE_LEN as usize).unwrap();
+            let mut attached = HostOnlyHashDAG::attach(&root, None).unwrap();
+            let leaf = [0xf0f0, 0x0f0f];
+            let vptr = attached.find_or_add_leaf(Pass(&leaf)).unwrap();
+            let allocated_pages = dag.lut.hi();
+            assert_eq!(dag.find_or_add_leaf(Pass(&leaf)), Ok(vptr));
+            assert_eq!(allocated_pages, dag.lut.hi());
+            drop(attached);
+            // Detaching must leave the shared memory intact.
+            assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+        }
+        #[test]
+        fn attach_without_full_nodes() {
+            let root = root("attach_without_full_nodes");
+            let _dag = HostOnlyHashDAG::blank(Some(&root), PAGE_LEN as usize, None).unwrap();
+            let attached = HashTable::attach(&root).unwrap();
+            assert!(attached.full_node_pointers.iter().all(|&vptr| vptr == !0));
+        }
+        #[test]
+        fn attach_missing() {
+            let root = root("attach_missing");
+            assert!(matches!(HashTable::attach(&root), Err(Error::Shmem(_))));
+        }
+        #[test]
+        fn attach_mismatched() {
+            let root = root("attach_mismatched");
+            let _page_table =
+                ShmemArray::<u32>::new(PAGE_LEN as usize, Some(format!("{root}page_table.flink")))
+                    .unwrap();
+            assert!(matches!(HashTable::attach(&root), Err(Error::Format(_))));
+            assert!(matches!(
+                ReadOnlyHashTable::attach(&root),
+                Err(Error::Format(_))
+            ));
+        }
+        #[test]
+        fn attach_overlong_bucket() {
+            let root = root("attach_overlong_bucket");
+            let mut dag =
+                HostOnlyHashDAG::with_capacity(Some(&root), 0xffff * PAGE_LEN as usize).unwrap();
+            let bucket_len = dag.config.new_bucket_len(0) + 1;
+            dag.bucket_len_copy_from(0, &[bucket_len]);
+            let expected = Error::BucketLenOutOfBounds {
+                level: 0,
+                bucket_len,
+            };
+            assert_eq!(HashTable::attach(&root).err(), Some(expected));
+        }
+        #[test]
+        fn attach_misplaced_page() {
+            let root = root("attach_misplaced_page");
+            let mut dag =
+                HostOnlyHashDAG::with_capacity(Some(&root), 0xffff * PAGE_LEN as usize).unwrap();
+            let hi = dag.lut.hi();
+            let mut lut = dag.lut.to_vec();
+            lut[0] = hi * PAGE_LEN;
+            dag.hash_dag.lut.copy_from(&lut, hi);
+            assert!(matches!(
+                ReadOnlyHashTable::attach(&root),
+                Err(Error::Format(_))
+            ));
+        }
+    }
+    mod snapshot {
+        use super::*;
+        fn path(name: &str) -> std::path::PathBuf {
+            std::env::temp_dir().join(format!("voxel-dags-{}-{name}.snapshot", std::process::id()))
+        }
+        #[test]
+        fn save_and_load() {
+            let path = path("save_and_load");
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let carved = dag.edit(full, Unlink, &shape).unwrap();
+            dag.save(&path, &[("full", full), ("carved", carved)])
+                .unwrap();
+            let (mut loaded, roots) = BasicHashDAG::load(&path, None, None).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(roots, [("full".into(), full), ("carved".into(), carved)]);
+            assert_eq!(loaded.full_node_pointers, dag.full_node_pointers);
+            assert_eq!(loaded.lut.hi(), dag.lut.hi());
+            assert_eq!(loaded.validate(carved), Ok(Valid));
+            assert!(same_structure(
+                (&loaded.hash_dag, carved),
+                (&dag.hash_dag, carved)
+            ));
+            let pool_pages = loaded.pool.len() / POOL_MASK_BIT_LEN;
+            let (staged, unstaged) = loaded
+                .tracker
+                .pool_mask
+                .split_at(pool_pages / POOL_MASK_BITS);
+            assert!(staged.iter().all(|&mask| mask == !0));
+            assert!(unstaged.iter().all(|&mask| mask == 0));
+            // Finding existing nodes must not add anything.
+            let allocated_pages = loaded.lut.hi();
+            let interior = loaded.interior(carved).unwrap().to_vec();
+            assert_eq!(loaded.find_or_add_interior(0, Pass(&interior)), Ok(carved));
+            assert_eq!(allocated_pages, loaded.lut.hi());
+            let expected = dag.edit(carved, Link, &shape).unwrap();
+            let vptr = loaded.edit(carved, Link, &shape).unwrap();
+            assert_eq!(vptr, expected);
+            assert_eq!(loaded.lut.hi(), dag.lut.hi());
+        }
+        #[test]
+        fn stage_after_load() {
+            let path = path("stage_after_load");
+            let mut dag = basic_with_capacity((32_000_000 / 4) as _).unwrap();
+            let vptr = add_lantern(&mut dag).0.unwrap();
+            dag.save(&path, &[("lantern", vptr)]).unwrap();
+            let (mut loaded, _) = BasicHashDAG::load(&path, None, None).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.staging_specs().pool_items, loaded.pool.len());
+            let mut dev_pool = vec![0; loaded.pool.len()].into_boxed_slice();
+            let mut dev_lut = vec![0; loaded.lut.len()].into_boxed_slice();
+            stage(&mut loaded, &mut dev_pool, &mut dev_lut);
+            assert_eq!(dev_pool.as_ref().cmp(&loaded.pool), Ordering::Equal);
+            assert_eq!(dev_lut.as_ref().cmp(&loaded.lut), Ordering::Equal);
+        }
+        #[test]
+        fn load_not_a_snapshot() {
+            let path = path("load_not_a_snapshot");
+            std::fs::write(&path, b"not a snapshot").unwrap();
+            let result = HashTable::load(&path, None);
+            std::fs::remove_file(&path).unwrap();
+            assert!(matches!(result, Err(Error::Format(_))));
+        }
+        #[test]
+        fn load_unsupported_version() {
+            let path = path("load_unsupported_version");
+            std::fs::write(
+                &path,
+                [b"HDAG".as_slice(), &u32::MAX.to_le_bytes()].concat(),
+            )
+            .unwrap();
+            let result = HashTable::load(&path, None);
+            std::fs::remove_file(&path).unwrap();
+            assert!(matches!(result, Err(Error::Format(_))));
+        }
+        #[test]
+        fn load_pages_mapped_twice() {
+            let path = path("load_pages_mapped_twice");
+            full_dag().save(&path, &[]).unwrap();
+            let mut bytes = std::fs::read(&path).unwrap();
+            // Past the magic, version, config, hasher, capacity and `hi`, the first two pages follow.
+            let pages = 4 + 4 + 9 * 4 + 8 + 4;
+            let first_pool_idx = bytes[pages + 4..pages + 8].to_vec();
+            bytes[pages + 12..pages + 16].copy_from_slice(&first_pool_idx);
+            std::fs::write(&path, bytes).unwrap();
+            let result = HashTable::load(&path, None);
+            std::fs::remove_file(&path).unwrap();
+            assert!(
+                matches!(result, Err(Error::Format(msg)) if msg.contains("as is another page"))
+            );
+        }
+        #[test]
+        fn load_missing() {
+            let result = HashTable::load(path("load_missing"), None);
+            assert!(matches!(result, Err(Error::Io(_))));
+        }
+    }
+    mod config {
+        use super::*;
+        const SMALL: HashTableConfig = HashTableConfig {
+            page_len: 64,
+            supported_levels: 12,
+            hi_levels: 6,
+            hi_bucket_len: 128,
+            lo_bucket_len: 512,
+            buckets_per_hi_level: 1 << 6,
+            buckets_per_lo_level: 1 << 10,
+            hasher: NodeHash::Murmur,
+        };
+        fn small_dag<'shmem>() -> HostOnlyHashDAG<'shmem> {
+            HostOnlyHashDAG::with_config(None, 0x1fff * SMALL.page_len as usize, SMALL).unwrap()
+        }
+        #[test]
+        fn default_matches_constants() {
+            let config = HashTableConfig::DEFAULT;
+            assert_eq!(config.validate(), Ok(()));
+            assert_eq!(config.leaf_level(), LEAF_LEVEL);
+            assert_eq!(config.color_tree_levels(), COLOR_TREE_LEVELS);
+            assert_eq!(config.total_pages(), TOTAL_PAGES);
+            assert_eq!(config.total_virt_space(), TOTAL_VIRT_SPACE);
+            let vptr = config.new_vptr(LEAF_LEVEL, 3, 5).unwrap();
+            assert_eq!(Ok(vptr), new_vptr(LEAF_LEVEL, 3, 5));
+            assert_eq!(config.vptr_to_lvl(vptr), vptr_to_lvl(vptr));
+        }
+        #[test]
+        fn validate_rejects_invalid() {
+            assert_eq!(SMALL.validate(), Ok(()));
+            let invalid = [
+                HashTableConfig {
+                    page_len: 48,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    page_len: 16,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    hi_bucket_len: 96,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    lo_bucket_len: 0,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    buckets_per_lo_level: 3,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    supported_levels: SUPPORTED_LEVELS + 1,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    hi_levels: 13,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    buckets_per_lo_level: 1 << 22,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    buckets_per_hi_level: 1,
+                    buckets_per_lo_level: 1,
+                    ..SMALL
+                },
+                // Only the levels in use count towards the virtual space, but every level is addressed.
+                HashTableConfig {
+                    hi_levels: 12,
+                    buckets_per_lo_level: 1 << 24,
+                    ..SMALL
+                },
+                HashTableConfig {
+                    hi_levels: 0,
+                    buckets_per_hi_level: 1 << 26,
+                    ..SMALL
+                },
+            ];
+            for config in invalid {
+                assert!(
+                    matches!(config.validate(), Err(Error::InvalidConfig(_))),
+                    "{config:?}"
+                );
+                assert!(HashTable::blank_with_config(None, 0, config).is_err());
+            }
+        }
+        #[test]
+        fn small_geometry() {
+            let mut dag = small_dag();
+            assert_eq!(dag.config(), &SMALL);
+            assert_eq!(dag.lut.len(), SMALL.total_pages() as usize);
+            let leaf_level = SMALL.leaf_level();
+            assert!(dag.full_node_ptr(leaf_level + 1).is_err());
+            let root = dag.full_node_ptr(0).unwrap();
+            assert_eq!(SMALL.vptr_to_lvl(root), 0);
+            assert_eq!(
+                SMALL.vptr_to_lvl(dag.full_node_ptr(leaf_level).unwrap()),
+                leaf_level
+            );
+            assert_eq!(dag.validate(root), Ok(Valid));
+            let shape = Sphere::new(&Vector3::new(256, 256, 256), 100);
+            let carved = dag.edit(root, Unlink, &shape).unwrap();
+            assert_ne!(carved, root);
+            assert_eq!(dag.validate(carved), Ok(Valid));
+            assert_eq!(dag.edit(carved, Link, &shape), Ok(root));
+            let leaf = [0xf0f0, 0x0f0f];
+            let vptr = dag.find_or_add_leaf(Pass(&leaf)).unwrap();
+            assert_eq!(SMALL.vptr_to_lvl(vptr), leaf_level);
+            assert_eq!(dag.find_or_add_leaf(Pass(&leaf)), Ok(vptr));
+        }
+        #[test]
+        fn small_geometry_next_to_default() {
+            let (small, default) = (small_dag(), full_dag());
+            let (small_root, default_root) = (
+                small.full_node_ptr(0).unwrap(),
+                default.full_node_ptr(0).unwrap(),
+            );
+            assert_eq!(small.validate(small_root), Ok(Valid));
+            assert_eq!(default.validate(default_root), Ok(Valid));
+            assert!(small.lut.len() < default.lut.len());
+            let voxels = |dag: &HostOnlyHashDAG, root| dag.get(root).unwrap() >> 8;
+            let color_tree_root = |dag: &HostOnlyHashDAG| {
+                let level = dag.config().color_tree_levels();
+                voxels(dag, dag.full_node_ptr(level).unwrap())
+            };
+            assert_eq!(color_tree_root(&small), color_tree_root(&default));
+        }
+        #[test]
+        fn basic_tracker_granularity() {
+            let mut dag =
+                BasicHashDAG::with_config(None, 0x1fff * SMALL.page_len as usize, SMALL).unwrap();
+            assert_eq!(dag.tracker.pool_mask_bit_len, SMALL.page_len as usize);
+            assert_eq!(
+                dag.tracker.pool_mask.len(),
+                SMALL.total_pages() as usize / POOL_MASK_BITS
+            );
+            let root = dag.full_node_ptr(0).unwrap();
+            let shape = AABB::new(&Vector3::zero(), 100);
+            dag.edit(root, Unlink, &shape).unwrap();
+            // The device starts out like a blank table.
+            let mut dev_pool = vec![0; dag.pool.len()].into_boxed_slice();
+            let mut dev_lut = vec![!0; dag.lut.len()].into_boxed_slice();
+            stage(&mut dag, &mut dev_pool, &mut dev_lut);
+            assert_eq!(dev_pool.as_ref().cmp(&dag.pool), Ordering::Equal);
+            assert_eq!(dev_lut.as_ref().cmp(&dag.lut), Ordering::Equal);
+        }
+        #[test]
+        fn snapshot_keeps_config() {
+            let path = std::env::temp_dir().join(format!(
+                "voxel-dags-{}-snapshot_keeps_config.snapshot",
+                std::process::id()
+            ));
+            let dag = small_dag();
+            let root = dag.full_node_ptr(0).unwrap();
+            dag.save(&path, &[("root", root)]).unwrap();
+            let (loaded, _) = HashTable::load(&path, None).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.config, SMALL);
+            assert_eq!(loaded.full_node_pointers, dag.full_node_pointers);
+            assert_eq!(loaded.validate(root), Ok(Valid));
+        }
+        #[test]
+        fn attach_with_config() {
+            let root = std::env::temp_dir()
+                .join(format!(
+                    "voxel-dags-{}-attach_with_config-",
+                    std::process::id()
+                ))
+                .to_string_lossy()
+                .into_owned();
+            let dag =
+                HostOnlyHashDAG::with_config(Some(&root), 0x1fff * SMALL.page_len as usize, SMALL)
+                    .unwrap();
+            let attached = HashTable::attach_with_config(&root, SMALL).unwrap();
+            assert_eq!(attached.full_node_pointers, dag.full_node_pointers);
+            assert!(matches!(HashTable::attach(&root), Err(Error::Format(_))));
+        }
+        #[test]
+        fn shmem_config_writes_runtime_values() {
+            let path = std::env::temp_dir()
+                .join(format!(
+                    "voxel-dags-{}-shmem_config.json",
+                    std::process::id()
+                ))
+                .to_string_lossy()
+                .into_owned();
+            let shmem_config = ShmemConfig {
+                path: path.clone(),
+                class: "HashTable".into(),
+                config: SMALL,
+            }
+            .write()
+            .unwrap();
+            let json = std::fs::read_to_string(&path).unwrap();
+            assert!(json.contains("\"PAGE_LEN\":64,"));
+            assert!(json.contains("\"SUPPORTED_LEVELS\":12,"));
+            assert!(json.contains(&format!("\"TOTAL_PAGES\":{},", SMALL.total_pages())));
+            drop(shmem_config);
+            assert!(!Path::new(&path).exists());
+        }
+        #[test]
+        fn attach_through_shmem_config() {
+            let (path, root) = [".json", "-"]
+                .map(|suffix| {
+                    std::env::temp_dir()
+                        .join(format!(
+                            "voxel-dags-{}-attach_through_shmem_config{suffix}",
+                            std::process::id()
+                        ))
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .into();
+            let config = HashTableConfig {
+                hasher: NodeHash::Seeded(7),
+                ..SMALL
+            };
+            let dag =
+                HostOnlyHashDAG::with_config(Some(&root), 0x1fff * SMALL.page_len as usize, config)
+                    .unwrap();
+            let shmem_config = ShmemConfig {
+                path: path.clone(),
+                class: "HashTable".into(),
+                config,
+            }
+            .write()
+            .unwrap();
+            let read = ShmemConfig::read(&path).unwrap();
+            assert_eq!(read, config);
+            let attached = ReadOnlyHashTable::attach_with_config(&root, read).unwrap();
+            assert_eq!(
+                attached.full_node_pointers(),
+                dag.full_node_pointers.as_slice()
+            );
+            // Derived constants have to agree with the geometry.
+            let json = std::fs::read_to_string(&path).unwrap().replace(
+                &format!("\"TOTAL_PAGES\":{},", SMALL.total_pages()),
+                "\"TOTAL_PAGES\":1,",
+            );
+            std::fs::write(&path, json).unwrap();
+            assert!(matches!(
+                ShmemConfig::read(&path),
+                Err(Error::InvalidConfig(_))
+            ));
+            // Files of the version before the hasher was written are rejected as such.
+            let json = std::fs::read_to_string(&path)
+                .unwrap()
+                .replace(&format!("\"VERSION\":{VERSION},"), "\"VERSION\":0,");
+            std::fs::write(&path, json).unwrap();
+            assert!(matches!(
+                ShmemConfig::read(&path),
+                Err(Error::Format(msg)) if msg.ends_with("is not of version 1.")
+            ));
+            drop(shmem_config);
+        }
+    }
+    mod grow {
+        use super::*;
+        #[test]
+        fn grow_keeps_nodes() {
+            let mut dag = host_only_with_capacity(1).unwrap();
+            let mut reference = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let (pool_len, allocated_pages) = (dag.pool.len(), dag.lut.hi());
+            dag.grow(0x200 * pool_len).unwrap();
+            assert_eq!(dag.pool.len(), 0x200 * pool_len);
+            assert_eq!(dag.lut.hi(), allocated_pages);
+            assert_eq!(dag.validate(full), Ok(Valid));
+            assert!(same_structure(
+                (&dag.hash_dag, full),
+                (&reference.hash_dag, full)
+            ));
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            let expected = reference.edit(full, Unlink, &shape).unwrap();
+            assert!(same_structure(
+                (&dag.hash_dag, vptr),
+                (&reference.hash_dag, expected)
+            ));
+            assert_eq!(dag.edit(vptr, Link, &shape), Ok(full));
+        }
+        #[test]
+        fn grow_invalid_capacity() {
+            let mut dag = host_only_with_capacity(1).unwrap();
+            let pool_len = dag.pool.len();
+            assert_eq!(dag.grow(pool_len), Err(Error::InvalidCapacity(pool_len)));
+            let too_large = TOTAL_VIRT_SPACE as usize + 1;
+            assert!(matches!(
+                dag.grow(too_large),
+                Err(Error::InvalidCapacity(_))
+            ));
+            assert_eq!(dag.pool.len(), pool_len);
+        }
+        #[test]
+        fn import_growable() {
+            let mut dag = host_only_with_capacity(1).unwrap();
+            dag.set_growable(true);
+            let (import, bd) = add_lantern(&mut dag);
+            let vptr = import.unwrap();
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            import_matches(&bd, &dag, true, vptr);
+        }
+        #[test]
+        fn grow_stages_whole_pool() {
+            let mut dag = basic_with_capacity(1).unwrap();
+            dag.tracker.clear();
+            dag.grow(2 * dag.pool.len()).unwrap();
+            let pool_pages = dag.pool.len() / POOL_MASK_BIT_LEN;
+            let (staged, unstaged) = dag.tracker.pool_mask.split_at(pool_pages / POOL_MASK_BITS);
+            assert!(staged.iter().all(|&mask| mask == !0));
+            assert!(unstaged.iter().all(|&mask| mask == 0));
+            let mut dev_pool = vec![0; dag.pool.len()].into_boxed_slice();
+            let mut dev_lut = vec![0; dag.lut.len()].into_boxed_slice();
+            stage(&mut dag, &mut dev_pool, &mut dev_lut);
+            assert_eq!(dev_pool.as_ref().cmp(&dag.pool), Ordering::Equal);
+            assert_eq!(dev_lut.as_ref().cmp(&dag.lut), Ordering::Equal);
+        }
+        #[test]
+        fn grow_shared() {
+            let root = std::env::temp_dir()
+                .join(format!("voxel-dags-{}-grow_shared-", std::process::id()))
+                .to_string_lossy()
+                .into_owned();
+            let mut dag = HostOnlyHashDAG::with_capacity(Some(&root), 1).unwrap();
+            let mut attached = HostOnlyHashDAG::attach(&root, None).unwrap();
+            let pool_len = dag.pool.len();
+            assert_eq!(attached.grow(2 * pool_len), Err(Error::NotOwner));
+            dag.grow(2 * pool_len).unwrap();
+            // Nothing of the old pool can be read until attaching again, neither nodes written after the grow
+            // within its range, which only the new pool holds, nor those written before.
+            let within = (1..)
+                .find_map(|i| {
+                    let vptr = dag.find_or_add_leaf(Pass(&[i, !i])).unwrap();
+                    (dag.pool_idx(vptr).unwrap() < pool_len).then_some(vptr)
+                })
+                .unwrap();
+            let full_leaf = dag.full_node_ptr(LEAF_LEVEL).unwrap();
+            for vptr in [within, full_leaf] {
+                assert_eq!(attached.leaf(vptr), Err(Error::StalePool(vptr)));
+                assert_eq!(attached.get(vptr), Err(Error::StalePool(vptr)));
+            }
+            // Nor can anything be written into it.
+            let bucket_len = dag.bucket_len.to_vec();
+            let hi = dag.lut.hi();
+            assert!(matches!(
+                attached.find_or_add_leaf(Pass(&[7, 11])),
+                Err(Error::StalePool(_))
+            ));
+            assert_eq!(*dag.bucket_len, *bucket_len);
+            assert_eq!(dag.lut.hi(), hi);
+            let vptr = (1..)
+                .find_map(|i| {
+                    let vptr = dag.find_or_add_leaf(Pass(&[i, !i])).unwrap();
+                    (pool_len < dag.pool_idx(vptr).unwrap()).then_some(vptr)
+                })
+                .unwrap();
+            assert_eq!(attached.leaf(vptr), Err(Error::StalePool(vptr)));
+            // Attaching again maps the new pool.
+            let attached = HashTable::attach(&root).unwrap();
+            assert_eq!(attached.pool.len(), dag.pool.len());
+            assert_eq!(attached.full_node_pointers, dag.full_node_pointers);
+            let root = dag.full_node_ptr(0).unwrap();
+            assert!(same_structure((&attached, root), (&dag.hash_dag, root)));
+        }
+        fn flink(name: &str) -> String {
+            std::env::temp_dir()
+                .join(format!("voxel-dags-{}-{name}.flink", std::process::id()))
+                .to_string_lossy()
+                .into_owned()
+        }
+        #[test]
+        fn resize_shared_array() {
+            let flink = flink("resize_shared_array");
+            let mut array = ShmemArray::<u32>::new(4, Some(&flink)).unwrap();
+            array.copy_from(0, &[1, 2, 3, 4]);
+            ShmemArray::resize(&mut array, 8, 3).unwrap();
+            assert_eq!(array[..], [1, 2, 3, 0, 0, 0, 0, 0]);
+            let opened = ShmemArray::<u32>::open(&flink).unwrap();
+            assert_eq!(opened[..], array[..]);
+            drop(opened);
+            drop(array);
+            assert!(!Path::new(&flink).exists());
+        }
+        #[test]
+        fn resize_shared_array_failed() {
+            let flink = flink("resize_shared_array_failed");
+            let mut array = ShmemArray::<u32>::new(4, Some(&flink)).unwrap();
+            array.copy_from(0, &[1, 2, 3, 4]);
+            assert!(ShmemArray::resize(&mut array, usize::MAX / 8, 4).is_err());
+            assert_eq!(array[..], [1, 2, 3, 4]);
+            // The flink still names the old segment.
+            let opened = ShmemArray::<u32>::open(&flink).unwrap();
+            assert_eq!(opened[..], [1, 2, 3, 4]);
+        }
+    }
+    mod probing {
+        use super::*;
+        /// Even fewer leaf buckets, so they overflow quickly.
+        const SKEWED: HashTableConfig = HashTableConfig {
+            buckets_per_lo_level: 1 << 4,
+            ..CROWDED
+        };
+        fn skewed_dag<'shmem>() -> HostOnlyHashDAG<'shmem> {
+            HostOnlyHashDAG::with_config(None, SKEWED.total_virt_space() as _, SKEWED).unwrap()
+        }
+        #[test]
+        fn overflowing_leaves() {
+            let mut dag = skewed_dag();
+            let leaf_level = SKEWED.leaf_level();
+            let leaves = leaves(3900);
+            let vptrs: Vec<u32> = leaves
+                .iter()
+                .map(|leaf| dag.find_or_add_leaf(Pass(leaf)).unwrap())
+                .collect();
+            let spilled = leaves.iter().zip(&vptrs).filter(|(leaf, &vptr)| {
+                let home = SKEWED.bucket_from_hash(leaf_level, hash_leaf(*leaf));
+                let bucket_len = SKEWED.new_bucket_len(leaf_level);
+                let home = SKEWED.new_vptr(leaf_level, home, 0).unwrap();
+                !(home..home + bucket_len).contains(&vptr)
+            });
+            assert_ne!(spilled.count(), 0, "No leaf left its bucket.");
+            let allocated_pages = dag.lut.hi();
+            for (leaf, &vptr) in leaves.iter().zip(&vptrs) {
+                assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+                assert_eq!(dag.find_or_add_leaf(Pass(leaf)), Ok(vptr));
+            }
+            assert_eq!(allocated_pages, dag.lut.hi());
+        }
+        #[test]
+        fn level_full() {
+            let mut dag = skewed_dag();
+            let leaf_level = SKEWED.leaf_level();
+            let error = leaves(SKEWED.buckets_per_lo_level * SKEWED.lo_bucket_len / 2)
+                .iter()
+                .find_map(|leaf| dag.find_or_add_leaf(Pass(leaf)).err());
+            assert!(matches!(
+                error,
+                Some(Error::BucketOverflow { level, .. }) if level == leaf_level
+            ));
+        }
+        #[test]
+        fn overflowing_edit() {
+            // Some buckets of the last interior level overflow, while the level has plenty of space left.
+            let config = HashTableConfig {
+                buckets_per_lo_level: 1 << 10,
+                ..SKEWED
+            };
+            let mut dag =
+                HostOnlyHashDAG::with_config(None, config.total_virt_space() as _, config).unwrap();
+            let root = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let carved = dag.edit(root, Unlink, &shape).unwrap();
+            assert_eq!(dag.validate(carved), Ok(Valid));
+            let allocated_pages = dag.lut.hi();
+            assert_eq!(dag.edit(root, Unlink, &shape), Ok(carved));
+            assert_eq!(allocated_pages, dag.lut.hi());
+            assert_eq!(dag.edit(carved, Link, &shape), Ok(root));
+        }
+        #[test]
+        fn overflowing_leaves_concurrently() {
+            let leaves = leaves(3900);
+            let mut expected = skewed_dag();
+            let dag = ConcurrentHashDAG::from(skewed_dag());
+            let vptrs: Vec<Vec<u32>> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..4)
+                    .map(|thread| {
+                        let (dag, leaves) = (&dag, &leaves);
+                        scope.spawn(move || {
+                            let mut vptrs = vec![0; leaves.len()];
+                            for i in (0..leaves.len()).map(|i| (i + thread * 975) % leaves.len()) {
+                                let leaf = Pass(&leaves[i]);
+                                vptrs[i] = HashDAGSync::find_or_add_leaf(dag, leaf).unwrap();
+                            }
+                            vptrs
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            assert!(vptrs.iter().all(|thread| thread == &vptrs[0]));
+            let dag = dag.into_inner();
+            for (leaf, &vptr) in leaves.iter().zip(&vptrs[0]) {
+                assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+                let leaf_level = SKEWED.leaf_level();
+                let bucket = SKEWED.bucket_from_hash(leaf_level, hash_leaf(leaf));
+                assert_eq!(dag.probe_leaf(bucket, leaf), Ok(Some(vptr)));
+                expected.find_or_add_leaf(Pass(leaf)).unwrap();
+            }
+            assert_eq!(dag.lut.hi(), expected.lut.hi());
+        }
+    }
+    mod storage {
+        use super::*;
+        fn heap_dag<'shmem>() -> HostOnlyHashDAG<'shmem> {
+            let capacity = 0xffff * PAGE_LEN as usize;
+            HostOnlyHashDAG::with_backing(Backing::Heap, capacity, HashTableConfig::DEFAULT)
+                .unwrap()
+        }
+        #[test]
+        fn heap_matches_shared() {
+            let (mut dag, mut reference) = (heap_dag(), full_dag());
+            assert!(matches!(dag.pool, Storage::Heap(_)));
+            assert_eq!(dag.full_node_pointers, reference.full_node_pointers);
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            let expected = reference.edit(full, Unlink, &shape).unwrap();
+            assert_eq!(vptr, expected);
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            assert_eq!(dag.pool[..], reference.pool[..]);
+            assert_eq!(dag.lut[..], reference.lut[..]);
+        }
+        #[test]
+        fn import_on_heap() {
+            let capacity = 32_000_000 / 4;
+            let mut dag =
+                HostOnlyHashDAG::with_backing(Backing::Heap, capacity, HashTableConfig::DEFAULT)
+                    .unwrap();
+            let (import, bd) = add_lantern(&mut dag);
+            let vptr = import.unwrap();
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            import_matches(&bd, &dag, true, vptr);
+        }
+        #[test]
+        fn clone_is_independent() {
+            let dag = full_dag();
+            let mut clone = dag.clone();
+            assert!(matches!(clone.pool, Storage::Heap(_)));
+            assert_eq!(clone.pool[..], dag.pool[..]);
+            let full = clone.full_node_ptr(0).unwrap();
+            let allocated_pages = dag.lut.hi();
+            let vptr = clone
+                .edit(full, Unlink, &Sphere::new(&Vector3::zero(), 100))
+                .unwrap();
+            assert_eq!(clone.validate(vptr), Ok(Valid));
+            assert_eq!(dag.lut.hi(), allocated_pages);
+            assert!(allocated_pages < clone.lut.hi());
+            assert_ne!(clone.bucket_len[..], dag.bucket_len[..]);
+        }
+        #[test]
+        fn grow_on_heap() {
+            let mut dag =
+                HostOnlyHashDAG::with_backing(Backing::Heap, 1, HashTableConfig::DEFAULT).unwrap();
+            dag.set_growable(true);
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            assert_eq!(dag.edit(vptr, Link, &shape), Ok(full));
+        }
+        #[test]
+        fn load_on_heap() {
+            let path = std::env::temp_dir().join(format!(
+                "voxel-dags-{}-load_on_heap.snapshot",
+                std::process::id()
+            ));
+            let dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            dag.save(&path, &[("full", full)]).unwrap();
+            let (loaded, roots) = BasicHashDAG::load_in(&path, Backing::Heap, None).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(matches!(loaded.pool, Storage::Heap(_)));
+            assert_eq!(roots, [("full".into(), full)]);
+            assert_eq!(loaded.validate(full), Ok(Valid));
+        }
+        #[test]
+        fn concurrent_on_heap() {
+            let dag = ConcurrentHashDAG::from(heap_dag());
+            let leaves: Vec<[u32; 2]> = (1..2000u32).map(|i| [i, !i]).collect();
+            let vptrs: Vec<Vec<u32>> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..4)
+                    .map(|_| {
+                        let (dag, leaves) = (&dag, &leaves);
+                        scope.spawn(move || {
+                            leaves
+                                .iter()
+                                .map(|leaf| HashDAGSync::find_or_add_leaf(dag, Pass(leaf)).unwrap())
+                                .collect()
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            assert!(vptrs.iter().all(|thread| thread == &vptrs[0]));
+            for (leaf, &vptr) in leaves.iter().zip(&vptrs[0]) {
+                assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+            }
+        }
+    }
+    #[cfg(unix)]
+    mod mapped {
+        use super::*;
+        fn dir(name: &str) -> std::path::PathBuf {
+            let dir =
+                std::env::temp_dir().join(format!("voxel-dags-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+        #[test]
+        fn mapped_matches_heap() {
+            let dir = dir("mapped_matches_heap");
+            let capacity = 0xffff * PAGE_LEN as usize;
+            let config = HashTableConfig::DEFAULT;
+            let mut dag =
+                HostOnlyHashDAG::with_backing(Backing::Mapped(&dir), capacity, config).unwrap();
+            let mut reference =
+                HostOnlyHashDAG::with_backing(Backing::Heap, capacity, config).unwrap();
+            assert!(matches!(dag.pool, Storage::Mapped(_)));
+            assert!(dir.join("data_pool.bin").is_file());
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            assert_eq!(reference.edit(full, Unlink, &shape), Ok(vptr));
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            assert_eq!(dag.pool[..], reference.pool[..]);
+            assert_eq!(dag.edit(vptr, Link, &shape), Ok(full));
+            drop(dag);
+            assert!(!dir.join("data_pool.bin").exists());
+            std::fs::remove_dir(&dir).unwrap();
+        }
+        #[test]
+        fn import_mapped() {
+            let dir = dir("import_mapped");
+            let mut dag = HostOnlyHashDAG::with_backing(
+                Backing::Mapped(&dir),
+                32_000_000 / 4,
+                HashTableConfig::DEFAULT,
+            )
+            .unwrap();
+            let (import, bd) = add_lantern(&mut dag);
+            let vptr = import.unwrap();
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            import_matches(&bd, &dag, true, vptr);
+        }
+        #[test]
+        fn grow_mapped() {
+            let dir = dir("grow_mapped");
+            let mut dag =
+                HostOnlyHashDAG::with_backing(Backing::Mapped(&dir), 1, HashTableConfig::DEFAULT)
+                    .unwrap();
+            dag.set_growable(true);
+            let pool_len = dag.pool.len();
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            assert!(pool_len < dag.pool.len());
+            let file_len = std::fs::metadata(dir.join("data_pool.bin")).unwrap().len();
+            assert_eq!(file_len, (4 * dag.pool.len()) as u64);
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            assert_eq!(dag.edit(vptr, Link, &shape), Ok(full));
+        }
+        #[test]
+        fn resize_mapped_array_failed() {
+            let dir = dir("resize_mapped_array_failed");
+            let mut array = MmapArray::create(dir.join("array.bin"), 4).unwrap();
+            array.copy_from(0, &[1, 2, 3, 4]);
+            assert!(array.resize(usize::MAX / 8).is_err());
+            assert_eq!(array[..], [1, 2, 3, 4]);
+            let file_len = std::fs::metadata(dir.join("array.bin")).unwrap().len();
+            assert_eq!(file_len, 16);
+            array.resize(8).unwrap();
+            assert_eq!(array[..], [1, 2, 3, 4, 0, 0, 0, 0]);
+        }
+        #[test]
+        fn mapped_snapshot() {
+            let dir = dir("mapped_snapshot");
+            let path = dir.join("table.snapshot");
+            let dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            dag.save(&path, &[("full", full)]).unwrap();
+            let (loaded, _) = BasicHashDAG::load_in(&path, Backing::Mapped(&dir), None).unwrap();
+            assert!(matches!(loaded.pool, Storage::Mapped(_)));
+            assert_eq!(loaded.validate(full), Ok(Valid));
+            assert!(same_structure(
+                (&loaded.hash_dag, full),
+                (&dag.hash_dag, full)
+            ));
+        }
+    }
+    mod hashing {
+        use super::*;
+        const SMALL: HashTableConfig = HashTableConfig {
+            page_len: 64,
+            supported_levels: 12,
+            hi_levels: 6,
+            hi_bucket_len: 128,
+            lo_bucket_len: 512,
+            buckets_per_hi_level: 1 << 6,
+            buckets_per_lo_level: 1 << 10,
+            hasher: NodeHash::XxHash,
+        };
+        const INTERIOR: [u32; 3] = [0b1001_0000, 12, 34];
+        #[test]
+        fn murmur_matches_utils() {
+            let leaf = [0x1234_5678, 0x9abc_def0];
+            assert_eq!(Murmur.hash_leaf(&leaf), hash_leaf(&leaf));
+            assert_eq!(Murmur.hash_interior(&INTERIOR), hash_interior(&INTERIOR));
+            assert_eq!(Seeded(0).hash_leaf(&leaf), hash_leaf(&leaf));
+            assert_eq!(Seeded(0).hash_interior(&INTERIOR), hash_interior(&INTERIOR));
+            assert_eq!(NodeHash::default(), NodeHash::Murmur);
+            assert_eq!(HashTableConfig::DEFAULT.hasher, NodeHash::Murmur);
+        }
+        #[test]
+        fn hashers_differ() {
+            let hashers = [
+                NodeHash::Murmur,
+                NodeHash::Seeded(7),
+                NodeHash::XxHash,
+                NodeHash::Fnv,
+            ];
+            for (i, a) in hashers.iter().enumerate() {
+                for b in &hashers[i + 1..] {
+                    assert_ne!(a.hash_interior(&INTERIOR), b.hash_interior(&INTERIOR));
+                    assert_ne!(a.hash_leaf(&[1, 2]), b.hash_leaf(&[1, 2]));
+                }
+                assert_ne!(a.hash_leaf(&[1, 2]), a.hash_leaf(&[2, 1]));
+                assert_eq!(NodeHash::from_words(a.to_words()), Ok(*a));
+            }
+            assert_eq!(
+                XxHash.hash_interior(&INTERIOR),
+                NodeHash::XxHash.hash_interior(&INTERIOR)
+            );
+            assert_eq!(Fnv.hash_leaf(&[1, 2]), NodeHash::Fnv.hash_leaf(&[1, 2]));
+            assert!(matches!(
+                NodeHash::from_words([4, 0]),
+                Err(Error::Format(_))
+            ));
+        }
+        #[test]
+        fn table_uses_its_hasher() {
+            for hasher in [NodeHash::Seeded(7), NodeHash::XxHash, NodeHash::Fnv] {
+                let config = HashTableConfig { hasher, ..SMALL };
+                let mut dag =
+                    HostOnlyHashDAG::with_config(None, 0x1fff * config.page_len as usize, config)
+                        .unwrap();
+                let leaf_level = config.leaf_level();
+                let leaf = [0x1234_5678, 0x9abc_def0];
+                let vptr = dag.find_or_add_leaf(Pass(&leaf)).unwrap();
+                assert_eq!(dag.find_or_add_leaf(Pass(&leaf)), Ok(vptr));
+                let bucket = config.bucket_from_hash(leaf_level, hasher.hash_leaf(&leaf));
+                assert_eq!(bucket, config.leaf_bucket(&leaf));
+                assert_eq!(dag.probe_leaf(bucket, &leaf), Ok(Some(vptr)));
+                assert_eq!(dag.find_full_nodes(), Ok(dag.full_node_pointers));
+                let full = dag.full_node_ptr(0).unwrap();
+                let shape = Sphere::new(&Vector3::new(256, 256, 256), 150);
+                let edited = dag.edit(full, Unlink, &shape).unwrap();
+                assert_eq!(dag.validate(edited), Ok(Valid));
+                assert_eq!(dag.edit(full, Unlink, &shape), Ok(edited));
+                assert_eq!(dag.edit(edited, Link, &shape), Ok(full));
+            }
+        }
+        #[test]
+        fn snapshot_keeps_hasher() {
+            let path = std::env::temp_dir().join(format!(
+                "voxel-dags-{}-snapshot_keeps_hasher.snapshot",
+                std::process::id()
+            ));
+            let config = HashTableConfig {
+                hasher: NodeHash::Seeded(42),
+                ..SMALL
+            };
+            let dag = HostOnlyHashDAG::with_config(None, 0x1fff * config.page_len as usize, config)
+                .unwrap();
+            dag.save(&path, &[]).unwrap();
+            let (loaded, _) = HashTable::load_in(&path, Backing::Heap).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.config, config);
+            assert_eq!(loaded.find_full_nodes(), Ok(dag.full_node_pointers));
+        }
+        #[test]
+        fn occupancy_per_level() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::new(512, 512, 512), 300);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            let reports = dag.occupancy(&[vptr], &Murmur).unwrap();
+            assert_eq!(reports.len(), LEAF_LEVEL as usize + 1);
+            assert_eq!(reports[0].nodes, 1);
+            for report in &reports {
+                let config = &dag.config;
+                let buckets = f64::from(config.buckets_per_level(report.level));
+                assert!(report.nodes <= (report.mean * buckets).round() as usize);
+                assert!(report.mean <= f64::from(report.max));
+                assert_eq!(report.overflowing, 0);
+            }
+            // Leaves are two words each.
+            let leaves = reports[LEAF_LEVEL as usize].nodes;
+            assert_eq!(
+                reports[LEAF_LEVEL as usize].mean
+                    * f64::from(dag.config.buckets_per_level(LEAF_LEVEL)),
+                (2 * leaves) as f64
+            );
+            let fnv = dag.occupancy(&[vptr], &Fnv).unwrap();
+            assert!(reports
+                .iter()
+                .zip(&fnv)
+                .all(|(murmur, fnv)| murmur.nodes == fnv.nodes && murmur.mean == fnv.mean));
+        }
+        #[test]
+        fn shmem_config_writes_hasher() {
+            let path = std::env::temp_dir()
+                .join(format!(
+                    "voxel-dags-{}-shmem_config_hasher.json",
+                    std::process::id()
+                ))
+                .to_string_lossy()
+                .into_owned();
+            let shmem_config = ShmemConfig {
+                path: path.clone(),
+                class: "HashTable".into(),
+                config: HashTableConfig {
+                    hasher: NodeHash::Seeded(42),
+                    ..SMALL
+                },
+            }
+            .write()
+            .unwrap();
+            let json = std::fs::read_to_string(&path).unwrap();
+            assert!(json.contains("\"HASHER\":\"seeded\","));
+            assert!(json.contains("\"HASH_SEED\":42,"));
+            drop(shmem_config);
+        }
+    }
+    mod fingerprints {
+        use super::*;
+        use crate::hash_table::{
+            basic::FINGERPRINTED_BUCKET_LEN,
+            fingerprints::{BucketFingerprints, Fingerprints},
+        };
+        fn crowded_dag<'shmem>(fingerprinted: bool) -> HostOnlyHashDAG<'shmem> {
+            let mut dag = HostOnlyHashDAG::with_backing(
+                Backing::Heap,
+                CROWDED.total_virt_space() as _,
+                CROWDED,
+            )
+            .unwrap();
+            dag.hash_dag.set_fingerprinted(fingerprinted).unwrap();
+            dag
+        }
+        #[test]
+        fn bucket_fingerprints_forget_truncated_nodes() {
+            assert_eq!(Fingerprints::of(0xabcd_0123), 0xabcd);
+            let mut index = BucketFingerprints::default();
+            for (fingerprint, offset) in [(7, 0), (3, 2), (7, 5), (7, 9)] {
+                index.push(fingerprint, offset);
+            }
+            index.set_indexed_len(12);
+            assert_eq!(index.candidates(7, 12).collect::<Vec<_>>(), [0, 5, 9]);
+            assert_eq!(index.candidates(7, 9).collect::<Vec<_>>(), [0, 5]);
+            index.truncate(5);
+            assert_eq!(index.indexed_len(), 5);
+            assert_eq!(index.candidates(7, 12).collect::<Vec<_>>(), [0]);
+            index.push(7, 5);
+            assert_eq!(index.candidates(7, 12).collect::<Vec<_>>(), [0, 5]);
+        }
+        #[test]
+        fn fingerprinted_matches_scan() {
+            let (mut dag, mut reference) = (crowded_dag(true), crowded_dag(false));
+            assert!(dag.fingerprints.is_some() && reference.fingerprints.is_none());
+            let vptr = carve(&mut dag);
+            assert_eq!(carve(&mut reference), vptr);
+            assert_eq!(dag.pool[..], reference.pool[..]);
+            let leaf_level = CROWDED.leaf_level();
+            let longest = (0..CROWDED.buckets_per_lo_level)
+                .map(|bucket| dag.bucket_len(leaf_level, bucket))
+                .max()
+                .unwrap();
+            assert!(FINGERPRINTED_BUCKET_LEN <= longest);
+            let hi = dag.lut.hi();
+            assert_eq!(carve(&mut dag), vptr);
+            assert_eq!(dag.lut.hi(), hi);
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+        }
+        #[test]
+        fn rolled_back_nodes_are_forgotten() {
+            let mut dag = crowded_dag(true);
+            carve(&mut dag);
+            let leaf_level = CROWDED.leaf_level();
+            let bucket = (0..CROWDED.buckets_per_lo_level)
+                .find(|&bucket| {
+                    let bucket_len = dag.bucket_len(leaf_level, bucket);
+                    FINGERPRINTED_BUCKET_LEN <= bucket_len && dag.has_room(leaf_level, bucket, 2)
+                })
+                .unwrap();
+            let rolled_back = dag.transaction(|dag| {
+                dag.add_leaf(Pass(&[1, 2]), bucket)?;
+                Err::<u32, _>(Error::EmptyDAG)
+            });
+            assert_eq!(rolled_back, Err(Error::EmptyDAG));
+            let vptr = dag.add_leaf(Pass(&[3, 4]), bucket).unwrap();
+            let bucket_len = dag.bucket_len(leaf_level, bucket);
+            assert_eq!(dag.find_leaf(bucket, bucket_len, &[1, 2]), Ok(None));
+            assert_eq!(dag.find_leaf(bucket, bucket_len, &[3, 4]), Ok(Some(vptr)));
+        }
+        #[test]
+        fn set_fingerprinted_indexes_existing_nodes() {
+            let mut dag = crowded_dag(false);
+            let vptr = carve(&mut dag);
+            let bucket_len = dag.bucket_len.to_vec();
+            dag.hash_dag.set_fingerprinted(true).unwrap();
+            assert_eq!(carve(&mut dag), vptr);
+            assert_eq!(dag.bucket_len[..], bucket_len[..]);
+        }
+        #[test]
+        fn collect_clears_reused_pages() {
+            let (mut dag, mut reference) = (crowded_dag(true), crowded_dag(false));
+            for dag in [&mut dag, &mut reference] {
+                let vptr = carve(dag);
+                let root = dag.collect(&[vptr]).unwrap()[0];
+                assert_eq!(carve(dag), root);
+                assert_eq!(dag.validate(root), Ok(Valid));
+            }
+            assert_eq!(dag.bucket_len[..], reference.bucket_len[..]);
+            assert_eq!(dag.lut[..], reference.lut[..]);
+        }
+        #[test]
+        fn load_indexes_nodes() {
+            let path = std::env::temp_dir().join(format!(
+                "voxel-dags-{}-load_indexes_nodes.snapshot",
+                std::process::id()
+            ));
+            let mut dag = crowded_dag(true);
+            let vptr = carve(&mut dag);
+            dag.save(&path, &[("root", vptr)]).unwrap();
+            let (mut loaded, _) = HostOnlyHashDAG::load_in(&path, Backing::Heap, None).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(loaded.fingerprints.is_some());
+            let bucket_len = loaded.bucket_len.to_vec();
+            assert_eq!(carve(&mut loaded), vptr);
+            assert_eq!(loaded.bucket_len[..], bucket_len[..]);
+        }
+        #[test]
+        fn named_shared_memory_is_not_fingerprinted() {
+            let root = std::env::temp_dir()
+                .join(format!("voxel-dags-{}-fingerprints-", std::process::id()))
+                .to_string_lossy()
+                .into_owned();
+            let dag = HostOnlyHashDAG::with_capacity(Some(&root), PAGE_LEN as usize).unwrap();
+            assert!(dag.fingerprints.is_none());
+            assert!(HashTable::attach(&root).unwrap().fingerprints.is_none());
+            assert!(host_only_with_capacity(PAGE_LEN as usize)
+                .unwrap()
+                .fingerprints
+                .is_some());
+        }
+    }
+    mod batch {
+        use super::*;
+        /// Distinct leaves, followed by some of them again and the full leaf.
+        fn repeated_leaves(count: u32) -> Vec<[u32; 2]> {
+            let mut leaves = leaves(count);
+            leaves.extend_from_within(..count as usize / 3);
+            leaves.push([!0, !0]);
+            leaves
+        }
+        /// Interior nodes of the last interior level, pointing at `children`.
+        fn interiors(count: u32, children: &[u32]) -> Vec<Vec<u32>> {
+            (1..=count)
+                .map(|i| {
+                    let mask = i % 255 + 1;
+                    let pointers = (0..mask.count_ones() as usize)
+                        .map(|child| children[(i as usize + child) % children.len()]);
+                    std::iter::once(mask).chain(pointers).collect()
+                })
+                .collect()
+        }
+        #[test]
+        fn leaves_like_sequential() {
+            let (mut dag, mut reference) = (full_dag(), full_dag());
+            let leaves = repeated_leaves(3000);
+            let nodes: Vec<_> = leaves.iter().map(|leaf| Strict(leaf)).collect();
+            let vptrs = dag.find_or_add_leaves(&nodes).unwrap();
+            let expected: Vec<_> = nodes
+                .iter()
+                .map(|&node| reference.find_or_add_leaf(node).unwrap())
+                .collect();
+            assert_eq!(vptrs, expected);
+            assert_eq!(vptrs.last(), Some(&dag.full_node_ptr(LEAF_LEVEL).unwrap()));
+            assert_eq!(dag.bucket_len[..], reference.bucket_len[..]);
+            assert_eq!(dag.lut[..], reference.lut[..]);
+            assert_eq!(dag.lut.hi(), reference.lut.hi());
+            assert_eq!(dag.find_or_add_leaves(&nodes).unwrap(), vptrs);
+        }
+        #[test]
+        fn interiors_like_sequential() {
+            let (mut dag, mut reference) = (full_dag(), full_dag());
+            let leaves = repeated_leaves(64);
+            let nodes: Vec<_> = leaves.iter().map(|leaf| Strict(leaf)).collect();
+            let children = dag.find_or_add_leaves(&nodes).unwrap();
+            reference.find_or_add_leaves(&nodes).unwrap();
+            let mut interiors = interiors(2000, &children);
+            interiors.extend_from_within(100..200);
+            let full = dag.full_node_ptr(LEAF_LEVEL - 1).unwrap();
+            interiors.push(dag.interior(full).unwrap().to_vec());
+            let nodes: Vec<_> = interiors.iter().map(|node| Pass(node)).collect();
+            let vptrs = dag.find_or_add_interiors(LEAF_LEVEL - 1, &nodes).unwrap();
+            let expected: Vec<_> = nodes
+                .iter()
+                .map(|&node| {
+                    reference
+                        .find_or_add_interior(LEAF_LEVEL - 1, node)
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(vptrs, expected);
+            assert_eq!(vptrs.last(), Some(&full));
+            assert_eq!(dag.bucket_len[..], reference.bucket_len[..]);
+        }
+        #[test]
+        fn probing_like_sequential() {
+            let config = HashTableConfig {
+                buckets_per_lo_level: 1 << 4,
+                ..HashTableConfig::DEFAULT
+            };
+            let dag = || {
+                HostOnlyHashDAG::with_config(None, config.total_virt_space() as _, config).unwrap()
+            };
+            let (mut dag, mut reference) = (dag(), dag());
+            let leaves = repeated_leaves(3900);
+            let nodes: Vec<_> = leaves.iter().map(|leaf| Pass(leaf)).collect();
+            let vptrs = dag.find_or_add_leaves(&nodes).unwrap();
+            for (&node, vptr) in nodes.iter().zip(vptrs) {
+                assert_eq!(reference.find_or_add_leaf(node), Ok(vptr));
+            }
+            assert_eq!(dag.bucket_len[..], reference.bucket_len[..]);
+            assert_eq!(dag.lut[..], reference.lut[..]);
+        }
+        #[test]
+        fn invalid_node_adds_nothing() {
+            let mut dag = full_dag();
+            let children = [dag.full_node_ptr(LEAF_LEVEL).unwrap()];
+            let interiors = interiors(2, &children);
+            let bucket_len = dag.bucket_len.to_vec();
+            let nodes = [Pass(&interiors[0]), Strict(&[1]), Pass(&interiors[1])];
+            assert!(matches!(
+                dag.find_or_add_interiors(LEAF_LEVEL - 1, &nodes),
+                Err(Error::InvalidNode(_))
+            ));
+            assert_eq!(dag.bucket_len[..], bucket_len[..]);
+        }
+        #[test]
+        fn grows_once() {
+            let mut dag = host_only_with_capacity(1).unwrap();
+            dag.set_growable(true);
+            let pool_len = dag.pool.len();
+            let leaves = repeated_leaves(3000);
+            let nodes: Vec<_> = leaves.iter().map(|leaf| Strict(leaf)).collect();
+            let vptrs = dag.find_or_add_leaves(&nodes).unwrap();
+            assert!(pool_len < dag.pool.len());
+            for (leaf, vptr) in leaves.iter().zip(vptrs) {
+                assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+            }
+        }
+        /// Fails once `budget` nodes have been registered, e.g. to abort a batch halfway.
+        #[derive(Debug, Clone)]
+        struct FailingTracker {
+            budget: usize,
+        }
+        impl Default for FailingTracker {
+            fn default() -> Self {
+                Self { budget: usize::MAX }
+            }
+        }
+        impl Tracker for FailingTracker {
+            fn register(&mut self, _: u32, range: std::ops::Range<usize>) -> Result<()> {
+                self.budget = self
+                    .budget
+                    .checked_sub(1)
+                    .ok_or(Error::InvalidRange(range))?;
+                Ok(())
+            }
+            fn register_all(&mut self, _: usize) {}
+            fn clear(&mut self) {}
+        }
+        #[test]
+        fn failing_batch_adds_nothing() {
+            let mut dag = SharedHashDAG::<HashTable, FailingTracker>::with_capacity(
+                None,
+                0xffff * PAGE_LEN as usize,
+            )
+            .unwrap();
+            let (bucket_len, lut, hi) = (dag.bucket_len.to_vec(), dag.lut.to_vec(), dag.lut.hi());
+            dag.tracker.budget = 1000;
+            let leaves = repeated_leaves(3000);
+            let nodes: Vec<_> = leaves.iter().map(|leaf| Strict(leaf)).collect();
+            assert!(matches!(
+                dag.find_or_add_leaves(&nodes),
+                Err(Error::InvalidRange(_))
+            ));
+            assert_eq!(dag.bucket_len[..], bucket_len[..]);
+            assert_eq!(dag.lut[..], lut[..]);
+            assert_eq!(dag.lut.hi(), hi);
+            // Retrying adds every node, each once.
+            dag.tracker.budget = usize::MAX;
+            let vptrs = dag.find_or_add_leaves(&nodes).unwrap();
+            for (leaf, vptr) in leaves.iter().zip(vptrs) {
+                assert_eq!(dag.leaf(vptr).unwrap(), leaf);
+                assert_eq!(dag.find_or_add_leaf(Strict(leaf)), Ok(vptr));
+            }
+        }
+        #[test]
+        fn exhausted_adds_nothing() {
+            let mut dag = host_only_with_capacity(1).unwrap();
+            let (bucket_len, lut, hi) = (dag.bucket_len.to_vec(), dag.lut.to_vec(), dag.lut.hi());
+            let leaves = repeated_leaves(3000);
+            let nodes: Vec<_> = leaves.iter().map(|leaf| Strict(leaf)).collect();
+            assert_eq!(dag.find_or_add_leaves(&nodes), Err(Error::PoolExhausted));
+            assert_eq!(dag.bucket_len[..], bucket_len[..]);
+            assert_eq!(dag.lut[..], lut[..]);
+            assert_eq!(dag.lut.hi(), hi);
+        }
+    }
+    mod view {
+        use super::*;
+        #[test]
+        fn staged_copy_matches_host() {
+            let mut dag = basic_with_capacity((32_000_000 / 4) as _).unwrap();
+            let mut dev_pool = vec![0; dag.pool.len()].into_boxed_slice();
+            let mut dev_lut = vec![0; dag.lut.len()].into_boxed_slice();
+            let vptr = add_lantern(&mut dag).0.unwrap();
+            stage(&mut dag, &mut dev_pool, &mut dev_lut);
+            let full_node_pointers = dag.full_node_pointers;
+            let view =
+                HashDAGView::new(dag.config, (&dev_pool, &dev_lut), &full_node_pointers).unwrap();
+            assert_eq!(view.validate(vptr), Ok(Valid));
+            assert_eq!(view.first_mismatch(&dag.hash_dag, vptr), Ok(None));
+            let (exported, expected) = (view.export(vptr).unwrap(), dag.export(vptr).unwrap());
+            assert_eq!(exported.pool, expected.pool);
+            assert_eq!(view.full_node_ptr(0), dag.full_node_ptr(0));
+        }
+        #[test]
+        fn unstaged_edit_mismatches() {
+            let mut dag = basic_with_capacity((32_000_000 / 4) as _).unwrap();
+            let mut dev_pool = vec![0; dag.pool.len()].into_boxed_slice();
+            let mut dev_lut = vec![0; dag.lut.len()].into_boxed_slice();
+            let vptr = add_lantern(&mut dag).0.unwrap();
+            stage(&mut dag, &mut dev_pool, &mut dev_lut);
+            let leaf = dag.find_or_add_leaf(Pass(&[1, 2])).unwrap();
+            let full_node_pointers = dag.full_node_pointers;
+            let view =
+                HashDAGView::new(dag.config, (&dev_pool, &dev_lut), &full_node_pointers).unwrap();
+            assert_eq!(view.first_mismatch(&dag.hash_dag, leaf), Ok(Some(leaf)));
+            let pool_idx = view.pool_idx(vptr).unwrap();
+            dev_pool[pool_idx + 1] ^= 1;
+            let view =
+                HashDAGView::new(dag.config, (&dev_pool, &dev_lut), &full_node_pointers).unwrap();
+            assert_eq!(view.first_mismatch(&dag.hash_dag, vptr), Ok(Some(vptr)));
+        }
+        #[test]
+        fn invalid_page_table() {
+            let dag = full_dag();
+            let (pool, lut) = dag.dump();
+            assert!(matches!(
+                HashDAGView::new(dag.config, (pool, &lut[1..]), &dag.full_node_pointers),
+                Err(Error::Format(_))
+            ));
+            let view = dag.view();
+            let full = dag.full_node_ptr(0).unwrap();
+            assert_eq!(view.interior(full), dag.interior(full));
+            assert!(matches!(
+                HashDAGView {
+                    pool: &pool[..1],
+                    ..view
+                }
+                .get(full),
+                Err(Error::OutOfBoundsVptr(_))
+            ));
+        }
+    }
+    mod history {
+        use super::*;
+        /// Like `carve`, recording each sphere.
+        fn carve_recorded(dag: &mut HostOnlyHashDAG, history: &mut History) {
+            for (center, extent) in SPHERES {
+                history
+                    .edit(
+                        dag,
+                        format!("sphere {center}"),
+                        Unlink,
+                        &sphere(center, extent),
+                    )
+                    .unwrap();
+            }
+        }
+        #[test]
+        fn undo_redo() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let mut history = History::new("full", full);
+            carve_recorded(&mut dag, &mut history);
+            let roots: Vec<_> = history.entries().iter().map(|&(_, root)| root).collect();
+            assert_eq!(roots.len(), SPHERES.len() + 1);
+            assert_eq!(history.root(), roots[3]);
+            assert_eq!(history.redo(), None);
+            assert_eq!(history.undo(), Some(roots[2]));
+            assert_eq!(history.undo(), Some(roots[1]));
+            assert_eq!(history.redo(), Some(roots[2]));
+            assert_eq!(history.checkout(0), Ok(full));
+            assert_eq!(history.undo(), None);
+            assert_eq!(history.checkout(4), Err(Error::InvalidHistoryEntry(4)));
+            assert_eq!(history.current(), 0);
+        }
+        #[test]
+        fn record_drops_redo() {
+            let mut dag = full_dag();
+            let mut history = History::new("full", dag.full_node_ptr(0).unwrap());
+            carve_recorded(&mut dag, &mut history);
+            history.checkout(1).unwrap();
+            let shape = Sphere::new(&Vector3::repeat(0), 40);
+            let root = history.edit(&mut dag, "corner", Unlink, &shape).unwrap();
+            assert_eq!(history.entries().len(), 3);
+            assert_eq!(history.entries()[2], ("corner".into(), root));
+            assert_eq!(history.redo(), None);
+        }
+        #[test]
+        fn collect_keeps_entries() {
+            let mut dag = full_dag();
+            let mut history = History::new("full", dag.full_node_ptr(0).unwrap());
+            carve_recorded(&mut dag, &mut history);
+            let exported: Vec<_> = history
+                .entries()
+                .iter()
+                .map(|&(_, root)| dag.export(root).unwrap().pool)
+                .collect();
+            let shape = Sphere::new(&Vector3::repeat(0), 40);
+            let other = dag.edit(history.root(), Link, &shape).unwrap();
+            let other = history.collect(&mut dag, &[other]).unwrap()[0];
+            assert_eq!(dag.validate(other), Ok(Valid));
+            for ((_, root), exported) in history.entries().iter().zip(&exported) {
+                assert_eq!(dag.validate(*root), Ok(Valid));
+                assert_eq!(&dag.export(*root).unwrap().pool, exported);
+            }
+        }
+        #[test]
+        fn retain_last() {
+            let mut dag = full_dag();
+            let mut history = History::new("full", dag.full_node_ptr(0).unwrap());
+            carve_recorded(&mut dag, &mut history);
+            let allocated_pages = dag.lut.hi();
+            let root = history.root();
+            history.retain_last(1);
+            assert_eq!(history.entries().len(), 1);
+            assert_eq!(history.undo(), None);
+            assert_eq!(history.root(), root);
+            history.collect(&mut dag, &[]).unwrap();
+            assert!(dag.lut.hi() < allocated_pages);
+            let mut history = History::new("full", dag.full_node_ptr(0).unwrap());
+            carve_recorded(&mut dag, &mut history);
+            history.checkout(0).unwrap();
+            history.retain_last(1);
+            assert_eq!(history.entries().len(), SPHERES.len() + 1);
+        }
+    }
+    mod transaction {
+        use super::*;
+        fn state(dag: &BasicHashDAG) -> (Vec<u32>, Vec<u32>, u32, Vec<u8>, u8) {
+            (
+                dag.bucket_len.to_vec(),
+                dag.lut.to_vec(),
+                dag.lut.hi(),
+                dag.tracker.pool_mask.to_vec(),
+                dag.tracker.page_table_mask,
+            )
+        }
+        fn big_sphere() -> Sphere {
+            Sphere::new(&Vector3::repeat(256), 400)
+        }
+        #[test]
+        fn failed_edit_rolls_back() {
+            let mut dag = basic_with_capacity(1).unwrap();
+            dag.tracker.clear();
+            let full = dag.full_node_ptr(0).unwrap();
+            let before = state(&dag);
+            assert_eq!(
+                dag.edit(full, Unlink, &big_sphere()),
+                Err(Error::PoolExhausted)
+            );
+            assert_eq!(state(&dag), before);
+            assert!(dag.journal.is_none());
+        }
+        #[test]
+        fn edit_after_rollback_like_fresh() {
+            let mut dag = basic_with_capacity(1).unwrap();
+            let mut fresh = basic_with_capacity(1).unwrap();
+            let full = dag.full_node_ptr(0).unwrap();
+            assert!(dag.edit(full, Unlink, &big_sphere()).is_err());
+            let shape = Sphere::new(&Vector3::repeat(8), 6);
+            let vptr = dag.edit(full, Unlink, &shape).unwrap();
+            assert_eq!(fresh.edit(full, Unlink, &shape), Ok(vptr));
+            assert_eq!(dag.validate(vptr), Ok(Valid));
+            assert_eq!(dag.bucket_len[..], fresh.bucket_len[..]);
+            assert_eq!(dag.lut[..], fresh.lut[..]);
+        }
+        #[test]
+        fn nested_rolls_back_inner() {
+            let mut dag = full_dag();
+            let bucket_len = dag.bucket_len.to_vec();
+            let leaf = dag
+                .transaction(|dag| {
+                    let leaf = dag.find_or_add_leaf(Pass(&[1, 2]))?;
+                    let inner = dag.transaction(|dag| {
+                        dag.find_or_add_leaf(Pass(&[3, 4]))?;
+                        Err::<(), _>(Error::EmptyDAG)
+                    });
+                    assert_eq!(inner, Err(Error::EmptyDAG));
+                    Ok(leaf)
+                })
+                .unwrap();
+            assert_eq!(dag.find_or_add_leaf(Pass(&[1, 2])), Ok(leaf));
+            let changed = dag
+                .bucket_len
+                .iter()
+                .zip(&bucket_len)
+                .filter(|(new, old)| new != old)
+                .count();
+            assert_eq!(changed, 1);
+            assert!(dag.journal.is_none());
+        }
+    }
+    mod boolean {
+        use super::*;
+        #[test]
+        fn intersection_like_edits() {
+            let mut dag = full_dag();
+            let spheres = [sphere(256, 150), sphere(330, 90)];
+            let carved = carved(&mut dag, &spheres);
+            let both = dag.edit(carved[0], Unlink, &spheres[1]).unwrap();
+            assert_eq!(dag.intersection(carved[0], carved[1]), Ok(both));
+            assert_eq!(dag.intersection(carved[1], carved[0]), Ok(both));
+            assert_eq!(dag.validate(both), Ok(Valid));
+        }
+        #[test]
+        fn identities() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let carved = carved(&mut dag, &[sphere(256, 150), sphere(330, 90)]);
+            let (a, b) = (carved[0], carved[1]);
+            let union = dag.union(a, b).unwrap();
+            let intersection = dag.intersection(a, b).unwrap();
+            let a_only = dag.difference(a, b).unwrap();
+            let b_only = dag.difference(b, a).unwrap();
+            let xor = dag.xor(a, b).unwrap();
+            for vptr in [union, intersection, a_only, b_only, xor] {
+                assert_eq!(dag.validate(vptr), Ok(Valid));
+            }
+            assert_eq!(dag.union(a_only, intersection), Ok(a));
+            assert_eq!(dag.union(a_only, b_only), Ok(xor));
+            assert_eq!(dag.difference(union, intersection), Ok(xor));
+            assert_eq!(dag.xor(xor, b), Ok(a));
+            // De Morgan, with the complements taken against the full node.
+            let (not_a, not_b) = (
+                dag.difference(full, a).unwrap(),
+                dag.difference(full, b).unwrap(),
+            );
+            let not_union = dag.intersection(not_a, not_b).unwrap();
+            assert_eq!(dag.difference(full, not_union), Ok(union));
+            assert_eq!(dag.union(a, not_a), Ok(full));
+            assert_eq!(dag.xor(full, not_a), Ok(a));
+        }
+        #[test]
+        fn empty_results() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let a = carved(&mut dag, &[sphere(256, 150)])[0];
+            let not_a = dag.difference(full, a).unwrap();
+            let bucket_len = dag.bucket_len.to_vec();
+            assert_eq!(dag.xor(a, a), Err(Error::EmptyDAG));
+            assert_eq!(dag.difference(a, full), Err(Error::EmptyDAG));
+            assert_eq!(dag.intersection(a, not_a), Err(Error::EmptyDAG));
+            assert_eq!(dag.bucket_len[..], bucket_len[..]);
+            assert_eq!(dag.union(a, a), Ok(a));
+            assert_eq!(dag.union(a, full), Ok(full));
+        }
+        #[test]
+        fn levels_must_match() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let leaf = dag.full_node_ptr(LEAF_LEVEL).unwrap();
+            assert_eq!(dag.union(full, leaf), Err(Error::InvalidLevel(LEAF_LEVEL)));
+            assert_eq!(dag.union(leaf, leaf), Err(Error::InvalidLevel(LEAF_LEVEL)));
+        }
+    }
+    mod query {
+        use super::*;
+        /// Pseudo random voxels around `sphere(256, 150)`, the same ones every time.
+        fn voxels(count: usize) -> Vec<Vector3<u32>> {
+            let mut random = XorShift::default();
+            (0..count)
+                .map(|_| Vector3::new(random.below(512), random.below(512), random.below(512)))
+                .collect()
+        }
+        #[test]
+        fn contains_like_shape() {
+            let mut dag = full_dag();
+            let shape = sphere(256, 150);
+            let vptr = carved(&mut dag, std::slice::from_ref(&shape))[0];
+            for voxel in voxels(2000) {
+                let carved = shape.collides(&AABB::from(OctVox::new(SUPPORTED_LEVELS, &voxel)));
+                assert_eq!(dag.contains(vptr, &voxel), Ok(!carved), "{voxel}");
+            }
+        }
+        #[test]
+        fn contains_all_like_contains() {
+            let mut dag = full_dag();
+            let vptr = carved(&mut dag, &[sphere(256, 150)])[0];
+            let mut voxels = voxels(2000);
+            voxels.push(Vector3::new(1 << SUPPORTED_LEVELS, 0, 0));
+            let expected: Vec<_> = voxels
+                .iter()
+                .map(|voxel| dag.contains(vptr, voxel).unwrap())
+                .collect();
+            assert!(expected.contains(&true) && expected.contains(&false));
+            assert_eq!(dag.contains_all(vptr, &voxels), Ok(expected.clone()));
+            assert_eq!(dag.view().contains_all(vptr, &voxels), Ok(expected));
+        }
+        #[test]
+        fn contains_below_root() {
+            let mut dag = full_dag();
+            let leaf = dag.find_or_add_leaf(Pass(&[1 << 9, 0])).unwrap();
+            assert_eq!(dag.contains(leaf, &Vector3::new(0, 0, 3)), Ok(true));
+            assert_eq!(dag.contains(leaf, &Vector3::new(0, 0, 2)), Ok(false));
+            assert_eq!(dag.contains(leaf, &Vector3::new(4, 0, 0)), Ok(false));
+            let full = dag.full_node_ptr(0).unwrap();
+            let outside = Vector3::new(0, 1 << SUPPORTED_LEVELS, 0);
+            assert_eq!(dag.contains(full, &outside), Ok(false));
+            assert_eq!(dag.contains(full, &Vector3::repeat(7)), Ok(true));
+        }
+    }
+    mod raycast {
+        use super::*;
+        /// Checks a hit by marching along the ray in small steps, none of which may find a voxel before it.
+        fn verify(
+            dag: &HostOnlyHashDAG,
+            vptr: u32,
+            (origin, direction): (Vector3<f64>, Vector3<f64>),
+        ) {
+            let hit = dag
+                .raycast(vptr, &origin, &direction, 1e4)
+                .unwrap()
+                .expect("Missed.");
+            assert_eq!(dag.contains(vptr, &hit.voxel), Ok(true));
+            let mut t = 0.0;
+            while t < hit.t - 1e-3 {
+                let point = origin + t * direction;
+                if point.iter().all(|&p| 0.0 <= p) {
+                    let voxel = point.map(|p| p.floor() as u32);
+                    assert_eq!(
+                        dag.contains(vptr, &voxel),
+                        Ok(false),
+                        "{voxel} before {hit:?}"
+                    );
+                }
+                t += 0.01;
+            }
+            if hit.normal != Vector3::zeros() {
+                let entered = origin + hit.t * direction;
+                let face = entered + hit.normal.map(f64::from) * 1e-3;
+                assert!(face.map(f64::floor) != hit.voxel.map(f64::from));
+            }
+        }
+        #[test]
+        fn full_node() {
+            let dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let (origin, direction) = (Vector3::new(-10.0, 5.5, 5.5), Vector3::new(1.0, 0.0, 0.0));
+            let expected = Hit {
+                voxel: Vector3::new(0, 5, 5),
+                level: SUPPORTED_LEVELS,
+                t: 10.0,
+                normal: Vector3::new(-1, 0, 0),
+            };
+            assert_eq!(
+                dag.raycast(full, &origin, &direction, 20.0),
+                Ok(Some(expected))
+            );
+            assert_eq!(dag.raycast(full, &origin, &direction, 5.0), Ok(None));
+            assert_eq!(dag.raycast(full, &origin, &-direction, 1e9), Ok(None));
+            let inside = Vector3::new(3.5, 2.5, 1.5);
+            let hit = dag
+                .raycast(full, &inside, &direction, 1.0)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                (hit.voxel, hit.t, hit.normal),
+                (Vector3::new(3, 2, 1), 0.0, Vector3::zeros())
+            );
+        }
+        #[test]
+        fn from_inside_carved_sphere() {
+            let mut dag = full_dag();
+            let vptr = carved(&mut dag, &[sphere(256, 150)])[0];
+            let center = Vector3::repeat(256.5);
+            let directions = [
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, -1.0, 0.0),
+                Vector3::new(0.3, 0.5, -0.8),
+                Vector3::new(-0.6, 0.2, 0.1),
+                Vector3::new(-0.4, -0.4, -0.4),
+            ];
+            for direction in directions {
+                verify(&dag, vptr, (center, direction));
+            }
+            let hit = dag
+                .raycast(vptr, &center, &Vector3::new(1.0, 0.0, 0.0), 1e4)
+                .unwrap()
+                .unwrap();
+            assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+            assert!((149.0..152.0).contains(&hit.t));
+        }
+        #[test]
+        fn grazing_carved_sphere() {
+            let mut dag = full_dag();
+            let vptr = carved(&mut dag, &[sphere(256, 150)])[0];
+            // Starts in a set voxel outside of the sphere, which is hit right away.
+            let origin = Vector3::new(120.5, 120.5, 300.0);
+            let direction = Vector3::new(0.7, 0.7, 0.1);
+            let hit = dag
+                .raycast(vptr, &origin, &direction, 1e4)
+                .unwrap()
+                .unwrap();
+            assert_eq!((hit.t, hit.normal), (0.0, Vector3::zeros()));
+            // Along a diagonal, passing exactly through edges and corners of empty nodes.
+            verify(
+                &dag,
+                vptr,
+                (
+                    Vector3::new(256.0, 256.0, 256.0),
+                    Vector3::new(1.0, 1.0, 0.0),
+                ),
+            );
+        }
+        #[test]
+        fn coarser_level() {
+            let mut dag = full_dag();
+            let vptr = carved(&mut dag, &[sphere(256, 150)])[0];
+            let (origin, direction) = (Vector3::repeat(256.5), Vector3::new(1.0, 0.0, 0.0));
+            let hit = dag
+                .raycast(vptr, &origin, &direction, 1e4)
+                .unwrap()
+                .unwrap();
+            let coarse = dag
+                .raycast_lod(vptr, (&origin, &direction, 1e4), LEAF_LEVEL)
+                .unwrap()
+                .unwrap();
+            assert_eq!(coarse.level, LEAF_LEVEL);
+            assert!(coarse.t <= hit.t);
+            assert_eq!(coarse.voxel.y, hit.voxel.y >> 2);
+            assert_eq!(
+                dag.view().raycast(vptr, &origin, &direction, 1e4),
+                Ok(Some(hit))
+            );
+            assert_eq!(
+                dag.raycast_lod(vptr, (&origin, &direction, 1e4), SUPPORTED_LEVELS + 1),
+                Err(Error::InvalidLevel(SUPPORTED_LEVELS + 1))
+            );
+        }
+    }
+    mod rendering {
+        use super::*;
+        fn small(width: u32, height: u32) -> UserPreferences {
+            UserPreferences {
+                width,
+                height,
+                ..UserPreferences::load("assets/user-pref.ron").unwrap()
+            }
+        }
+        #[test]
+        fn load_user_preferences() {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/user-pref.ron");
+            let preferences = UserPreferences::load(path).unwrap();
+            assert_eq!((preferences.width, preferences.height), (960, 540));
+            assert_eq!(preferences.start_pos_eye, (0, 0, -4096));
+            assert_eq!((preferences.fov, preferences.aperture), (0.2, 0.0004));
+            assert_eq!(preferences.demo, 0);
+        }
+        #[test]
+        fn front_face_of_full_node() {
+            let dag = full_dag();
+            let full = dag.full_node_ptr(SUPPORTED_LEVELS - 10).unwrap();
+            // Square, so that the face fills the view, which is wider than high otherwise.
+            let camera = Camera::from_preferences(&small(18, 18), 1 << 10);
+            assert_eq!(camera.eye, Vector3::new(512.0, 512.0, -3584.0));
+            let depth = camera.render(&*dag, full, Shading::Depth).unwrap();
+            assert_eq!(depth.pixels, vec![255; 18 * 18]);
+            let normal = camera.render(&*dag, full, Shading::Normal).unwrap();
+            assert_eq!(normal.pixel(17, 17), [127, 127, 0]);
+            assert!(normal.pixels.chunks(3).all(|pixel| pixel == [127, 127, 0]));
+            let mut bytes = Vec::new();
+            normal.write(&mut bytes).unwrap();
+            assert!(bytes.starts_with(b"P6\n18 18\n255\n"));
+            assert_eq!(bytes.len(), 13 + 18 * 18 * 3);
+        }
+        #[test]
+        fn lantern_reference_image() {
+            let mut dag = basic_with_capacity((32_000_000 / 4) as _).unwrap();
+            let (root, bd) = add_lantern(&mut dag);
+            let root = root.unwrap();
+            let camera = Camera::from_preferences(&small(48, 27), 1 << bd.levels);
+            let depth = camera.render(&*dag, root, Shading::Depth).unwrap();
+            assert!(depth.pixels.contains(&0));
+            assert!(depth.pixels.contains(&255));
+            // The center ray hits, and the same image comes out of a view of the table.
+            assert_ne!(depth.pixel(24, 13), [0]);
+            assert_eq!(camera.render(&dag.view(), root, Shading::Depth), Ok(depth));
+            let normal = camera.render(&*dag, root, Shading::Normal).unwrap();
+            assert!(normal
+                .pixels
+                .chunks(3)
+                .all(|pixel| pixel == [0; 3] || pixel.iter().filter(|&&c| c == 127).count() == 2));
+            let path = std::env::temp_dir().join("voxel-dags-lantern.ppm");
+            normal.save(&path).unwrap();
+            let mut bytes = Vec::new();
+            normal.write(&mut bytes).unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), bytes);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+    mod voxels {
+        use super::*;
+        #[test]
+        fn leaf_in_order() {
+            let mut dag = full_dag();
+            let leaf = dag.find_or_add_leaf(Pass(&[1 << 9 | 1, 1 << 31])).unwrap();
+            let voxels: Vec<_> = dag.voxels(leaf).unwrap().collect::<Result<_>>().unwrap();
+            let expected = [
+                Vector3::new(0, 0, 0),
+                Vector3::new(0, 0, 3),
+                Vector3::new(3, 3, 3),
+            ];
+            assert_eq!(voxels, expected);
+        }
+        #[test]
+        fn full_node_as_cube() {
+            let dag = full_dag();
+            let full = dag.full_node_ptr(LEAF_LEVEL - 1).unwrap();
+            let cubes: Vec<_> = dag.cubes(full).unwrap().collect::<Result<_>>().unwrap();
+            let expected = Cube {
+                min: Vector3::zeros(),
+                size: 8,
+                level: LEAF_LEVEL - 1,
+            };
+            assert_eq!(cubes, [expected]);
+            let voxels: Vec<_> = dag.voxels(full).unwrap().collect::<Result<_>>().unwrap();
+            assert_eq!(voxels.len(), 512);
+            let first = [(0, 0, 0), (0, 0, 1), (0, 1, 0)].map(|(x, y, z)| Vector3::new(x, y, z));
+            assert_eq!(voxels[..3], first);
+            assert_eq!(voxels[511], Vector3::repeat(7));
+            assert!(voxels.iter().all(|v| dag.contains(full, v) == Ok(true)));
+        }
+        #[test]
+        fn sphere_like_contains() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::repeat(16), 12);
+            let carved = dag.edit(full, Unlink, &shape).unwrap();
+            let sphere = dag.difference(full, carved).unwrap();
+            let mut expected = Vec::new();
+            for x in 0..32 {
+                for y in 0..32 {
+                    for z in 0..32 {
+                        let voxel = Vector3::new(x, y, z);
+                        if dag.contains(sphere, &voxel).unwrap() {
+                            expected.push(voxel);
+                        }
+                    }
+                }
+            }
+            let mut voxels: Vec<_> = dag.voxels(sphere).unwrap().collect::<Result<_>>().unwrap();
+            voxels.sort_unstable_by_key(|v| (v.x, v.y, v.z));
+            assert_eq!(voxels, expected);
+            let cubes: Vec<_> = dag.cubes(sphere).unwrap().collect::<Result<_>>().unwrap();
+            assert!(cubes.iter().any(|cube| cube.level == LEAF_LEVEL));
+            assert!(cubes.len() < voxels.len());
+            let expanded = cubes.into_iter().flat_map(Cube::voxels);
+            assert!(expanded.map(Ok).eq(dag.view().voxels(sphere).unwrap()));
+        }
+        #[test]
+        fn unreadable_node_is_yielded() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let shape = Sphere::new(&Vector3::repeat(16), 12);
+            let carved = dag.edit(full, Unlink, &shape).unwrap();
+            let view = dag.view();
+            let truncated = HashDAGView {
+                pool: &view.pool[..1],
+                ..view
+            };
+            let mut cubes = truncated.cubes(carved).unwrap();
+            assert!(matches!(cubes.next(), Some(Err(Error::OutOfBoundsVptr(_)))));
+            assert_eq!(cubes.next(), None);
+            let mut voxels = truncated.voxels(carved).unwrap();
+            assert!(matches!(
+                voxels.next(),
+                Some(Err(Error::OutOfBoundsVptr(_)))
+            ));
+            assert_eq!(voxels.next(), None);
+        }
+    }
+    mod count_in_aabb {
+        use super::*;
+        fn aabb(min: [i64; 3], max: [i64; 3]) -> AABB {
+            AABB {
+                min: min.into(),
+                max: max.into(),
+            }
+        }
+        #[test]
+        fn full_node() {
+            let dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            assert_eq!(
+                dag.count_in_aabb(full, &aabb([-5, 3, 3], [10, 7, 8])),
+                Ok(200)
+            );
+            assert_eq!(dag.count_in_aabb(full, &aabb([-5, 3, 3], [0, 7, 8])), Ok(0));
+            let leaf = dag.full_node_ptr(LEAF_LEVEL).unwrap();
+            assert_eq!(dag.count_in_aabb(leaf, &aabb([-9; 3], [9; 3])), Ok(64));
+        }
+        #[test]
+        fn carved_sphere_like_contains() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let carved = carved(&mut dag, &[sphere(256, 150)])[0];
+            let (min, max) = ([90, 230, 236], [130, 270, 270]);
+            let mut expected = 0;
+            for x in min[0]..max[0] {
+                for y in min[1]..max[1] {
+                    for z in min[2]..max[2] {
+                        let voxel = Vector3::new(x, y, z).map(|v: i64| v as u32);
+                        expected += u64::from(dag.contains(carved, &voxel).unwrap());
+                    }
+                }
+            }
+            assert!(0 < expected && expected < 40 * 40 * 34);
+            assert_eq!(dag.count_in_aabb(carved, &aabb(min, max)), Ok(expected));
+            // Whatever the edit removed is left over by the difference, so both add up to the whole box.
+            let sphere = dag.difference(full, carved).unwrap();
+            let whole = aabb([0; 3], [512; 3]);
+            let (kept, removed) = (
+                dag.count_in_aabb(carved, &whole).unwrap(),
+                dag.count_in_aabb(sphere, &whole).unwrap(),
+            );
+            assert_eq!(kept + removed, 512 * 512 * 512);
+            let box_around = aabb([100; 3], [412; 3]);
+            assert_eq!(dag.count_in_aabb(sphere, &box_around), Ok(removed));
+        }
+        #[test]
+        fn small_sphere_like_voxels() {
+            let mut dag = full_dag();
+            let full = dag.full_node_ptr(0).unwrap();
+            let carved = dag
+                .edit(full, Unlink, &Sphere::new(&Vector3::repeat(16), 12))
+                .unwrap();
+            let sphere = dag.difference(full, carved).unwrap();
+            let half = aabb([0, 0, 0], [16, 32, 21]);
+            let expected = dag
+                .voxels(sphere)
+                .unwrap()
+                .map(Result::unwrap)
+                .filter(|v| v.x < 16 && v.z < 21)
+                .count();
+            assert_eq!(dag.count_in_aabb(sphere, &half), Ok(expected as u64));
+            assert_eq!(dag.view().count_in_aabb(sphere, &half), Ok(expected as u64));
+        }
+    }
 }
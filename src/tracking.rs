@@ -1,17 +1,45 @@
 use super::{
     constants::{PAGE_LEN, TOTAL_PAGES},
-    hash_table::basic::HashTable,
-    Result, SharedHashDAG,
+    hash_table::{basic::HashTable, config::HashTableConfig},
+    Error, Result, SharedHashDAG,
 };
 use ::std::ops::Range;
 
 pub trait Tracker {
+    /// Creates a tracker fitting the geometry of a table.
+    #[inline]
+    #[must_use]
+    fn for_config(_config: &HashTableConfig) -> Self
+    where
+        Self: Default,
+    {
+        Self::default()
+    }
     fn register(&mut self, vptr: u32, range: Range<usize>) -> Result<()>;
+    /// Marks the whole pool of `pool_len` words and the page table as modified, e.g. after the pool has been compacted.
+    fn register_all(&mut self, pool_len: usize);
+    /// Called once the pool has moved into a new segment of `pool_len` words, which has to be staged as a whole.
+    #[inline]
+    fn register_grown(&mut self, pool_len: usize) {
+        self.register_all(pool_len);
+    }
     fn clear(&mut self);
+    /// Starts remembering registrations, so that `rollback` can undo them.
+    #[inline]
+    fn begin(&mut self) {}
+    /// Forgets what has been remembered since `begin`.
+    #[inline]
+    fn commit(&mut self) {}
+    /// Undoes the registrations since `begin`. Keeping them, as by default, only stages more than needed.
+    #[inline]
+    fn rollback(&mut self) {}
 }
 
 pub mod basic {
-    use super::{HashTable, Range, Result, SharedHashDAG, Tracker, PAGE_LEN, TOTAL_PAGES};
+    use super::{
+        Error, HashTable, HashTableConfig, Range, Result, SharedHashDAG, Tracker, PAGE_LEN,
+        TOTAL_PAGES,
+    };
 
     pub type BasicHashDAG<'shmem> = SharedHashDAG<HashTable<'shmem>, BasicTracker>;
 
@@ -22,11 +50,18 @@ pub mod basic {
     pub const POOL_MASK_BIT_LEN: usize = PAGE_LEN as usize;
     pub const LUT_MASK_BIT_LEN: usize = TOTAL_PAGES as usize / LUT_MASK_BITS;
 
+    #[derive(Clone)]
     pub struct BasicTracker {
         /// The pool mask is a collection of words with each bit representing a complete page.
         pub pool_mask: Box<[PoolMask]>,
         /// The page table mask is a single word with each bit representing a partition of the page table.
         pub page_table_mask: PageTableMask,
+        /// The words of the pool represented by a bit of the pool mask: a page.
+        pub pool_mask_bit_len: usize,
+        /// The entries of the page table represented by a bit of the page table mask.
+        pub lut_mask_bit_len: usize,
+        /// Between `begin` and `commit`: the page table mask and every changed byte of the pool mask, as before.
+        undo: Option<(PageTableMask, Vec<(usize, PoolMask)>)>,
     }
 
     impl Default for BasicTracker {
@@ -35,26 +70,72 @@ pub mod basic {
             Self {
                 pool_mask: vec![0; TOTAL_PAGES as usize / POOL_MASK_BITS].into_boxed_slice(),
                 page_table_mask: 0,
+                pool_mask_bit_len: POOL_MASK_BIT_LEN,
+                lut_mask_bit_len: LUT_MASK_BIT_LEN,
+                undo: None,
             }
         }
     }
 
     impl Tracker for BasicTracker {
+        #[inline]
+        fn for_config(config: &HashTableConfig) -> Self {
+            let total_pages = config.total_pages() as usize;
+            Self {
+                pool_mask: vec![0; total_pages / POOL_MASK_BITS].into_boxed_slice(),
+                page_table_mask: 0,
+                pool_mask_bit_len: config.page_len as _,
+                lut_mask_bit_len: total_pages / LUT_MASK_BITS,
+                undo: None,
+            }
+        }
         #[inline]
         fn register(&mut self, vptr: u32, range: Range<usize>) -> Result<()> {
-            let idx = range.start / POOL_MASK_BIT_LEN;
-            if idx != (range.end - 1) / POOL_MASK_BIT_LEN {
-                return Err("Cannot register a range spanning beyond a page.".into());
+            let idx = range.start / self.pool_mask_bit_len;
+            if idx != (range.end - 1) / self.pool_mask_bit_len {
+                return Err(Error::InvalidRange(range));
+            }
+            let (byte, bit) = (idx / POOL_MASK_BITS, 1 << (idx % POOL_MASK_BITS));
+            if let Some((_, changed)) = &mut self.undo {
+                if self.pool_mask[byte] & bit == 0 {
+                    changed.push((byte, self.pool_mask[byte]));
+                }
             }
-            self.pool_mask[idx / POOL_MASK_BITS] |= 1 << (idx % POOL_MASK_BITS);
-            self.page_table_mask |= 1 << (vptr / PAGE_LEN / LUT_MASK_BIT_LEN as u32);
+            self.pool_mask[byte] |= bit;
+            let page = vptr as usize / self.pool_mask_bit_len;
+            self.page_table_mask |= 1 << (page / self.lut_mask_bit_len);
             Ok(())
         }
+        /// Cannot be rolled back, e.g. as the pool has moved. Pages beyond the pool stay unmarked,
+        /// as there is nothing to stage for them.
+        #[inline]
+        fn register_all(&mut self, pool_len: usize) {
+            self.undo = None;
+            let pages = pool_len / self.pool_mask_bit_len;
+            self.pool_mask[..pages / POOL_MASK_BITS].fill(!0);
+            self.page_table_mask = !0;
+        }
         #[inline]
         fn clear(&mut self) {
-            self.pool_mask = vec![0; TOTAL_PAGES as usize / POOL_MASK_BITS].into_boxed_slice();
+            self.pool_mask.fill(0);
             self.page_table_mask = 0;
         }
+        #[inline]
+        fn begin(&mut self) {
+            self.undo = Some((self.page_table_mask, Vec::new()));
+        }
+        #[inline]
+        fn commit(&mut self) {
+            self.undo = None;
+        }
+        fn rollback(&mut self) {
+            if let Some((page_table_mask, changed)) = self.undo.take() {
+                self.page_table_mask = page_table_mask;
+                for (byte, mask) in changed.into_iter().rev() {
+                    self.pool_mask[byte] = mask;
+                }
+            }
+        }
     }
 }
 
@@ -71,7 +152,7 @@ pub mod dummy {
         HostOnlyHashDAG::blank(root, capacity, None)
     }
 
-    #[derive(Debug, Default)]
+    #[derive(Debug, Default, Clone)]
     pub struct DummyTracker;
 
     impl Tracker for DummyTracker {
@@ -80,6 +161,8 @@ pub mod dummy {
             Ok(())
         }
         #[inline]
+        fn register_all(&mut self, _: usize) {}
+        #[inline]
         fn clear(&mut self) {}
     }
 }
@@ -1,10 +1,4 @@
-use super::{
-    constants::{
-        BUCKETS_PER_HI_LEVEL, BUCKETS_PER_LO_LEVEL, HI_BUCKET_LEN, HI_LEVELS, LO_BUCKET_LEN, SEED,
-        SUPPORTED_LEVELS, TOTAL_BUCKETS, TOTAL_HI_BUCKETS, TOTAL_VIRT_SPACE,
-    },
-    Result,
-};
+use super::{constants::SEED, hash_table::config::HashTableConfig, Error, Result};
 use nalgebra::Vector3;
 use std::num::Wrapping;
 
@@ -14,8 +8,8 @@ pub mod shmem {
     use ::{
         shared_memory::{Shmem, ShmemConf, ShmemError},
         std::{
-            marker::PhantomPinned, mem::size_of, ops::Deref, path::Path, pin::Pin,
-            slice::from_raw_parts_mut,
+            fs::write, marker::PhantomPinned, mem::size_of, ops::Deref, path::Path, pin::Pin,
+            slice::from_raw_parts_mut, sync::atomic::AtomicU32,
         },
     };
 
@@ -25,6 +19,15 @@ pub mod shmem {
         _pin: PhantomPinned,
     }
 
+    // SAFETY: `Shmem` only holds the mapping, which is valid on every thread of the process,
+    // so it may be used and released from any of them. Through `&ShmemArray` the memory is only read,
+    // accessed through atomics or written by `copy_from_shared`, whose callers guarantee that no other thread
+    // accesses the same elements meanwhile.
+    #[allow(clippy::non_send_fields_in_send_ty)]
+    unsafe impl<T: Send> Send for ShmemArray<'_, T> {}
+    // SAFETY: see `Send`.
+    unsafe impl<T: Sync> Sync for ShmemArray<'_, T> {}
+
     impl<T> Deref for ShmemArray<'_, T> {
         type Target = [T];
         fn deref(&self) -> &Self::Target {
@@ -38,6 +41,9 @@ pub mod shmem {
             flink: Option<S>,
         ) -> Result<Pin<Box<Self>>, ShmemError> {
             let conf = flink.map_or_else(ShmemConf::new, |path| ShmemConf::new().flink(path));
+            Self::create(conf, len)
+        }
+        fn create(conf: ShmemConf, len: usize) -> Result<Pin<Box<Self>>, ShmemError> {
             let mut raw = Self {
                 inner: conf.size(len * size_of::<T>()).create()?,
                 slice: &mut [],
@@ -48,6 +54,39 @@ pub mod shmem {
             raw.slice = unsafe { from_raw_parts_mut(raw.inner.as_ptr().cast::<T>(), len) };
             Ok(Box::pin(raw))
         }
+        /// Opens shared memory created elsewhere, e.g. by another process. Its length follows from its size.
+        /// The memory is not removed when this array is dropped.
+        pub fn open<S: AsRef<Path>>(flink: S) -> Result<Pin<Box<Self>>, ShmemError> {
+            let mut raw = Self {
+                inner: ShmemConf::new().flink(flink).open()?,
+                slice: &mut [],
+                _pin: PhantomPinned,
+            };
+            let len = raw.inner.len() / size_of::<T>();
+            // SAFETY: the data pointed to will be pinned without move
+            // SAFETY: the pool is one allocated object
+            raw.slice = unsafe { from_raw_parts_mut(raw.inner.as_ptr().cast::<T>(), len) };
+            Ok(Box::pin(raw))
+        }
+        /// Like `open`, mapping the memory read-only where the OS allows it, i.e. on unix.
+        /// Writing to it then faults, so it must never be written through this array.
+        pub fn open_read_only<S: AsRef<Path>>(flink: S) -> Result<Pin<Box<Self>>, ShmemError> {
+            let array = Self::open(flink)?;
+            #[cfg(unix)]
+            {
+                use nix::sys::mman::{mprotect, ProtFlags};
+                // SAFETY: the mapping starts at `as_ptr` and spans `len` bytes, nothing else is affected.
+                unsafe {
+                    mprotect(
+                        array.inner.as_ptr().cast(),
+                        array.inner.len(),
+                        ProtFlags::PROT_READ,
+                    )
+                }
+                .map_err(|errno| ShmemError::MapOpenFailed(errno as u32))?;
+            }
+            Ok(array)
+        }
         #[inline]
         /// # Safety
         ///
@@ -66,10 +105,209 @@ pub mod shmem {
         pub fn copy_from(self: &mut Pin<Box<Self>>, offset: usize, slice: &[T]) {
             self.as_mut().mut_copy_from(offset, slice);
         }
+        #[inline]
+        /// Copies through a shared reference, so other threads can keep reading (other parts of) the array.
+        ///
+        /// # Safety
+        ///
+        /// - No other thread may read or write `offset..offset + slice.len()` at the same time.
+        pub unsafe fn copy_from_shared(&self, offset: usize, slice: &[T]) {
+            assert!(offset + slice.len() <= self.slice.len());
+            let dst = self.inner.as_ptr().cast::<T>().add(offset);
+            dst.copy_from_nonoverlapping(slice.as_ptr(), slice.len());
+        }
+        /// Moves the first `keep` elements into a new segment of `len` elements, under the same flink.
+        /// Memory opened elsewhere keeps mapping the old segment.
+        /// The old segment is only released once the new one exists, so on error nothing changes.
+        pub fn resize(
+            self: &mut Pin<Box<Self>>,
+            len: usize,
+            keep: usize,
+        ) -> Result<(), ShmemError> {
+            let Some(flink) = self.inner.get_flink_path().cloned() else {
+                let mut resized = Self::new(len, None::<&Path>)?;
+                resized.copy_from(0, &self[..keep]);
+                *self = resized;
+                return Ok(());
+            };
+            let old_id = self.inner.get_os_id().to_owned();
+            // The flink is pointed at the new segment while the old one is still alive.
+            let conf = ShmemConf::new().flink(&flink).force_create_flink();
+            let mut resized = match Self::create(conf, len) {
+                Ok(resized) => resized,
+                Err(error) => {
+                    // Creating the flink may have truncated or removed it, so it is written again.
+                    let _ = write(&flink, &old_id);
+                    return Err(error);
+                }
+            };
+            resized.copy_from(0, &self[..keep]);
+            // SAFETY: the old segment is only released, the array is not moved.
+            let old = unsafe { self.as_mut().get_unchecked_mut() };
+            // Dropping the old segment as owner would remove the flink, which now names the new one.
+            if old.inner.set_owner(false) {
+                #[cfg(unix)]
+                let _ = nix::sys::mman::shm_unlink(old_id.as_str());
+            }
+            *self = resized;
+            Ok(())
+        }
+        #[inline]
+        #[must_use]
+        /// Whether this array created the shared memory, rather than opening it.
+        pub fn is_owner(&self) -> bool {
+            self.inner.is_owner()
+        }
+    }
+
+    impl ShmemArray<'_, u32> {
+        #[inline]
+        #[must_use]
+        /// Views a word as an atomic, for words which are accessed by several threads.
+        pub fn atomic(&self, idx: usize) -> &AtomicU32 {
+            assert!(idx < self.slice.len());
+            // SAFETY: the word is aligned and lives as long as the shared memory does.
+            unsafe { &*self.inner.as_ptr().cast::<AtomicU32>().add(idx) }
+        }
+    }
+}
+
+/// Words kept in a file mapped into memory, so the OS can page them in and out.
+#[cfg(unix)]
+pub mod mmap {
+    #![allow(clippy::cast_ptr_alignment)]
+    use super::Result;
+    use ::{
+        nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags},
+        std::{
+            fs::{remove_file, File, OpenOptions},
+            io,
+            mem::{replace, size_of},
+            num::NonZeroUsize,
+            ops::Deref,
+            os::unix::io::AsRawFd,
+            path::{Path, PathBuf},
+            ptr::NonNull,
+            slice::{from_raw_parts, from_raw_parts_mut},
+            sync::atomic::AtomicU32,
+        },
+    };
+
+    pub struct MmapArray {
+        ptr: NonNull<u32>,
+        len: usize,
+        file: File,
+        path: PathBuf,
+    }
+
+    // SAFETY: the array owns its mapping, which `ptr` points to and which is valid on every thread.
+    // Through `&MmapArray` the words are only read, accessed through atomics or written by `copy_from_shared`,
+    // whose callers guarantee that no other thread accesses the same words meanwhile.
+    unsafe impl Send for MmapArray {}
+    // SAFETY: see `Send`.
+    unsafe impl Sync for MmapArray {}
+
+    impl Deref for MmapArray {
+        type Target = [u32];
+        #[inline]
+        fn deref(&self) -> &Self::Target {
+            // SAFETY: the mapping spans `len` words until it is dropped.
+            unsafe { from_raw_parts(self.ptr.as_ptr(), self.len) }
+        }
+    }
+
+    impl Drop for MmapArray {
+        #[inline]
+        fn drop(&mut self) {
+            // SAFETY: nothing borrows the mapping anymore.
+            unsafe { munmap(self.ptr.as_ptr().cast(), self.len * size_of::<u32>()) }.ok();
+            remove_file(&self.path).unwrap_or_default();
+        }
+    }
+
+    impl MmapArray {
+        /// Creates the file at `path` with `len` zeroed words and maps it. Any existing file is truncated.
+        /// The file is removed once the array is dropped.
+        pub fn create<P: AsRef<Path>>(path: P, len: usize) -> Result<Self> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)?;
+            file.set_len((len * size_of::<u32>()) as _)?;
+            Ok(Self {
+                ptr: Self::map(&file, len)?,
+                len,
+                file,
+                path: path.as_ref().to_owned(),
+            })
+        }
+        fn map(file: &File, len: usize) -> Result<NonNull<u32>> {
+            let size = NonZeroUsize::new(len * size_of::<u32>())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+            let prot = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+            // SAFETY: the file is only mapped by this array, whose mappings all share the file's pages.
+            let ptr = unsafe { mmap(None, size, prot, MapFlags::MAP_SHARED, file.as_raw_fd(), 0) }
+                .map_err(io::Error::from)?;
+            Ok(NonNull::new(ptr.cast()).expect("mmap returned a null pointer."))
+        }
+        #[inline]
+        pub fn copy_from(&mut self, offset: usize, slice: &[u32]) {
+            // SAFETY: the mapping spans `len` words and is borrowed mutably.
+            let words = unsafe { from_raw_parts_mut(self.ptr.as_ptr(), self.len) };
+            words[offset..offset + slice.len()].copy_from_slice(slice);
+        }
+        /// Copies through a shared reference, so other threads can keep reading (other parts of) the array.
+        ///
+        /// # Safety
+        ///
+        /// - No other thread may read or write `offset..offset + slice.len()` at the same time.
+        #[inline]
+        pub unsafe fn copy_from_shared(&self, offset: usize, slice: &[u32]) {
+            assert!(offset + slice.len() <= self.len);
+            let dst = self.ptr.as_ptr().add(offset);
+            dst.copy_from_nonoverlapping(slice.as_ptr(), slice.len());
+        }
+        #[inline]
+        #[must_use]
+        /// Views a word as an atomic, for words which are accessed by several threads.
+        pub fn atomic(&self, idx: usize) -> &AtomicU32 {
+            assert!(idx < self.len);
+            // SAFETY: the word is aligned and lives as long as the mapping does.
+            unsafe { &*self.ptr.as_ptr().cast::<AtomicU32>().add(idx) }
+        }
+        /// Resizes the file to `len` words and maps it again. The contents stay in place, new words are zeroed.
+        /// The new mapping is created before the old one is unmapped, so a failure leaves the array as it was.
+        pub fn resize(&mut self, len: usize) -> Result<()> {
+            let (old_len, size) = (self.len, |len: usize| (len * size_of::<u32>()) as u64);
+            if old_len < len {
+                // The old mapping only spans the start of the file, which growing leaves in place.
+                self.file.set_len(size(len))?;
+            }
+            let ptr = match Self::map(&self.file, len) {
+                Ok(ptr) => ptr,
+                Err(error) => {
+                    if old_len < len {
+                        self.file.set_len(size(old_len)).ok();
+                    }
+                    return Err(error);
+                }
+            };
+            let old_ptr = replace(&mut self.ptr, ptr);
+            self.len = len;
+            // SAFETY: the array is borrowed mutably, so nothing refers to the old mapping anymore.
+            unsafe { munmap(old_ptr.as_ptr().cast(), size(old_len) as _) }
+                .map_err(io::Error::from)?;
+            if len < old_len {
+                self.file.set_len(size(len))?;
+            }
+            Ok(())
+        }
     }
 }
 
-fn murmur_hash_64(h: u64) -> u64 {
+pub(crate) fn murmur_hash_64(h: u64) -> u64 {
     let mut h = Wrapping(h);
     h ^= h >> 33;
     h *= Wrapping(0xff51_afd7_ed55_8ccd);
@@ -79,8 +317,8 @@ fn murmur_hash_64(h: u64) -> u64 {
     h.0
 }
 
-fn murmur_hash_32(node: &[u32]) -> u32 {
-    let mut h = Wrapping(SEED);
+pub(crate) fn murmur_hash_32(seed: u32, node: &[u32]) -> u32 {
+    let mut h = Wrapping(seed);
     let n = Wrapping(node.len());
     for &k in node {
         let mut k = Wrapping(k);
@@ -103,16 +341,7 @@ fn murmur_hash_32(node: &[u32]) -> u32 {
 
 #[must_use]
 pub fn new_bucket_len_idx_u32(level: u32, bucket: u32) -> u32 {
-    debug_assert!(level < SUPPORTED_LEVELS);
-    let idx = if level < HI_LEVELS {
-        debug_assert!(bucket < BUCKETS_PER_HI_LEVEL);
-        level * BUCKETS_PER_HI_LEVEL
-    } else {
-        debug_assert!(bucket < BUCKETS_PER_LO_LEVEL);
-        TOTAL_HI_BUCKETS + (level - HI_LEVELS) * BUCKETS_PER_LO_LEVEL
-    } + bucket;
-    debug_assert!(idx < TOTAL_BUCKETS);
-    idx
+    HashTableConfig::DEFAULT.new_bucket_len_idx_u32(level, bucket)
 }
 
 #[inline]
@@ -124,64 +353,31 @@ pub fn new_bucket_len_idx(level: u32, bucket: u32) -> usize {
 #[inline]
 #[must_use]
 pub fn buckets_per_level(level: u32) -> u32 {
-    debug_assert!(level < SUPPORTED_LEVELS);
-    if level < HI_LEVELS {
-        BUCKETS_PER_HI_LEVEL
-    } else {
-        BUCKETS_PER_LO_LEVEL
-    }
+    HashTableConfig::DEFAULT.buckets_per_level(level)
 }
 
 #[inline]
 #[must_use]
 pub fn new_bucket_len(level: u32) -> u32 {
-    debug_assert!(level < SUPPORTED_LEVELS);
-    if level < HI_LEVELS {
-        HI_BUCKET_LEN
-    } else {
-        LO_BUCKET_LEN
-    }
+    HashTableConfig::DEFAULT.new_bucket_len(level)
 }
 
 #[inline]
 /// The supplied hash is bounded to the range [0-b[ with b := correct number of buckets at this level
 #[must_use]
 pub fn bucket_from_hash(level: u32, hash: u32) -> u32 {
-    hash & (buckets_per_level(level) - 1) // TODO could use modulo too, but is more expensive, curious about distribution though
+    HashTableConfig::DEFAULT.bucket_from_hash(level, hash)
 }
 
-const HI: u32 = TOTAL_HI_BUCKETS * HI_BUCKET_LEN;
 /// This returns the virtual pointer composed exclusively out of all three given parameters
 pub fn new_vptr(level: u32, bucket: u32, offset_bucket: u32) -> Result<u32> {
-    debug_assert!(level < SUPPORTED_LEVELS);
-    let idx = if level < HI_LEVELS {
-        if HI_BUCKET_LEN <= offset_bucket {
-            return Err("Creating pointer: Bucket offset out of bounds! (Top level)".into());
-        } else {
-            new_bucket_len_idx_u32(level, bucket) * HI_BUCKET_LEN
-        }
-    } else if LO_BUCKET_LEN <= offset_bucket {
-        return Err("Creating pointer: Bucket offset out of bounds! (Low level)".into());
-    } else if BUCKETS_PER_LO_LEVEL <= bucket {
-        return Err("Creating pointer: Bucket too large!".into());
-    } else {
-        let lo = ((level - HI_LEVELS) * BUCKETS_PER_LO_LEVEL + bucket) * LO_BUCKET_LEN;
-        HI + lo
-    } + offset_bucket;
-    if idx < TOTAL_VIRT_SPACE {
-        Ok(idx)
-    } else {
-        Err("Creating pointer: Virtual pointer is out of bounds!".into())
-    }
+    HashTableConfig::DEFAULT.new_vptr(level, bucket, offset_bucket)
 }
 
 #[inline]
 #[must_use]
 pub const fn vptr_to_lvl(vptr: u32) -> u32 {
-    let hi = vptr / (BUCKETS_PER_HI_LEVEL * HI_BUCKET_LEN);
-    let lo = vptr.overflowing_sub(HI).0 / (BUCKETS_PER_LO_LEVEL * LO_BUCKET_LEN) + HI_LEVELS;
-    let is_hi_lvl = (vptr < HI) as u32;
-    is_hi_lvl * hi + (1 - is_hi_lvl) * lo
+    HashTableConfig::DEFAULT.vptr_to_lvl(vptr)
 }
 
 #[inline]
@@ -191,14 +387,14 @@ pub fn hash_leaf(leaf: &[u32]) -> u32 {
 }
 
 #[inline]
-const fn as_leaf_mask(leaf: &[u32]) -> u64 {
+pub(crate) const fn as_leaf_mask(leaf: &[u32]) -> u64 {
     (leaf[1] as u64) << 32 | leaf[0] as u64
 }
 
 #[inline]
 #[must_use]
 pub fn hash_interior(node: &[u32]) -> u32 {
-    murmur_hash_32(node)
+    murmur_hash_32(SEED, node)
 }
 
 #[inline]
@@ -232,62 +428,108 @@ pub const fn bottom_child_mask(leaf: &[u32], child: u32) -> u8 {
     (leaf[(4 <= child) as usize] >> ((child & 3) * 8)) as u8
 }
 
+/// A xorshift generator of pseudo random numbers, which are the same every time, e.g. for scenes in tests and benchmarks.
+#[derive(Debug, Clone)]
+pub struct XorShift(u32);
+
+impl Default for XorShift {
+    #[inline]
+    fn default() -> Self {
+        Self(0x2545_f491)
+    }
+}
+
+impl XorShift {
+    /// The next number below `bound`.
+    #[inline]
+    pub const fn below(&mut self, bound: u32) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 % bound
+    }
+}
+
 pub mod serialization {
+    use super::{Error, Result};
     use serde::de::DeserializeOwned;
     use std::{
         fs::File,
-        io::{BufReader, Read},
+        io::{self, BufReader, Read, Write},
         mem::size_of,
+        path::Path,
     };
 
+    /// Reads `assets/{name}.ron`, relative to the current directory.
+    #[inline]
+    pub fn load_ron<T: DeserializeOwned>(name: &str) -> Result<T> {
+        read_ron(format!("assets/{name}.ron"))
+    }
+
     #[inline]
-    pub fn load_ron<T: DeserializeOwned>(name: &str) -> Result<T, String> {
-        ron::de::from_reader(&mut BufReader::new(
-            File::open(format!("assets/{name}.ron")).unwrap(),
-        ))
-        .map_err(|e| format!("Failed to parse {name}.ron, reason: {e}"))
+    pub fn read_ron<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
+        let path = path.as_ref();
+        ron::de::from_reader(&mut BufReader::new(File::open(path)?))
+            .map_err(|e| Error::Format(format!("Failed to parse {}, reason: {e}", path.display())))
     }
 
     #[inline]
-    pub fn read_size<R: Read>(file: &mut R) -> Option<usize> {
+    pub fn read_size<R: Read>(file: &mut R) -> io::Result<usize> {
         let mut bytes = [0; 8];
-        file.read_exact(&mut bytes).ok()?;
-        Some(u64::from_le_bytes(bytes) as usize)
+        file.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes) as usize)
     }
 
     #[inline]
-    pub fn read_word<R: Read>(file: &mut R) -> Option<u32> {
+    pub fn read_word<R: Read>(file: &mut R) -> io::Result<u32> {
         let mut bytes = [0; 4];
-        file.read_exact(&mut bytes).ok()?;
-        Some(u32::from_le_bytes(bytes))
+        file.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
     }
 
     #[inline]
-    pub fn read_boxed_slice<R: Read, T: Sized + Clone>(file: &mut R) -> Option<Box<[T]>> {
-        let mut bytes = vec![0; size_of::<T>() * read_size(file)?];
-        file.read_exact(&mut bytes).ok()?;
-        // SAFETY: bytes is a single allocated object with the correct length for alignment.
-        Some(unsafe { bytes.align_to::<T>() }.1.iter().cloned().collect())
+    pub fn read_boxed_slice<R: Read, T: Sized + Clone>(file: &mut R) -> io::Result<Box<[T]>> {
+        let size = read_size(file)?;
+        read_exact_slice(file, size)
     }
 
     #[inline]
     pub fn read_exact_slice<R: Read, T: Sized + Clone>(
         file: &mut R,
         size: usize,
-    ) -> Option<Box<[T]>> {
+    ) -> io::Result<Box<[T]>> {
         let mut bytes = vec![0; size_of::<T>() * size];
-        file.read_exact(&mut bytes).ok()?;
+        file.read_exact(&mut bytes)?;
         // SAFETY: bytes is a single allocated object with the correct length for alignment.
-        Some(unsafe { bytes.align_to::<T>() }.1.iter().cloned().collect())
+        Ok(unsafe { bytes.align_to::<T>() }.1.iter().cloned().collect())
+    }
+
+    #[inline]
+    pub fn write_size<W: Write>(file: &mut W, size: usize) -> io::Result<()> {
+        file.write_all(&(size as u64).to_le_bytes())
+    }
+
+    #[inline]
+    pub fn write_word<W: Write>(file: &mut W, word: u32) -> io::Result<()> {
+        file.write_all(&word.to_le_bytes())
+    }
+
+    #[inline]
+    pub fn write_slice<W: Write>(file: &mut W, slice: &[u32]) -> io::Result<()> {
+        write_size(file, slice.len())?;
+        slice.iter().try_for_each(|&word| write_word(file, word))
     }
 }
 
 mod tests {
     #[allow(unused_imports)]
-    use super::{murmur_hash_32, murmur_hash_64};
+    use super::{murmur_hash_32, murmur_hash_64, SEED};
 
     #[test]
     fn valid_murmur_hash_32() {
-        assert_ne!(murmur_hash_32(&[123, 123]), murmur_hash_32(&[123]));
+        assert_ne!(
+            murmur_hash_32(SEED, &[123, 123]),
+            murmur_hash_32(SEED, &[123])
+        );
     }
 }
@@ -4,10 +4,9 @@ use self::{
     Validation::{Invalid, Valid},
 };
 use super::{
-    constants::{COLOR_TREE_LEVELS, LEAF_LEVEL},
-    hash_table::basic::HashTable,
-    utils::{hash_interior, hash_leaf, vptr_to_lvl},
-    HashDAG, Result,
+    hash_table::{basic::HashTable, config::HashTableConfig},
+    hashing::NodeHasher,
+    Error, HashDAG, Result,
 };
 use ::std::ops::Deref;
 
@@ -18,13 +17,11 @@ pub struct LevelInfo {
 }
 
 impl LevelInfo {
+    /// The level info in a table of the default geometry, see `HashTableConfig::level_info` otherwise.
     #[inline]
     #[must_use]
     pub const fn new(level: u32) -> Self {
-        Self {
-            is_color_tree_level: COLOR_TREE_LEVELS <= level,
-            is_last_interior: level + 1 == LEAF_LEVEL,
-        }
+        HashTableConfig::DEFAULT.level_info(level)
     }
 }
 
@@ -58,7 +55,7 @@ impl<'pool> Node<'pool> {
     pub fn validated_as_leaf(self) -> Result<Node<'pool>> {
         match validate_leaf(self)? {
             Valid => Ok(self.validated()),
-            Invalid(msg) => Err(msg),
+            Invalid(msg) => Err(Error::InvalidNode(msg)),
         }
     }
     #[inline]
@@ -69,18 +66,18 @@ impl<'pool> Node<'pool> {
     ) -> Result<Node<'pool>> {
         match validate_interior(dag, self, level_info)? {
             Valid => Ok(self.validated()),
-            Invalid(msg) => Err(msg),
+            Invalid(msg) => Err(Error::InvalidNode(msg)),
         }
     }
     #[inline]
     #[must_use]
-    pub fn hash_as_leaf(self) -> u32 {
-        hash_leaf(*self)
+    pub fn hash_as_leaf<H: NodeHasher>(self, hasher: &H) -> u32 {
+        hasher.hash_leaf(*self)
     }
     #[inline]
     #[must_use]
-    pub fn hash_as_interior(self) -> u32 {
-        hash_interior(*self)
+    pub fn hash_as_interior<H: NodeHasher>(self, hasher: &H) -> u32 {
+        hasher.hash_interior(*self)
     }
 }
 
@@ -95,36 +92,49 @@ pub trait Validator {
 }
 
 impl Validator for HashTable<'_> {
+    #[inline]
     fn validate(&self, vptr: u32) -> Result<Validation> {
-        let mut visited = vec![false; self.pool.len()].into_boxed_slice();
-        let mut items = Vec::with_capacity(self.pool.len() / 16);
-        items.push(vptr);
-        for level_info in (vptr_to_lvl(vptr)..LEAF_LEVEL).map(LevelInfo::new) {
-            let mut new_items = Vec::with_capacity(items.capacity());
-            while let Some(vptr) = items.pop() {
-                let interior = self.interior(vptr)?;
-                match Strict(interior).validated_as_interior(self, level_info) {
-                    Ok(node) => {
-                        for &vptr in node.iter().skip(1) {
-                            let pool_idx = self.pool_idx(vptr)?;
-                            if !visited[pool_idx] {
-                                visited[pool_idx] = true;
-                                new_items.push(vptr);
-                            }
+        validate(self, vptr, |vptr| self.pool_idx(vptr))
+    }
+}
+
+/// Validates the DAG spanned by `vptr`, visiting every node once by its index in the pool.
+pub(crate) fn validate<DAG, F>(dag: &DAG, vptr: u32, pool_idx: F) -> Result<Validation>
+where
+    DAG: HashDAG,
+    F: Fn(u32) -> Result<usize>,
+{
+    let pool_len = dag.dump().0.len();
+    let mut visited = vec![false; pool_len].into_boxed_slice();
+    let mut items = Vec::with_capacity(pool_len / 16);
+    items.push(vptr);
+    let config = dag.config();
+    let levels = config.vptr_to_lvl(vptr)..config.leaf_level();
+    for level_info in levels.map(|level| config.level_info(level)) {
+        let mut new_items = Vec::with_capacity(items.capacity());
+        while let Some(vptr) = items.pop() {
+            let interior = dag.interior(vptr)?;
+            match Strict(interior).validated_as_interior(dag, level_info) {
+                Ok(node) => {
+                    for &vptr in node.iter().skip(1) {
+                        let pool_idx = pool_idx(vptr)?;
+                        if !visited[pool_idx] {
+                            visited[pool_idx] = true;
+                            new_items.push(vptr);
                         }
                     }
-                    Err(msg) => return Ok(Invalid(msg)),
                 }
+                Err(error) => return Ok(Invalid(error.to_string())),
             }
-            items = new_items;
         }
-        while let Some(vptr) = items.pop() {
-            if let Err(msg) = Strict(self.leaf(vptr)?).validated_as_leaf() {
-                return Ok(Invalid(msg));
-            }
+        items = new_items;
+    }
+    while let Some(vptr) = items.pop() {
+        if let Err(error) = Strict(dag.leaf(vptr)?).validated_as_leaf() {
+            return Ok(Invalid(error.to_string()));
         }
-        Ok(Valid)
     }
+    Ok(Valid)
 }
 
 pub mod utils {
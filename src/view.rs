@@ -0,0 +1,159 @@
+use super::{
+    basic_dag::{BasicDAG, BasicDAGHeader},
+    conversion::export,
+    hash_table::{basic::HashTable, config::HashTableConfig},
+    validation::{validate, Validation, Validator},
+    Error, HashDAG, Result,
+};
+
+/// A read-only `HashDAG` over borrowed slices, e.g. the pool and page table which `Staging::stage` uploaded.
+/// The page table is the one returned by `HashDAG::dump`, so without the allocation watermark.
+#[derive(Debug, Copy, Clone)]
+pub struct HashDAGView<'a> {
+    pub config: HashTableConfig,
+    pub pool: &'a [u32],
+    pub lut: &'a [u32],
+    pub full_node_pointers: &'a [u32],
+}
+
+impl<'a> HashDAGView<'a> {
+    /// Checks that the page table covers `config` and that there is a full node pointer for every level.
+    pub fn new(
+        config: HashTableConfig,
+        (pool, lut): (&'a [u32], &'a [u32]),
+        full_node_pointers: &'a [u32],
+    ) -> Result<Self> {
+        if lut.len() != config.total_pages() as usize {
+            Err(Error::Format(format!(
+                "The page table has {} pages, expected {}.",
+                lut.len(),
+                config.total_pages()
+            )))
+        } else if full_node_pointers.len() <= config.leaf_level() as usize {
+            Err(Error::Format(format!(
+                "Expected {} full node pointers, got {}.",
+                config.leaf_level() + 1,
+                full_node_pointers.len()
+            )))
+        } else {
+            Ok(Self {
+                config,
+                pool,
+                lut,
+                full_node_pointers,
+            })
+        }
+    }
+    #[inline]
+    pub fn full_node_ptr(&self, level: u32) -> Result<u32> {
+        match self.full_node_pointers.get(level as usize) {
+            Some(&vptr) if level <= self.config.leaf_level() => Ok(vptr),
+            _ => Err(Error::InvalidLevel(level)),
+        }
+    }
+    /// Like `HashTable::pool_idx`, bounded by the borrowed pool instead of the allocated pages.
+    pub fn pool_idx(&self, vptr: u32) -> Result<usize> {
+        let page_len = self.config.page_len;
+        let page = (vptr / page_len) as usize;
+        match self.lut.get(page) {
+            Some(&pool_idx) if pool_idx == !0 => Err(Error::UnallocatedVptr(vptr)),
+            Some(&pool_idx) if ((pool_idx + page_len) as usize) <= self.pool.len() => {
+                Ok((pool_idx + vptr % page_len) as _)
+            }
+            Some(_) => Err(Error::OutOfBoundsVptr(vptr)),
+            None => Err(Error::InvalidPage(page)),
+        }
+    }
+    /// Exports like `Converter::export`.
+    #[inline]
+    pub fn export(&self, vptr: u32) -> Result<BasicDAG> {
+        export(self, vptr, None)
+    }
+    /// Exports like `Converter::export_with_header`.
+    #[inline]
+    pub fn export_with_header(&self, vptr: u32, header: BasicDAGHeader) -> Result<BasicDAG> {
+        export(self, vptr, Some(header))
+    }
+    /// Walks the DAG spanned by `vptr` in both `self` and `other`, returning the first node whose words differ.
+    /// Both are expected to share the same virtual pointers, like a staged copy and its host.
+    /// Nodes which `self` cannot read, e.g. on pages that were never staged, count as differing.
+    pub fn first_mismatch<DAG: HashDAG>(&self, other: &DAG, vptr: u32) -> Result<Option<u32>> {
+        let leaf_level = self.config.leaf_level();
+        let mut visited = vec![false; self.pool.len()];
+        let mut items = vec![(self.config.vptr_to_lvl(vptr), vptr)];
+        while let Some((level, vptr)) = items.pop() {
+            let (node, expected) = if level == leaf_level {
+                (self.leaf(vptr), other.leaf(vptr)?)
+            } else {
+                (self.interior(vptr), other.interior(vptr)?)
+            };
+            match node {
+                Ok(node) if node == expected => {
+                    let pool_idx = self.pool_idx(vptr)?;
+                    if !visited[pool_idx] && level < leaf_level {
+                        items.extend(node.iter().skip(1).map(|&child| (level + 1, child)));
+                    }
+                    visited[pool_idx] = true;
+                }
+                _ => return Ok(Some(vptr)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl HashDAG for HashDAGView<'_> {
+    #[inline]
+    fn get(&self, vptr: u32) -> Result<u32> {
+        Ok(self.pool[self.pool_idx(vptr)?])
+    }
+    /// Staged data may be corrupt, so nodes running past the pool fail instead of panicking.
+    #[inline]
+    fn leaf(&self, vptr: u32) -> Result<&[u32]> {
+        let pool_idx = self.pool_idx(vptr)?;
+        self.pool
+            .get(pool_idx..=pool_idx + 1)
+            .ok_or(Error::OutOfBoundsVptr(vptr))
+    }
+    #[inline]
+    fn interior(&self, vptr: u32) -> Result<&[u32]> {
+        let pool_idx = self.pool_idx(vptr)?;
+        let children = (self.pool[pool_idx] as u8).count_ones() as usize;
+        self.pool
+            .get(pool_idx..=pool_idx + children)
+            .ok_or(Error::OutOfBoundsVptr(vptr))
+    }
+    #[inline]
+    fn dump(&self) -> (&[u32], &[u32]) {
+        (self.pool, self.lut)
+    }
+    #[inline]
+    fn config(&self) -> &HashTableConfig {
+        &self.config
+    }
+    #[inline]
+    fn full_node_pointers(&self) -> &[u32] {
+        self.full_node_pointers
+    }
+}
+
+impl Validator for HashDAGView<'_> {
+    #[inline]
+    fn validate(&self, vptr: u32) -> Result<Validation> {
+        validate(self, vptr, |vptr| self.pool_idx(vptr))
+    }
+}
+
+impl HashTable<'_> {
+    /// Views the table as it would be staged.
+    #[inline]
+    #[must_use]
+    pub fn view(&self) -> HashDAGView<'_> {
+        HashDAGView {
+            config: self.config,
+            pool: &self.pool,
+            lut: &self.lut,
+            full_node_pointers: &self.full_node_pointers,
+        }
+    }
+}